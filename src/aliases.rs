@@ -0,0 +1,187 @@
+//! Aliases: shorthands for things to send, expanded in lines of input before they go to the
+//! server.
+
+use regex::Regex;
+use serde::Deserialize;
+
+/// How many times one line can be expanded (by different aliases) before we give up.
+const MAX_DEPTH: usize = 10;
+
+/// An alias from an `[[aliases]]` or `[[worlds.NAME.aliases]]` table.  Either `name` or `pattern`
+/// should be given: a `name` alias matches lines whose first word is `name`, and a `pattern`
+/// alias matches lines matching a regular expression.
+///
+/// In `expand`, `%1`, `%2`... are the words after the name (or the pattern's capture groups),
+/// `%*` is everything after the name (or the whole line), and `%%` is a `%`.  If a named alias's
+/// `expand` doesn't use any of those, the rest of the line is added on the end.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AliasSpec {
+    pub name: Option<String>,
+    pub pattern: Option<String>,
+    pub expand: String,
+}
+
+enum Matcher {
+    Word(String),
+    Pattern(Regex),
+}
+
+pub struct Alias {
+    matcher: Matcher,
+    expand: String,
+}
+
+impl Alias {
+    pub fn compile(spec: &AliasSpec) -> Result<Alias, String> {
+        let matcher = match (&spec.name, &spec.pattern) {
+            (Some(name), None) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                Matcher::Word(name.clone())
+            },
+            (Some(name), None) => return Err(format!("bad alias name '{}'", name)),
+            (None, Some(pattern)) => Matcher::Pattern(Regex::new(pattern)
+                .map_err(|e| format!("bad alias pattern '{}': {}", pattern, e))?),
+            _ => return Err("an alias needs one of name or pattern".to_string()),
+        };
+        Ok(Alias { matcher, expand: spec.expand.clone() })
+    }
+
+    /// What this alias is for, to show the user.
+    pub fn describe(&self) -> String {
+        match self.matcher {
+            Matcher::Word(ref name) => format!("{} = {}", name, self.expand),
+            Matcher::Pattern(ref pattern) => format!("/{}/ = {}", pattern, self.expand),
+        }
+    }
+
+    /// Expand `line`, if this alias matches it.
+    fn apply(&self, line: &str) -> Option<String> {
+        match self.matcher {
+            Matcher::Word(ref name) => {
+                let line = line.trim_start();
+                let (word, rest) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+                if word != name {
+                    return None;
+                }
+                let rest = rest.trim();
+                let words: Vec<&str> = rest.split_whitespace().collect();
+
+                let (expanded, used) = expand_args(&self.expand, |n| words.get(n.wrapping_sub(1)).copied(), rest);
+                if used || rest.is_empty() {
+                    Some(expanded)
+                } else {
+                    Some(format!("{} {}", expanded, rest))
+                }
+            },
+            Matcher::Pattern(ref pattern) => {
+                let caps = pattern.captures(line)?;
+                let get = |n: usize| if n == 0 { None } else { caps.get(n).map(|m| m.as_str()) };
+                Some(expand_args(&self.expand, get, line).0)
+            },
+        }
+    }
+}
+
+/// Replace `%1`... in `template` with `arg(1)`..., and `%*` with `all`.  Missing arguments become
+/// empty.  Also returns whether any argument was used.
+fn expand_args<'a>(template: &str, arg: impl Fn(usize) -> Option<&'a str>, all: &str) -> (String, bool) {
+    let mut out = String::with_capacity(template.len());
+    let mut used = false;
+    let mut rest = template;
+
+    while let Some(i) = rest.find('%') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+
+        if let Some(after) = rest.strip_prefix('*') {
+            out.push_str(all);
+            used = true;
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('%') {
+            out.push('%');
+            rest = after;
+        } else {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            if digits == 0 {
+                out.push('%');
+                continue;
+            }
+            let n: usize = rest[..digits].parse().unwrap_or(usize::MAX);
+            out.push_str(arg(n).unwrap_or(""));
+            used = true;
+            rest = &rest[digits..];
+        }
+    }
+
+    out.push_str(rest);
+    (out, used)
+}
+
+/// A set of aliases, tried in order.
+#[derive(Default)]
+pub struct Aliases {
+    aliases: Vec<Alias>,
+}
+
+impl Aliases {
+    pub fn new() -> Aliases {
+        Aliases::default()
+    }
+
+    pub fn compile<'a>(specs: impl IntoIterator<Item = &'a AliasSpec>) -> Result<Aliases, String> {
+        let aliases = specs.into_iter().map(Alias::compile).collect::<Result<_, _>>()?;
+        Ok(Aliases { aliases })
+    }
+
+    pub fn aliases(&self) -> &[Alias] {
+        &self.aliases
+    }
+
+    /// Expand any aliases in `line`.  The first alias that matches is used, then the result is
+    /// checked again, so aliases can be built out of other aliases; an alias is never applied to
+    /// its own expansion, though, so `kill` can be an alias for `kill %* carefully`.
+    pub fn expand(&self, line: &str) -> Result<String, String> {
+        let mut line = line.to_string();
+        let mut used = vec![false; self.aliases.len()];
+
+        for _ in 0..MAX_DEPTH {
+            let next = self.aliases.iter().enumerate()
+                .filter(|(i, _)| !used[*i])
+                .find_map(|(i, alias)| alias.apply(&line).map(|expanded| (i, expanded)));
+            match next {
+                Some((i, expanded)) => {
+                    used[i] = true;
+                    line = expanded;
+                },
+                None => return Ok(line),
+            }
+        }
+
+        Err(format!("Too many aliases inside each other expanding '{}'", line))
+    }
+}
+
+#[test]
+fn expands_aliases() {
+    let named = |name: &str, expand: &str| AliasSpec { name: Some(name.to_string()), expand: expand.to_string(), ..AliasSpec::default() };
+    let pattern = |pattern: &str, expand: &str| AliasSpec { pattern: Some(pattern.to_string()), expand: expand.to_string(), ..AliasSpec::default() };
+
+    let aliases = Aliases::compile(&[
+        named("k", "kill"),
+        named("gt", "tell %1 [%2] %*"),
+        pattern("^heal (\\w+)$", "cast 'cure' %1"),
+        named("kill", "kill %* carefully"),
+        named("h", "heal me"),
+    ]).unwrap();
+    assert!(Aliases::compile(&[AliasSpec { expand: "x".to_string(), ..AliasSpec::default() }]).is_err());
+
+    assert_eq!(aliases.expand("k orc").unwrap(), "kill orc carefully");
+    assert_eq!(aliases.expand("gt bob hi there").unwrap(), "tell bob [hi] bob hi there");
+    assert_eq!(aliases.expand("h").unwrap(), "cast 'cure' me");
+    assert_eq!(aliases.expand("kit orc").unwrap(), "kit orc");
+    assert_eq!(aliases.expand("50%% off").unwrap(), "50%% off");
+
+    // Every alias here leads to another, so it only stops at the limit.
+    let looping: Vec<AliasSpec> = (0..MAX_DEPTH + 1).map(|i| named(&format!("a{}", i), &format!("a{}", i + 1))).collect();
+    assert!(Aliases::compile(&looping).unwrap().expand("a0").is_err());
+}
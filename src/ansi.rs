@@ -0,0 +1,444 @@
+//! Parsing the ANSI escape sequences servers colour their text with into runs of styled text, and
+//! turning those back into escape sequences, plain text or HTML.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+/// A colour from an SGR sequence.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Colour {
+    /// One of the 256 xterm palette colours; 0-15 are the classic 16.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Colour {
+    /// Read a colour as written in the config: one of the eight basic colour names, optionally
+    /// prefixed with `bright-`, a palette number from 0 to 255, or `#rrggbb`.
+    pub fn from_name(name: &str) -> Option<Colour> {
+        const NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+        let name = name.trim().to_lowercase();
+        if let Some(hex) = name.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return None;
+            }
+            let part = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            return Some(Colour::Rgb(part(0)?, part(2)?, part(4)?));
+        }
+        if let Ok(i) = name.parse() {
+            return Some(Colour::Indexed(i));
+        }
+
+        let (name, offset) = match name.strip_prefix("bright-") {
+            Some(name) => (name, 8),
+            None => (name.as_str(), 0),
+        };
+        NAMES.iter().position(|n| *n == name).map(|i| Colour::Indexed(i as u8 + offset))
+    }
+
+    /// Pick the RGB value we show this colour as when we can't leave it up to a terminal.
+    pub fn to_rgb(self) -> (u8, u8, u8) {
+        const BASIC: [(u8, u8, u8); 16] = [
+            (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+            (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+            (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+            (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+        ];
+
+        match self {
+            Colour::Rgb(r, g, b) => (r, g, b),
+            Colour::Indexed(i) if i < 16 => BASIC[i as usize],
+            Colour::Indexed(i) if i < 232 => {
+                // The 6x6x6 colour cube.
+                let i = i - 16;
+                let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+                (level(i / 36), level((i / 6) % 6), level(i % 6))
+            },
+            Colour::Indexed(i) => {
+                // The greyscale ramp.
+                let level = 8 + (i - 232) * 10;
+                (level, level, level)
+            },
+        }
+    }
+
+    fn sgr(self, background: bool) -> String {
+        let base = if background { 40 } else { 30 };
+        match self {
+            Colour::Indexed(i) if i < 8 => format!("{}", base + i as u16),
+            Colour::Indexed(i) if i < 16 => format!("{}", base + 60 + (i - 8) as u16),
+            Colour::Indexed(i) => format!("{};5;{}", base + 8, i),
+            Colour::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+        }
+    }
+}
+
+/// How a run of text looks.  The default is however the terminal draws text normally.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Style {
+    pub fg: Option<Colour>,
+    pub bg: Option<Colour>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    /// Update the style according to the parameters of an SGR (`ESC [ ... m`) sequence.
+    pub fn apply_sgr(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = Style::default();
+            return;
+        }
+
+        let mut params = params.iter().cloned();
+        while let Some(p) = params.next() {
+            match p {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.reverse = true,
+                22 => self.bold = false,
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.reverse = false,
+                30..=37 => self.fg = Some(Colour::Indexed((p - 30) as u8)),
+                39 => self.fg = None,
+                40..=47 => self.bg = Some(Colour::Indexed((p - 40) as u8)),
+                49 => self.bg = None,
+                90..=97 => self.fg = Some(Colour::Indexed((p - 90 + 8) as u8)),
+                100..=107 => self.bg = Some(Colour::Indexed((p - 100 + 8) as u8)),
+                38 | 48 => {
+                    let colour = match params.next() {
+                        Some(5) => params.next().map(|i| Colour::Indexed(i as u8)),
+                        Some(2) => {
+                            let (r, g, b) = (params.next(), params.next(), params.next());
+                            match (r, g, b) {
+                                (Some(r), Some(g), Some(b)) => Some(Colour::Rgb(r as u8, g as u8, b as u8)),
+                                _ => None,
+                            }
+                        },
+                        _ => None,
+                    };
+                    if p == 38 {
+                        self.fg = colour;
+                    } else {
+                        self.bg = colour;
+                    }
+                },
+                // Blinking and the rest: we don't do those.
+                _ => { },
+            }
+        }
+    }
+
+    /// An SGR sequence that switches from the default style to this one.
+    pub fn to_sgr(&self) -> String {
+        let mut params = vec!["0".to_string()];
+        if self.bold { params.push("1".to_string()); }
+        if self.italic { params.push("3".to_string()); }
+        if self.underline { params.push("4".to_string()); }
+        if self.reverse { params.push("7".to_string()); }
+        if let Some(fg) = self.fg { params.push(fg.sgr(false)); }
+        if let Some(bg) = self.bg { params.push(bg.sgr(true)); }
+        format!("\x1b[{}m", params.join(";"))
+    }
+
+    /// CSS declarations for this style.
+    pub fn to_css(&self) -> String {
+        let (mut fg, mut bg) = (self.fg, self.bg);
+        if self.reverse {
+            // Without knowing what the page's default colours are, this is the best we can do.
+            fg = Some(bg.unwrap_or(Colour::Indexed(0)));
+            bg = Some(self.fg.unwrap_or(Colour::Indexed(7)));
+        }
+
+        let mut css = String::new();
+        if let Some(fg) = fg {
+            let (r, g, b) = fg.to_rgb();
+            let _ = write!(css, "color:#{:02x}{:02x}{:02x};", r, g, b);
+        }
+        if let Some(bg) = bg {
+            let (r, g, b) = bg.to_rgb();
+            let _ = write!(css, "background-color:#{:02x}{:02x}{:02x};", r, g, b);
+        }
+        if self.bold { css.push_str("font-weight:bold;"); }
+        if self.italic { css.push_str("font-style:italic;"); }
+        if self.underline { css.push_str("text-decoration:underline;"); }
+        css
+    }
+}
+
+/// A run of text in a single style.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+/// A line of text, split into runs of different styles.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct StyledLine {
+    pub spans: Vec<Span>,
+}
+
+impl StyledLine {
+    /// A line of text all in the default style.
+    pub fn unstyled(text: &str) -> StyledLine {
+        let mut line = StyledLine::default();
+        line.push(text, Style::default());
+        line
+    }
+
+    /// Parse a line containing ANSI escape sequences.  Styles carry over from one line to the
+    /// next, so this takes the style in effect at the start of the line and returns the one in
+    /// effect at the end of it along with the line.  Escape sequences other than SGR, and control
+    /// characters other than tabs, are dropped.
+    pub fn parse(raw: &str, mut style: Style) -> (StyledLine, Style) {
+        let mut line = StyledLine::default();
+        let mut text = String::new();
+        let mut chars = raw.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                if chars.peek() != Some(&'[') {
+                    // Some other kind of escape; throw its one character away too.
+                    chars.next();
+                    continue;
+                }
+                chars.next();
+
+                // A CSI sequence: parameter bytes, then a final byte in '@'..='~'.
+                let mut params = String::new();
+                let mut last = None;
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        last = Some(c);
+                        break;
+                    }
+                    params.push(c);
+                }
+
+                if last == Some('m') {
+                    line.push(&text, style);
+                    text.clear();
+                    let params: Vec<u16> = if params.is_empty() {
+                        vec![]
+                    } else {
+                        params.split(';').map(|p| p.parse().unwrap_or(0)).collect()
+                    };
+                    style.apply_sgr(&params);
+                }
+            } else if !c.is_control() || c == '\t' {
+                text.push(c);
+            }
+        }
+
+        line.push(&text, style);
+        (line, style)
+    }
+
+    /// Add some text onto the end of the line.
+    pub fn push(&mut self, text: &str, style: Style) {
+        if text.is_empty() {
+            return;
+        }
+        match self.spans.last_mut() {
+            Some(last) if last.style == style => last.text.push_str(text),
+            _ => self.spans.push(Span { text: text.to_string(), style }),
+        }
+    }
+
+    /// Add another line's text onto the end of this one.
+    pub fn append(&mut self, other: &StyledLine) {
+        for span in &other.spans {
+            self.push(&span.text, span.style);
+        }
+    }
+
+    /// The part of the line at `range` (byte offsets into plain().)
+    pub fn slice(&self, range: Range<usize>) -> StyledLine {
+        let mut out = StyledLine::default();
+        let mut pos = 0;
+        for span in &self.spans {
+            let end = pos + span.text.len();
+            let (from, to) = (range.start.clamp(pos, end), range.end.clamp(pos, end));
+            out.push(&span.text[from - pos..to - pos], span.style);
+            pos = end;
+        }
+        out
+    }
+
+    /// Change the style of the text at `range` (byte offsets into plain()), by passing each
+    /// part's existing style through `over`.
+    pub fn restyle(&mut self, range: Range<usize>, over: impl Fn(Style) -> Style) {
+        let mut out = StyledLine::default();
+        let mut pos = 0;
+        for span in &self.spans {
+            let end = pos + span.text.len();
+            // Split the span into the parts before, inside and after the range.
+            let cuts = [pos, range.start.clamp(pos, end), range.end.clamp(pos, end), end];
+            for (i, part) in cuts.windows(2).enumerate() {
+                let text = &span.text[part[0] - pos..part[1] - pos];
+                out.push(text, if i == 1 { over(span.style) } else { span.style });
+            }
+            pos = end;
+        }
+        *self = out;
+    }
+
+    /// The style of the text at byte offset `at` in plain(), or of the last character if `at` is
+    /// past the end.
+    pub fn style_at(&self, at: usize) -> Style {
+        let mut pos = 0;
+        for span in &self.spans {
+            pos += span.text.len();
+            if at < pos {
+                return span.style;
+            }
+        }
+        self.spans.last().map_or(Style::default(), |span| span.style)
+    }
+
+    /// Replace the text at `range` (byte offsets into plain()) with `with`, which takes on the
+    /// style of the first character it replaces.  Everything else keeps its style.
+    pub fn replace(&mut self, range: Range<usize>, with: &str) {
+        let style = self.style_at(range.start);
+        let mut out = StyledLine::default();
+
+        // The part before the range...
+        let mut pos = 0;
+        for span in &self.spans {
+            let end = pos + span.text.len();
+            if pos < range.start {
+                out.push(&span.text[..end.min(range.start) - pos], span.style);
+            }
+            pos = end;
+        }
+
+        out.push(with, style);
+
+        // ...and the part after it.
+        let mut pos = 0;
+        for span in &self.spans {
+            let end = pos + span.text.len();
+            if end > range.end {
+                out.push(&span.text[range.end.max(pos) - pos..], span.style);
+            }
+            pos = end;
+        }
+
+        *self = out;
+    }
+
+    /// The text without any styling.
+    pub fn plain(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    /// The line with ANSI escape sequences, ending in the default style.
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        let mut current = Style::default();
+        for span in &self.spans {
+            if span.style != current {
+                out.push_str(&span.style.to_sgr());
+                current = span.style;
+            }
+            out.push_str(&span.text);
+        }
+        if current != Style::default() {
+            out.push_str("\x1b[0m");
+        }
+        out
+    }
+
+    /// The line as HTML, with styled runs in `<span>`s.
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        for span in &self.spans {
+            let css = span.style.to_css();
+            if css.is_empty() {
+                out.push_str(&escape_html(&span.text));
+            } else {
+                let _ = write!(out, "<span style=\"{}\">{}</span>", css, escape_html(&span.text));
+            }
+        }
+        out
+    }
+}
+
+/// Escape text for putting in HTML.
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[test]
+fn parses_sgr() {
+    let (line, style) = StyledLine::parse("a \x1b[1;31mred\x1b[0m <b>\x1b[38;5;208mor", Style::default());
+
+    assert_eq!(line.plain(), "a red <b>or");
+    assert_eq!(line.spans.len(), 4);
+    assert_eq!(line.spans[1].style, Style { fg: Some(Colour::Indexed(1)), bold: true, ..Style::default() });
+    assert_eq!(style.fg, Some(Colour::Indexed(208)));
+    assert_eq!(line.to_ansi(), "a \x1b[0;1;31mred\x1b[0m <b>\x1b[0;38;5;208mor\x1b[0m");
+    assert_eq!(line.to_html(), "a <span style=\"color:#cd0000;font-weight:bold;\">red</span> &lt;b&gt;\
+                                <span style=\"color:#ff8700;\">or</span>");
+
+    let mut replaced = line.clone();
+    replaced.replace(3..5, "ED");
+    assert_eq!(replaced.plain(), "a rED <b>or");
+    assert_eq!(replaced.spans[1].text, "rED");
+    assert_eq!(replaced.spans[1].style.fg, Some(Colour::Indexed(1)));
+
+    // The style carries on into the next line.
+    let (next, _) = StyledLine::parse("\x1b[Kstill", style);
+    assert_eq!(next.spans[0].style.fg, Some(Colour::Indexed(208)));
+    assert_eq!(next.plain(), "still");
+
+    // 256-colour and truecolour, in the foreground and background.
+    let (line, style) = StyledLine::parse("\x1b[38;5;123;48;2;1;2;3mx\x1b[38;2;255;128;0;48;5;17my", Style::default());
+    assert_eq!(line.spans[0].style, Style { fg: Some(Colour::Indexed(123)), bg: Some(Colour::Rgb(1, 2, 3)), ..Style::default() });
+    assert_eq!(style, Style { fg: Some(Colour::Rgb(255, 128, 0)), bg: Some(Colour::Indexed(17)), ..Style::default() });
+    assert_eq!(line.to_ansi(), "\x1b[0;38;5;123;48;2;1;2;3mx\x1b[0;38;2;255;128;0;48;5;17my\x1b[0m");
+
+    // Resets part way through a line, with and without a 0.
+    let (line, style) = StyledLine::parse("\x1b[1;32mgreen\x1b[mplain\x1b[4munder\x1b[0;35mpink", Style::default());
+    assert_eq!(line.spans.iter().map(|span| span.text.as_str()).collect::<Vec<_>>(), vec!["green", "plain", "under", "pink"]);
+    assert_eq!(line.spans[1].style, Style::default());
+    assert_eq!(line.spans[2].style, Style { underline: true, ..Style::default() });
+    assert_eq!(style, Style { fg: Some(Colour::Indexed(5)), ..Style::default() });
+
+    // A line that only changes the style hands it on to the next, which can turn parts of it off.
+    let (line, style) = StyledLine::parse("\x1b[1;44m", Style::default());
+    assert!(line.spans.is_empty());
+    let (line, style) = StyledLine::parse("bold\x1b[22mblue", style);
+    assert_eq!(line.spans[0].style, Style { bold: true, bg: Some(Colour::Indexed(4)), ..Style::default() });
+    assert_eq!(line.spans[1].style, Style { bg: Some(Colour::Indexed(4)), ..Style::default() });
+    assert_eq!(style, line.spans[1].style);
+
+    // Malformed and truncated escapes don't leave anything behind in the text.
+    let (line, style) = StyledLine::parse("\x1b[31mred\x1b[38;5mnone\x1b[38;2;1;2mnone", Style::default());
+    assert_eq!(line.plain(), "rednonenone");
+    assert_eq!(line.spans[1].style.fg, None);
+    assert_eq!(style.fg, None);
+    let (line, style) = StyledLine::parse("a\x1bXb\x1b[1;?mc\x1b[31", Style::default());
+    assert_eq!(line.plain(), "abc");
+    assert_eq!(line.spans.len(), 1);
+    assert_eq!(style, Style::default());
+    let (line, _) = StyledLine::parse("end\x1b", Style::default());
+    assert_eq!(line.plain(), "end");
+}
@@ -0,0 +1,212 @@
+use mint::net::replay::Speed;
+
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: mint [OPTIONS] [WORLD | HOST:PORT]
+
+Options:
+  --host HOST         Connect to HOST (requires --port)
+  --port PORT         Port to connect to
+  --tls               Connect using TLS
+  --log FILE          Append everything received and sent to FILE (as HTML
+                      if it ends in .html, with colours if it ends in .ansi)
+  --record FILE       Record everything the server sends to FILE
+  --replay FILE       Play back a recording instead of connecting anywhere
+  --speed N           Play recordings back N times as fast as real time
+  --step              Play recordings back one chunk each time Enter is
+                      pressed (or N chunks, if N is typed first)
+  --config PATH       Read worlds from PATH instead of
+                      $XDG_CONFIG_HOME/mint/config.toml
+  --no-tui            Run without the terminal UI, reading lines from stdin and
+                      printing to stdout
+  --events KIND       Event manager to use: 'threaded' (default) or 'poll'
+  -h, --help          Show this message
+  -V, --version       Show the version number";
+
+/// Which EventManager implementation to run.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EventsKind {
+    Threaded,
+    Poll,
+}
+
+/// Options parsed from the command line.
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    /// The positional argument, if any.
+    pub world: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub tls: bool,
+    pub log: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub speed: Speed,
+    pub tui: bool,
+    pub events: EventsKind,
+}
+
+/// What the command line asked us to do.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Run(Options),
+    Help,
+    Version,
+}
+
+impl Options {
+    /// Work out what we've been asked to connect to, if anything: either a world name or an
+    /// address the TcpConnectionManager understands, with `tls://` in front if --tls was given.
+    pub fn target(&self) -> Result<Option<String>, String> {
+        if self.replay.is_some() {
+            if self.world.is_some() || self.host.is_some() || self.port.is_some() || self.tls {
+                return Err("--replay doesn't connect anywhere, so it can't be given a world".to_string());
+            }
+            if self.record.is_some() {
+                return Err("--record and --replay can't be used together".to_string());
+            }
+            return Ok(None);
+        }
+
+        let target = match (&self.world, &self.host, self.port) {
+            (Some(_), Some(_), _) | (Some(_), _, Some(_)) => {
+                return Err("Give either a world or --host/--port, not both".to_string());
+            },
+            (None, Some(host), Some(port)) if host.contains(':') && !host.starts_with('[') => {
+                // A bare IPv6 address; it needs brackets to be told apart from the port.
+                format!("[{}]:{}", host, port)
+            },
+            (None, Some(host), Some(port)) => format!("{}:{}", host, port),
+            (None, Some(_), None) => return Err("--host needs --port as well".to_string()),
+            (None, None, Some(_)) => return Err("--port needs --host as well".to_string()),
+            (None, None, None) => return Ok(None),
+            (Some(world), None, None) => {
+                if let Some(i) = world.rfind(':') {
+                    parse_port(&world[i + 1..])?;
+                }
+                world.clone()
+            },
+        };
+
+        if self.tls {
+            Ok(Some(format!("tls://{}", target)))
+        } else {
+            Ok(Some(target))
+        }
+    }
+}
+
+fn parse_port(port: &str) -> Result<u16, String> {
+    port.parse().map_err(|_| format!("'{}' isn't a valid port number", port))
+}
+
+/// Parse the command line (not including the program name.)
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Action, String> {
+    let mut opts = Options {
+        world: None,
+        host: None,
+        port: None,
+        tls: false,
+        log: None,
+        config: None,
+        record: None,
+        replay: None,
+        speed: Speed::Scaled(1.0),
+        tui: true,
+        events: EventsKind::Threaded,
+    };
+
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next() {
+        // Allow --option=value as well as --option value.
+        let (name, mut inline_value) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+            _ => (arg.clone(), None),
+        };
+        // An empty value is as good as none, and an option can't be taken for one.
+        let mut value = |name: &str| -> Result<String, String> {
+            inline_value.take().or_else(|| args.next_if(|next| !next.starts_with('-') || next == "-"))
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Action::Help),
+            "-V" | "--version" => return Ok(Action::Version),
+            "--host" => opts.host = Some(value("--host")?),
+            "--port" => opts.port = Some(parse_port(&value("--port")?)?),
+            "--tls" => opts.tls = true,
+            "--log" => opts.log = Some(PathBuf::from(value("--log")?)),
+            "--config" => opts.config = Some(PathBuf::from(value("--config")?)),
+            "--record" => opts.record = Some(PathBuf::from(value("--record")?)),
+            "--replay" => opts.replay = Some(PathBuf::from(value("--replay")?)),
+            "--speed" => {
+                let speed = value("--speed")?;
+                match speed.parse::<f64>() {
+                    Ok(n) if n > 0.0 && n.is_finite() => opts.speed = Speed::Scaled(n),
+                    _ => return Err(format!("'{}' isn't a valid speed", speed)),
+                }
+            },
+            "--step" => opts.speed = Speed::Stepped,
+            "--no-tui" => opts.tui = false,
+            "--events" => {
+                opts.events = match value("--events")?.as_str() {
+                    "threaded" => EventsKind::Threaded,
+                    "poll" => EventsKind::Poll,
+                    other => return Err(format!("Unknown event manager '{}'", other)),
+                };
+            },
+            _ if name.starts_with('-') && name.len() > 1 => {
+                return Err(format!("Unknown option '{}'", name));
+            },
+            _ => {
+                if opts.world.is_some() {
+                    return Err(format!("Unexpected argument '{}'", arg));
+                }
+                opts.world = Some(arg.clone());
+            },
+        }
+
+        if inline_value.is_some() {
+            return Err(format!("{} doesn't take a value", name));
+        }
+    }
+
+    Ok(Action::Run(opts))
+}
+
+#[test]
+fn parses_arguments() {
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+    match parse(args(&["--tls", "--log=out.txt", "example.org:4000"])) {
+        Ok(Action::Run(opts)) => {
+            assert_eq!(opts.target(), Ok(Some("tls://example.org:4000".to_string())));
+            assert_eq!(opts.log, Some(PathBuf::from("out.txt")));
+        },
+        other => panic!("unexpected {:?}", other),
+    }
+    match parse(args(&["--host", "::1", "--port", "23", "--no-tui"])) {
+        Ok(Action::Run(opts)) => {
+            assert_eq!(opts.target(), Ok(Some("[::1]:23".to_string())));
+            assert!(!opts.tui);
+        },
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert_eq!(parse(args(&["-V", "--bogus"])), Ok(Action::Version));
+    assert!(parse(args(&["--bogus"])).is_err());
+    assert!(parse(args(&["--port"])).is_err());
+    for missing in [&["--log"][..], &["--log="], &["--log", ""], &["--log", "--no-tui"]] {
+        assert_eq!(parse(args(missing)), Err("--log needs a value".to_string()));
+    }
+    assert!(parse(args(&["--tls=yes"])).is_err());
+    assert!(parse(args(&["a:1", "b:2"])).is_err());
+
+    match parse(args(&["mume"])) {
+        Ok(Action::Run(opts)) => assert_eq!(opts.target(), Ok(Some("mume".to_string()))),
+        other => panic!("unexpected {:?}", other),
+    }
+}
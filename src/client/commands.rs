@@ -0,0 +1,107 @@
+//! Client commands: lines of input starting with `/`, which are handled here instead of being
+//! sent to the server.  A line starting with `//` is sent with the first `/` taken off.
+
+use super::Client;
+
+use std::path::Path;
+
+/// Split a line of input into a command name and its arguments, if it's a command.
+pub(super) fn parse(line: &str) -> Option<(&str, &str)> {
+    if !line.starts_with('/') || line.starts_with("//") {
+        return None;
+    }
+
+    let line = line[1..].trim();
+    match line.find(char::is_whitespace) {
+        Some(i) => Some((&line[..i], line[i..].trim_start())),
+        None => Some((line, "")),
+    }
+}
+
+/// Turn a line that isn't a command into what should actually be sent.
+pub(super) fn unescape(line: String) -> String {
+    match line.strip_prefix("//") {
+        Some(rest) => format!("/{}", rest),
+        None => line,
+    }
+}
+
+impl Client {
+    /// Run a client command.  Returns false if it's time to quit.
+    pub(super) fn run_command(&mut self, name: &str, args: &str) -> bool {
+        match name {
+            "alias" => self.cmd_alias(args),
+            "connect" => self.cmd_connect(args),
+            "goto" => self.cmd_goto(args),
+            "log" => self.cmd_log(args),
+            "map" => self.cmd_map(args),
+            "record" => self.cmd_record(args),
+            "reload" => self.cmd_reload(),
+            "set" => self.cmd_set(args),
+            "trigger" => self.cmd_trigger(args),
+            "unset" => self.cmd_unset(args),
+            "worlds" => self.cmd_worlds(),
+            "quit" => return false,
+            _ => self.output(format!("Unknown command '/{}'", name)),
+        }
+        true
+    }
+
+    fn cmd_connect(&mut self, args: &str) {
+        if args.is_empty() {
+            return self.output("Usage: /connect <world | host:port>".to_string());
+        }
+        if let Err(why) = self.connect(args) {
+            self.output(format!("Couldn't connect to {}: {}", args, why));
+        }
+    }
+
+    /// `/record start FILE` or `/record stop`, for the active connection.
+    fn cmd_record(&mut self, args: &str) {
+        let cid = match self.active {
+            Some(cid) => cid,
+            None => return self.output("Not connected".to_string()),
+        };
+
+        let mut args = args.splitn(2, char::is_whitespace);
+        match (args.next(), args.next().map(str::trim)) {
+            (Some("start"), Some(path)) if !path.is_empty() => {
+                let result = self.conns.borrow_mut().start_recording(cid, Path::new(path));
+                match result {
+                    Ok(()) => self.output(format!("Recording to {}", path)),
+                    Err(why) => self.output(why),
+                }
+            },
+            (Some("stop"), None) => {
+                if self.conns.borrow_mut().stop_recording(cid) {
+                    self.output("Stopped recording".to_string());
+                } else {
+                    self.output("Not recording".to_string());
+                }
+            },
+            _ => self.output("Usage: /record start FILE | /record stop".to_string()),
+        }
+    }
+
+    fn cmd_worlds(&mut self) {
+        if self.config.worlds.is_empty() {
+            return self.output("No worlds are configured".to_string());
+        }
+
+        let lines: Vec<String> = self.config.worlds.values()
+            .map(|world| format!("{}: {}", world.name, world.address()))
+            .collect();
+        for line in lines {
+            self.output(line);
+        }
+    }
+}
+
+#[test]
+fn parses_commands() {
+    assert_eq!(parse("/connect  mume "), Some(("connect", "mume")));
+    assert_eq!(parse("/quit"), Some(("quit", "")));
+    assert_eq!(parse("say /quit"), None);
+    assert_eq!(parse("//quit"), None);
+    assert_eq!(unescape("//quit".to_string()), "/quit");
+}
@@ -0,0 +1,71 @@
+//! Keeping the gauges up to date with what the server sends.
+
+use crate::gauges::{GaugeSpec, Gauges};
+use crate::net::ConnectionID;
+
+use super::Client;
+
+impl Client {
+    /// Set up the global gauges and the active world's, and show them.
+    pub(super) fn load_gauges(&mut self) {
+        let world = self.world.as_ref().and_then(|name| self.config.worlds.get(name));
+        let specs: Vec<GaugeSpec> = self.config.gauges.iter()
+            .chain(world.into_iter().flat_map(|world| world.gauges.iter()))
+            .cloned()
+            .collect();
+        // The config was checked when it was loaded, so these compile.
+        self.gauges = Gauges::compile(&specs).unwrap_or_default();
+        self.show_gauges();
+    }
+
+    /// Empty the gauges, so they don't go on showing numbers from a connection that's gone.
+    pub(super) fn clear_gauges(&mut self) {
+        self.gauges.clear();
+        self.show_gauges();
+    }
+
+    fn show_gauges(&mut self) {
+        self.ui.borrow_mut().set_gauges(self.gauges.readings().to_vec());
+    }
+
+    /// A GMCP message from `which`, which might have numbers for the gauges in it.
+    pub(super) fn gauge_gmcp(&mut self, which: ConnectionID, package: &str, data: &str) {
+        if self.active == Some(which) && self.gauges.gmcp(package, data) {
+            self.show_gauges();
+        }
+    }
+
+    /// The same as gauge_gmcp(), for an MSDP variable.
+    pub(super) fn gauge_msdp(&mut self, which: ConnectionID, variable: &str, data: &str) {
+        if self.active == Some(which) && self.gauges.msdp(variable, data) {
+            self.show_gauges();
+        }
+    }
+}
+
+#[test]
+fn updates_gauges() {
+    use crate::config::Config;
+    use crate::meta::Event;
+    use crate::testing::fake_client;
+
+    let (mut client, _, ui) = fake_client(vec![]);
+    client.set_config(Config::parse(r#"
+        [[gauges]]
+        label = "HP"
+        value = "Char.Vitals.hp"
+        max = "Char.Vitals.maxhp"
+        colour = "red"
+    "#).unwrap());
+    assert_eq!(ui.borrow().gauges[0].value, None);
+
+    let cid = client.connect("example.org:4000").unwrap();
+    let vitals = |which, data: &str| Event::Gmcp { which, package: "Char.Vitals".to_string(), data: data.to_string() };
+    client.handle_event(vitals(cid, r#"{"hp": 30, "maxhp": 40}"#));
+    client.handle_event(vitals(cid + 1, r#"{"hp": 10}"#));
+    let gauge = ui.borrow().gauges[0].clone();
+    assert_eq!((gauge.label.as_str(), gauge.value, gauge.max), ("HP", Some(30.0), Some(40.0)));
+
+    client.handle_event(Event::ConnectionEnd { which: cid, reason: "gone".to_string() });
+    assert_eq!(ui.borrow().gauges[0].value, None);
+}
@@ -0,0 +1,97 @@
+//! What happens to each line the user types on its way to the server: several commands on one
+//! line, repeats, speedwalks, aliases and client commands.  Also the `/alias` command.
+
+use crate::expand;
+
+use super::{commands, Client};
+
+impl Client {
+    /// Deal with a line of input from the user.  Returns false if it's time to quit.
+    pub(super) fn user_input(&mut self, line: String) -> bool {
+        let lines = self.expand_input(&line, true);
+        // Script aliases can ask for more than sending things.
+        self.script_results(None, vec![]);
+        let lines = match lines {
+            Ok(lines) => lines,
+            Err(why) => {
+                self.output(why);
+                return true;
+            },
+        };
+
+        for line in lines {
+            match commands::parse(&line) {
+                Some((name, args)) => {
+                    if !self.run_command(name, args) {
+                        return false;
+                    }
+                },
+                None => {
+                    let line = self.expand_variables(&line);
+                    self.send(commands::unescape(line));
+                },
+            }
+        }
+        true
+    }
+
+    /// Work out what commands a line of input stands for.  What aliases expand to is taken apart
+    /// in the same way, except that aliases aren't applied to it again (they do their own
+    /// expanding of aliases inside aliases.)  Scripts' aliases are tried before the config's.
+    fn expand_input(&mut self, line: &str, aliases: bool) -> Result<Vec<String>, String> {
+        let pieces = expand::split_commands(line, &self.config.input.separator);
+        let several = pieces.len() > 1;
+        let mut lines = vec![];
+
+        for piece in pieces {
+            // A line by itself goes as it was typed, even if that's blank, but the gaps around
+            // separators don't count.
+            let piece = if several { piece.trim() } else { piece.as_str() };
+            if several && piece.is_empty() {
+                continue;
+            }
+
+            let (count, piece) = expand::repeat_count(piece)?;
+            let scripted = match aliases && commands::parse(piece).is_none() {
+                true => self.scripts.alias(piece),
+                false => None,
+            };
+            let expanded = if let Some(sent) = scripted {
+                sent?
+            } else if commands::parse(piece).is_some() {
+                vec![piece.to_string()]
+            } else {
+                let aliased = if aliases { self.aliases.expand(piece)? } else { piece.to_string() };
+                if aliased != piece {
+                    self.expand_input(&aliased, false)?
+                } else if self.config.input.speedwalk {
+                    expand::speedwalk(piece).unwrap_or_else(|| vec![piece.to_string()])
+                } else {
+                    vec![piece.to_string()]
+                }
+            };
+
+            for _ in 0..count {
+                lines.extend(expanded.iter().cloned());
+            }
+        }
+
+        Ok(lines)
+    }
+
+    /// `/alias` or `/alias list`.
+    pub(super) fn cmd_alias(&mut self, args: &str) {
+        match args {
+            "" | "list" => {
+                if self.aliases.aliases().is_empty() {
+                    return self.output("No aliases are defined".to_string());
+                }
+                let lines: Vec<String> = self.aliases.aliases().iter().map(|alias| alias.describe()).collect();
+                for line in lines {
+                    self.output(line);
+                }
+            },
+            _ => self.output("Usage: /alias [list]".to_string()),
+        }
+    }
+}
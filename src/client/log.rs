@@ -0,0 +1,93 @@
+//! Keeping session logs, and the `/log` command.
+
+use crate::logging::{LogFormat, Logger};
+use crate::net::ConnectionID;
+
+use super::Client;
+
+impl Client {
+    /// Start logging connection `cid` with `logger`, replacing any log it already had.
+    pub fn start_log(&mut self, cid: ConnectionID, logger: Logger) {
+        self.logs.insert(cid, logger);
+    }
+
+    pub(super) fn log_server(&mut self, cid: ConnectionID, line: &str) {
+        let result = match self.logs.get_mut(&cid) {
+            Some(logger) => logger.server_text(line),
+            None => return,
+        };
+        self.check_log(cid, result);
+    }
+
+    pub(super) fn log_input(&mut self, cid: ConnectionID, line: &str) {
+        let result = match self.logs.get_mut(&cid) {
+            Some(logger) => logger.input(line),
+            None => return,
+        };
+        self.check_log(cid, result);
+    }
+
+    /// If writing to a log failed, stop logging rather than complain about every single line.
+    fn check_log(&mut self, cid: ConnectionID, result: std::io::Result<()>) {
+        if let Err(e) = result {
+            self.logs.remove(&cid);
+            self.output(format!("Couldn't write to log ({}); logging stopped", e));
+        }
+    }
+
+    /// `/log start [text|ansi|html]`, `/log stop`, or just `/log` to see what's being logged.
+    pub(super) fn cmd_log(&mut self, args: &str) {
+        let mut args = args.split_whitespace();
+        let (sub, format) = (args.next(), args.next());
+
+        let cid = match self.active {
+            Some(cid) => cid,
+            None => return self.output("Not connected".to_string()),
+        };
+
+        match sub {
+            None => {
+                let status = match self.logs.get(&cid) {
+                    Some(logger) => format!("Logging to {}", logger.path().display()),
+                    None => "Not logging".to_string(),
+                };
+                self.output(status);
+            },
+            Some("start") => {
+                let format = match format {
+                    Some(name) => match LogFormat::from_name(name) {
+                        Some(format) => Some(format),
+                        None => return self.output(format!("Unknown log format '{}'", name)),
+                    },
+                    None => None,
+                };
+                self.cmd_log_start(cid, format);
+            },
+            Some("stop") => {
+                match self.logs.remove(&cid) {
+                    Some(logger) => self.output(format!("Stopped logging to {}", logger.path().display())),
+                    None => self.output("Not logging".to_string()),
+                }
+            },
+            Some(_) => self.output("Usage: /log [start [text|ansi|html] | stop]".to_string()),
+        }
+    }
+
+    /// Start a daily log of `cid` in the configured directory.
+    pub(super) fn cmd_log_start(&mut self, cid: ConnectionID, format: Option<LogFormat>) {
+        let format = format.unwrap_or(self.config.logging.format);
+        let dir = match self.config.log_dir() {
+            Some(dir) => dir,
+            None => return self.output("Couldn't work out where to put logs".to_string()),
+        };
+        let name = self.names.get(&cid).cloned().unwrap_or_else(|| format!("connection-{}", cid));
+
+        match Logger::daily(&dir, &name, format) {
+            Ok(logger) => {
+                self.output(format!("Logging to {}", logger.path().display()));
+                self.start_log(cid, logger);
+            },
+            Err(why) => self.output(why),
+        }
+    }
+}
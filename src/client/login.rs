@@ -0,0 +1,149 @@
+//! Automatic login: answering a world's prompts from the `login` steps in its config.
+
+use regex::Regex;
+
+use crate::config::World;
+use crate::net::ConnectionID;
+
+use super::Client;
+
+/// How far through logging in to one connection we are.
+pub(super) struct LoginState {
+    cid: ConnectionID,
+    // Each step's pattern, response, and whether the response has the password in it (so it
+    // shouldn't end up in any logs.)
+    steps: Vec<(Regex, String, bool)>,
+    next: usize,
+}
+
+impl LoginState {
+    fn new(cid: ConnectionID, world: &World, password: &str) -> Option<LoginState> {
+        let login = world.login.as_ref()?;
+        let character = world.character.as_deref().unwrap_or("");
+
+        let steps = login.steps.iter().map(|step| {
+            // Patterns were checked when the config was loaded.
+            let pattern = Regex::new(&step.expect).expect("login pattern");
+            let secret = step.send.contains("$password");
+            let send = step.send.replace("$character", character).replace("$password", password);
+            (pattern, send, secret)
+        }).collect();
+
+        Some(LoginState { cid, steps, next: 0 })
+    }
+
+    /// If `text` from connection `cid` is what the next step is waiting for, return what to send.
+    fn check(&mut self, cid: ConnectionID, text: &str) -> Option<(String, bool)> {
+        if cid != self.cid {
+            return None;
+        }
+        let (ref pattern, ref send, secret) = *self.steps.get(self.next)?;
+        if pattern.is_match(text) {
+            self.next += 1;
+            Some((send.clone(), secret))
+        } else {
+            None
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.next >= self.steps.len()
+    }
+}
+
+impl Client {
+    /// Get ready to log in on `cid`, if `world` is set up for it.
+    pub(super) fn start_login(&mut self, cid: ConnectionID, world: &World) {
+        self.login = None;
+        let needs_password = match world.login {
+            Some(ref login) => login.steps.iter().any(|step| step.send.contains("$password")),
+            None => return,
+        };
+
+        let password = match self.config.password_for(world) {
+            Ok(Some(password)) => password,
+            Ok(None) if !needs_password => String::new(),
+            Ok(None) => {
+                return self.output(format!("Not logging in to {} automatically: no password found",
+                                           world.name));
+            },
+            Err(why) => {
+                return self.output(format!("Not logging in to {} automatically: {}",
+                                           world.name, why));
+            },
+        };
+
+        self.login = LoginState::new(cid, world, &password);
+    }
+
+    /// See if some text from the server is a login prompt we should answer.
+    pub(super) fn check_login(&mut self, cid: ConnectionID, text: &str) {
+        let response = match self.login {
+            Some(ref mut login) => login.check(cid, text),
+            None => return,
+        };
+
+        if let Some((line, secret)) = response {
+            if !secret {
+                self.log_input(cid, &line);
+            }
+            if self.write_line(cid, line).is_err() {
+                self.output("Couldn't write to connection".to_string());
+            }
+        }
+
+        if self.login.as_ref().is_some_and(|login| login.finished()) {
+            self.login = None;
+        }
+    }
+
+    /// Give up on logging in if the connection goes away.
+    pub(super) fn end_login(&mut self, cid: ConnectionID) {
+        if self.login.as_ref().is_some_and(|login| login.cid == cid) {
+            self.login = None;
+        }
+    }
+}
+
+#[test]
+fn logs_in_when_prompted() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use crate::config::Config;
+    use crate::meta::Event;
+    use crate::testing::fake_client;
+
+    // The password comes from a secrets file next to the config.
+    let dir = std::env::temp_dir().join(format!("mint-login-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let secrets = dir.join("secrets.toml");
+    fs::write(&secrets, "[worlds.test]\npassword = \"hunter2\"\n").unwrap();
+    fs::set_permissions(&secrets, fs::Permissions::from_mode(0o600)).unwrap();
+
+    let mut config = Config::parse(r#"
+        [worlds.test]
+        host = "example.org"
+        port = 4000
+        character = "Bilbo"
+        login.steps = [
+            { expect = "^By what name", send = "$character" },
+            { expect = "^Password:", send = "$password" },
+        ]
+    "#).unwrap();
+    config.path = Some(dir.join("config.toml"));
+
+    let (mut client, conns, _) = fake_client(vec![
+        Event::ServerText { line: "Welcome!".to_string(), which: 1 },
+        Event::ServerPrompt { text: "Password: ".to_string(), which: 1 },
+        Event::ServerPrompt { text: "By what name are you known? ".to_string(), which: 1 },
+        Event::ServerPrompt { text: "Password: ".to_string(), which: 1 },
+        Event::ServerPrompt { text: "Password: ".to_string(), which: 1 },
+        Event::QuitRequest,
+    ]);
+    client.set_config(config);
+    let cid = client.connect("test").unwrap();
+    assert_eq!(client.run(), Ok(()));
+
+    assert_eq!(conns.borrow().written_to(cid), vec!["Bilbo\r\n".to_string(), "hunter2\r\n".to_string()]);
+    fs::remove_dir_all(&dir).unwrap();
+}
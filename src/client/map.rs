@@ -0,0 +1,300 @@
+//! Keeping the automapper's map up to date, showing it, and walking around it.
+
+use crate::map::{self, Map, RoomInfo, Step};
+use crate::net::ConnectionID;
+use crate::timer::TimerID;
+
+use super::Client;
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A `/goto` on its way somewhere.
+pub(super) struct Walk {
+    // What the user asked to go to.
+    target: String,
+    // The steps still to take.
+    steps: VecDeque<Step>,
+    // Where the last step should have taken us.
+    expected: String,
+    // The timer that takes the next step, once we've got where the last one was going.
+    timer: Option<TimerID>,
+}
+
+impl Client {
+    /// Keep maps in `dir`, a file for each world.  Until this is called, maps are neither loaded
+    /// nor saved.
+    pub fn set_map_dir(&mut self, dir: PathBuf) {
+        self.map_dir = Some(dir);
+    }
+
+    /// A GMCP message or MSDP variable from `which`, if it was about the room we're in.
+    pub(super) fn map_room(&mut self, which: ConnectionID, info: Option<RoomInfo>) {
+        let info = match info {
+            Some(info) if self.active == Some(which) => info,
+            _ => return,
+        };
+        let id = info.id.clone();
+
+        // The map's only loaded once there's something to put on it, so that worlds without
+        // room data don't get empty maps.
+        if self.map.is_none() {
+            self.map = Some(self.load_map());
+        }
+        if self.map.as_mut().expect("map was just loaded").enter(info) {
+            self.save_map();
+        }
+        self.show_map();
+        self.walk_arrived(&id);
+    }
+
+    /// Where the active connection's map is kept, named after its world or its address.
+    fn map_path(&self) -> Option<PathBuf> {
+        let name = self.names.get(&self.active?)?;
+        // Don't let an odd world name or address put the file somewhere strange.
+        let name: String = name.chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+            .collect();
+        Some(self.map_dir.as_ref()?.join(format!("{}.json", name)))
+    }
+
+    fn load_map(&mut self) -> Map {
+        match self.map_path().map(|path| Map::load(&path)) {
+            Some(Ok(map)) => map,
+            Some(Err(why)) => {
+                self.output(format!("{}; starting a new one", why));
+                Map::new()
+            },
+            None => Map::new(),
+        }
+    }
+
+    /// Save the map after every change, so that nothing's lost if the connection (or we) go away.
+    fn save_map(&mut self) {
+        let saved = match (self.map_path(), &self.map) {
+            (Some(path), Some(map)) => map.save(&path),
+            _ => Ok(()),
+        };
+        if let Err(why) = saved {
+            self.output(why);
+        }
+    }
+
+    /// Redraw the map pane, if there is one.
+    pub(super) fn show_map(&mut self) {
+        let lines = match self.map {
+            Some(ref map) if self.config.map.pane => map::render(map, self.config.map.width, self.config.map.height),
+            _ => vec![],
+        };
+        self.ui.borrow_mut().show_pane("map".to_string(), lines);
+    }
+
+    /// `/map` shows the map in the main window; the rest change what we know about the room
+    /// we're in and its exits.
+    pub(super) fn cmd_map(&mut self, args: &str) {
+        // After reconnecting, the saved map is there before the server's said where we are.
+        if self.map.is_none() && self.active.is_some() {
+            self.map = Some(self.load_map());
+        }
+        let map = match self.map {
+            Some(ref mut map) if map.current().is_some() => map,
+            _ => return self.output("No map yet".to_string()),
+        };
+
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let changed = match args.as_slice() {
+            [] => {
+                let lines = map::render(map, self.config.map.width, self.config.map.height);
+                for line in lines {
+                    self.output(line.trim_end().to_string());
+                }
+                return;
+            },
+            ["label"] => map.set_label(None),
+            ["label", label] => map.set_label(Some(label.to_string())),
+            ["door", dir] => map.change_exit(dir, |exit| exit.door = None),
+            ["door", dir, door] => map.change_exit(dir, |exit| exit.door = Some(door.to_string())),
+            ["lock", dir] => map.change_exit(dir, |exit| exit.locked = true),
+            ["unlock", dir] => map.change_exit(dir, |exit| exit.locked = false),
+            ["weight", dir, weight] => match weight.parse() {
+                Ok(weight) if weight > 0 => map.change_exit(dir, |exit| exit.weight = weight),
+                _ => return self.output(format!("Bad weight '{}'", weight)),
+            },
+            _ => return self.output("Usage: /map [label [NAME] | door DIR [NAME] | lock DIR | unlock DIR | weight DIR N]".to_string()),
+        };
+
+        if changed {
+            self.save_map();
+        } else {
+            self.output(format!("There's no exit '{}' here", args.get(1).unwrap_or(&"")));
+        }
+    }
+
+    /// `/goto ROOM` walks to a room on the map, by its label, number or name; `/goto` on its own
+    /// stops walking.
+    pub(super) fn cmd_goto(&mut self, args: &str) {
+        if args.is_empty() {
+            if self.walk.is_some() {
+                self.stop_walk();
+                self.output("Stopped walking".to_string());
+            } else {
+                self.output("Usage: /goto ROOM".to_string());
+            }
+            return;
+        }
+
+        let map = match self.map {
+            Some(ref map) => map,
+            None => return self.output("No map yet".to_string()),
+        };
+        let (here, there) = match (map.current(), map.find(args)) {
+            (Some(here), Some(there)) => (here.to_string(), there.to_string()),
+            (None, _) => return self.output("Don't know where we are".to_string()),
+            (_, None) => return self.output(format!("Don't know any room '{}'", args)),
+        };
+        let steps = match map.path(&here, &there) {
+            Some(steps) if steps.is_empty() => return self.output("Already there".to_string()),
+            Some(steps) => steps,
+            None => return self.output(format!("Don't know the way to '{}'", args)),
+        };
+
+        self.stop_walk();
+        self.walk = Some(Walk {
+            target: args.to_string(),
+            steps: steps.into(),
+            expected: here,
+            timer: None,
+        });
+        self.take_step();
+    }
+
+    /// Send the commands for the next step of the walk.
+    fn take_step(&mut self) {
+        let step = match self.walk.as_mut() {
+            Some(walk) => match walk.steps.pop_front() {
+                Some(step) => {
+                    walk.timer = None;
+                    walk.expected = step.to.clone();
+                    step
+                },
+                None => return,
+            },
+            None => return,
+        };
+
+        for command in step.commands() {
+            self.send(command);
+        }
+    }
+
+    /// We've turned up in room `id`: carry on walking if that's where we were going, or give up.
+    fn walk_arrived(&mut self, id: &str) {
+        let walk = match self.walk {
+            Some(ref walk) => walk,
+            None => return,
+        };
+
+        if walk.expected != id {
+            let name = |id: &str| self.map.as_ref().and_then(|map| map.room(id))
+                .map_or_else(|| id.to_string(), |room| room.name.clone());
+            let message = format!("Stopped walking to {}: ended up in {} instead of {}",
+                                  walk.target, name(id), name(&walk.expected));
+            self.stop_walk();
+            return self.output(message);
+        }
+        if walk.steps.is_empty() {
+            let message = format!("Arrived at {}", walk.target);
+            self.walk = None;
+            return self.output(message);
+        }
+
+        let delay = Duration::from_secs_f64(self.config.map.walk_delay);
+        match self.timers {
+            Some(ref timers) => {
+                let timer = timers.borrow_mut().schedule(delay);
+                self.walk.as_mut().expect("walk was just checked").timer = Some(timer);
+            },
+            None => self.take_step(),
+        }
+    }
+
+    /// Take the next step, if timer `id` is the walk's.  Returns whether it was.
+    pub(super) fn walk_timer(&mut self, id: TimerID) -> bool {
+        match self.walk {
+            Some(ref walk) if walk.timer == Some(id) => {
+                self.take_step();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Give up on the walk, if there is one.
+    pub(super) fn stop_walk(&mut self) {
+        let timer = self.walk.take().and_then(|walk| walk.timer);
+        if let (Some(timer), Some(timers)) = (timer, self.timers.as_ref()) {
+            timers.borrow_mut().cancel(timer);
+        }
+    }
+}
+
+#[test]
+fn walks_with_goto() {
+    use crate::config::Config;
+    use crate::meta::Event;
+    use crate::testing::fake_client;
+    use crate::timer::TimerManager;
+    use std::{cell::RefCell, fs, rc::Rc};
+
+    let dir = std::env::temp_dir().join(format!("mint-walk-test-{}", std::process::id()));
+    let (mut client, conns, ui) = fake_client(vec![]);
+    // Without the clock on the status line, the walk's timers are the only ones.
+    client.set_config(Config::parse("[status]\nshow = false\n[map]\npane = false").unwrap());
+    client.set_timers(Rc::new(RefCell::new(TimerManager::new())));
+    client.set_map_dir(dir.clone());
+    let cid = client.connect_address("example.org:4000".to_string()).unwrap();
+
+    let room = |num: u32, exits: &str| Event::Gmcp {
+        which: cid,
+        package: "Room.Info".to_string(),
+        data: format!(r#"{{"num": {}, "name": "Room {}", "exits": {{{}}}}}"#, num, num, exits),
+    };
+    let input = |line: &str| Event::UserInput { line: line.to_string(), which: 0 };
+    let sent = || conns.borrow_mut().written.drain(..).map(|(_, line)| line).collect::<Vec<_>>();
+
+    // 1 -n- 2 -e- 3 -e- 4, with a gate between 3 and 4.
+    client.handle_event(room(1, r#""n": 2"#));
+    client.handle_event(room(2, r#""s": 1, "e": 3"#));
+    client.handle_event(room(3, r#""w": 2, "e": 4"#));
+    client.handle_event(room(4, r#""w": 3"#));
+    client.handle_event(input("/map door w gate"));
+    assert!(dir.join("example.org_4000.json").exists());
+
+    client.handle_event(input("/goto 1"));
+    assert_eq!(sent(), vec!["open gate\r\n", "w\r\n"]);
+
+    // Each step waits for its timer, and only its own.
+    client.handle_event(room(3, r#""w": 2, "e": 4"#));
+    assert!(sent().is_empty());
+    client.handle_event(Event::Timer { id: 99 });
+    assert!(sent().is_empty());
+    client.handle_event(Event::Timer { id: 1 });
+    assert_eq!(sent(), vec!["w\r\n"]);
+    client.handle_event(room(2, r#""s": 1, "e": 3"#));
+    client.handle_event(Event::Timer { id: 2 });
+    assert_eq!(sent(), vec!["s\r\n"]);
+    client.handle_event(room(1, r#""n": 2"#));
+    assert_eq!(ui.borrow().lines("default").last().unwrap(), "Arrived at 1");
+
+    // Ending up somewhere unexpected stops the walk.
+    client.handle_event(input("/goto 4"));
+    assert_eq!(sent(), vec!["n\r\n"]);
+    client.handle_event(room(3, r#""w": 2, "e": 4"#));
+    assert_eq!(ui.borrow().lines("default").last().unwrap(),
+               "Stopped walking to 4: ended up in Room 3 instead of Room 2");
+    client.handle_event(Event::Timer { id: 3 });
+    assert!(sent().is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
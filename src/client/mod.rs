@@ -0,0 +1,334 @@
+use crate::aliases::Aliases;
+use crate::ansi::Style;
+use crate::config::{Config, World};
+use crate::filters::Filters;
+use crate::gauges::Gauges;
+use crate::highlights::Highlights;
+use crate::logging::Logger;
+use crate::map::{Map, RoomInfo};
+use crate::meta::{Event, EventManager};
+use crate::net::encoding::Encoding;
+use crate::net::{ConnectionInterface, ConnectionID};
+use crate::scripting::Scripts;
+use crate::timer::{TimerID, TimerManager};
+use crate::triggers::TriggerSet;
+use crate::ui::UserInterface;
+use crate::variables::{Scope, Value, Variables};
+use crate::watcher::FileWatcher;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+mod commands;
+mod gauges;
+mod input;
+mod log;
+mod login;
+mod map;
+mod reload;
+mod scripts;
+mod status;
+mod text;
+mod variables;
+
+/// The core of the client: pulls Events out of an EventManager and routes them between the
+/// connections and the user interface.  It only knows about those through their traits, so the
+/// same core can be driven by the terminal UI and real sockets, or by the fakes in `testing`.
+pub struct Client {
+    events: Box<dyn EventManager>,
+    conns: Rc<RefCell<dyn ConnectionInterface>>,
+    ui: Rc<RefCell<dyn UserInterface>>,
+
+    // The connection that user input gets sent to, if any.  Since we don't have real window
+    // management or multiple connections yet, this is the only connection we really care about.
+    active: Option<ConnectionID>,
+    // The world the active connection is to, if it was made by name.
+    world: Option<String>,
+    // What to call each connection: its world's name, or else its address.
+    names: HashMap<ConnectionID, String>,
+    // Whether each connection is connecting, connected or closed.
+    states: HashMap<ConnectionID, &'static str>,
+
+    config: Config,
+    // Where we are in logging in automatically, if we are.
+    login: Option<login::LoginState>,
+
+    // The connections we're keeping logs of.
+    logs: HashMap<ConnectionID, Logger>,
+
+    // The global triggers, gags, substitutions, highlights and aliases, and the active world's.
+    triggers: TriggerSet,
+    filters: Filters,
+    highlights: Highlights,
+    aliases: Aliases,
+    // The gauges, and the numbers they're showing for the active connection.
+    gauges: Gauges,
+    // The colours in effect at the end of the last line from each connection, since they carry
+    // over from one line to the next.
+    styles: HashMap<ConnectionID, Style>,
+
+    // Scripts get at the variables too.
+    variables: Rc<RefCell<Variables>>,
+    // Where to save the variables marked to be saved, if anywhere.
+    variables_path: Option<PathBuf>,
+
+    // Where scripts' timers are scheduled, if there's anywhere.
+    timers: Option<Rc<RefCell<TimerManager>>>,
+    // The global scripts and the active world's.
+    scripts: Scripts,
+    // Watches the config file and scripts, if anything is.
+    watcher: Option<Rc<RefCell<FileWatcher>>>,
+
+    // The active connection's map, once the server's told us about a room, where we're walking
+    // to on it, and where maps are kept, if anywhere.
+    map: Option<Map>,
+    walk: Option<map::Walk>,
+    map_dir: Option<PathBuf>,
+
+    // What's on the status line, and the timer that keeps its time right.
+    status: Vec<String>,
+    clock: Option<TimerID>,
+}
+
+impl Client {
+    /// Create a new Client.  Any EventSources involved (which will usually include `conns` and
+    /// `ui`) should already have been started on `events`.
+    pub fn new(events: Box<dyn EventManager>,
+               conns: Rc<RefCell<dyn ConnectionInterface>>,
+               ui: Rc<RefCell<dyn UserInterface>>) -> Client {
+        let variables = Rc::new(RefCell::new(Variables::new()));
+        Client {
+            events,
+            conns,
+            ui,
+            active: None,
+            world: None,
+            config: Config::default(),
+            names: HashMap::new(),
+            states: HashMap::new(),
+            login: None,
+            logs: HashMap::new(),
+            triggers: TriggerSet::new(),
+            filters: Filters::new(),
+            highlights: Highlights::new(),
+            aliases: Aliases::new(),
+            gauges: Gauges::new(),
+            styles: HashMap::new(),
+            scripts: Scripts::new(variables.clone(), None, None),
+            variables,
+            variables_path: None,
+            timers: None,
+            watcher: None,
+            map: None,
+            walk: None,
+            map_dir: None,
+            status: vec![],
+            clock: None,
+        }
+    }
+
+    /// Schedule scripts' timers with `timers`, which should already have been started on the
+    /// EventManager.  This needs doing before set_config(), which loads the scripts.
+    pub fn set_timers(&mut self, timers: Rc<RefCell<TimerManager>>) {
+        self.timers = Some(timers);
+    }
+
+    /// Reload the config file and scripts when they change, using `watcher`, which should
+    /// already have been started on the EventManager.
+    pub fn set_watcher(&mut self, watcher: Rc<RefCell<FileWatcher>>) {
+        self.watcher = Some(watcher);
+        self.watch_files();
+    }
+
+    /// Use the worlds and settings from `config`.
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+        self.load_rules();
+        self.load_gauges();
+        self.load_scripts();
+        self.seed_variables();
+        self.start_clock();
+        self.update_status();
+    }
+
+    /// Each world's settings are where its variables start out, if they haven't been saved.
+    fn seed_variables(&mut self) {
+        for world in self.config.worlds.values() {
+            for (name, value) in &world.settings {
+                if let Some(value) = Value::from_toml(value) {
+                    self.variables.borrow_mut().set_default(Scope::World(&world.name), name, value);
+                }
+            }
+        }
+    }
+
+    /// Start a connection and make it the active one.  `target` can be the name of a world from
+    /// the config, or an address for the ConnectionInterface.
+    pub fn connect(&mut self, target: &str) -> Result<ConnectionID, String> {
+        let address = self.config.address_for(target)?;
+        let world = self.config.worlds.get(target.trim_start_matches("tls://")).cloned();
+        let cid = self.conns.borrow_mut().start_connection(address.clone())?;
+
+        let name = world.as_ref().map_or(address, |world| world.name.clone());
+        self.attach(cid, name, world);
+        Ok(cid)
+    }
+
+    /// Start a connection to `address`, exactly as given, and make it the active one.
+    pub fn connect_address(&mut self, address: String) -> Result<ConnectionID, String> {
+        let cid = self.conns.borrow_mut().start_connection(address.clone())?;
+        self.attach(cid, address, None);
+        Ok(cid)
+    }
+
+    /// Set things up for a new connection, which becomes the active one.
+    fn attach(&mut self, cid: ConnectionID, name: String, world: Option<World>) {
+        self.active = Some(cid);
+        self.names.insert(cid, name);
+        self.states.insert(cid, "connecting");
+        self.world = world.as_ref().map(|world| world.name.clone());
+        self.load_rules();
+        self.load_gauges();
+        self.load_scripts();
+        self.stop_walk();
+        self.map = None;
+        self.show_map();
+        if let Some(encoding) = world.as_ref().and_then(|world| Encoding::from_name(&world.encoding)) {
+            self.conns.borrow_mut().set_encoding(cid, encoding);
+        }
+        match world {
+            Some(ref world) => self.start_login(cid, world),
+            None => self.login = None,
+        }
+        if self.config.logging.auto {
+            self.cmd_log_start(cid, None);
+        }
+    }
+
+    /// Process Events until the user asks to quit (returning Ok) or the EventManager gives up
+    /// (returning its reason.)
+    pub fn run(&mut self) -> Result<(), String> {
+        loop {
+            let event = self.events.next_event()?;
+            if !self.handle_event(event) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Deal with a single Event.  Returns false if it's time to quit.
+    pub fn handle_event(&mut self, event: Event) -> bool {
+        let carry_on = self.route_event(event);
+        self.update_status();
+        carry_on
+    }
+
+    fn route_event(&mut self, event: Event) -> bool {
+        match event {
+            Event::ServerText { line, which } | Event::ServerPrompt { text: line, which } => {
+                self.server_text(which, line);
+            },
+            Event::Gmcp { which, package, data } => {
+                if package.eq_ignore_ascii_case("Room.Info") {
+                    self.map_room(which, RoomInfo::from_gmcp(&data));
+                }
+                self.gauge_gmcp(which, &package, &data);
+                self.script_gmcp(which, &package, &data);
+            },
+            Event::Msdp { which, variable, data } => {
+                if variable == "ROOM" {
+                    self.map_room(which, RoomInfo::from_msdp(&data));
+                }
+                self.gauge_msdp(which, &variable, &data);
+            },
+            Event::Timer { id } => {
+                if !self.is_clock(id) && !self.walk_timer(id) {
+                    self.script_timer(id);
+                }
+            },
+            Event::FilesChanged { paths } => {
+                self.files_changed(&paths);
+            },
+            Event::ConnectionStart { which } => {
+                self.states.insert(which, "connected");
+                let name = self.names.get(&which).cloned().unwrap_or_else(|| which.to_string());
+                self.output(format!("Connected to {}", name));
+            },
+            Event::ConnectionEnd { which, reason } => {
+                self.end_login(which);
+                self.states.insert(which, "closed");
+                if self.active == Some(which) {
+                    self.stop_walk();
+                    self.clear_gauges();
+                }
+                self.logs.remove(&which);
+                self.styles.remove(&which);
+                self.conns.borrow_mut().stop_recording(which);
+                self.output(format!("Connection {} closed: {}", which, reason));
+            },
+            Event::QuitRequest => {
+                return false;
+            },
+            Event::UserInput { line, which: _ } => {
+                return self.user_input(line);
+            },
+            event => {
+                self.output(format!("Unhandled event: {:?}", event));
+            },
+        }
+
+        true
+    }
+
+    /// Send a line of input to the active connection.
+    fn send(&mut self, line: String) {
+        match self.active {
+            Some(cid) => self.send_to(cid, line),
+            None => self.output("Not connected".to_string()),
+        }
+    }
+
+    /// Send a line of input to connection `cid`.
+    fn send_to(&mut self, cid: ConnectionID, line: String) {
+        self.log_input(cid, &line);
+        if self.write_line(cid, line).is_err() {
+            self.output("Couldn't write to connection".to_string());
+        }
+    }
+
+    /// Write a line to a connection, without logging it.  Telnet says lines end in `\r\n`.
+    fn write_line(&mut self, cid: ConnectionID, mut line: String) -> Result<(), ()> {
+        line.push_str("\r\n");
+        self.conns.borrow_mut().write_to_connection(cid, line)
+    }
+
+    /// Show a line of text to the user.
+    pub fn output(&mut self, line: String) {
+        self.output_to("default", line);
+    }
+
+    /// Show a line of text in a particular window.
+    fn output_to(&mut self, window: &str, line: String) {
+        // If the UI can't show anything, there's nobody to complain to about it.
+        let _ = self.ui.borrow_mut().push_to_window(window.to_string(), line);
+    }
+}
+
+#[test]
+fn routes_text_and_input() {
+    use crate::testing::fake_client;
+
+    let (mut client, conns, ui) = fake_client(vec![
+        Event::ServerText { line: "Hello there.".to_string(), which: 1 },
+        Event::UserInput { line: "look".to_string(), which: 0 },
+        Event::QuitRequest,
+        Event::UserInput { line: "never sent".to_string(), which: 0 },
+    ]);
+    let cid = client.connect("example.org:4000").unwrap();
+    assert_eq!(client.run(), Ok(()));
+
+    assert_eq!(ui.borrow().lines("default"), vec!["Hello there.".to_string()]);
+    assert_eq!(conns.borrow().written_to(cid), vec!["look\r\n".to_string()]);
+}
@@ -0,0 +1,64 @@
+//! Reloading the config file and scripts when they change, or when the user asks with `/reload`.
+
+use crate::config::Config;
+
+use super::Client;
+
+use std::path::PathBuf;
+
+impl Client {
+    /// Tell the file watcher what to watch: the config file, if there is one, and the scripts.
+    pub(super) fn watch_files(&mut self) {
+        let watcher = match self.watcher {
+            Some(ref watcher) => watcher.clone(),
+            None => return,
+        };
+        let paths = self.config.path.iter().cloned().chain(self.script_paths());
+        watcher.borrow_mut().watch(paths);
+    }
+
+    pub(super) fn files_changed(&mut self, paths: &[PathBuf]) {
+        if self.config.path.as_ref().is_some_and(|config| paths.contains(config)) {
+            // Reloading the config reloads the scripts too.
+            self.reload_config();
+        } else if self.reload_scripts() {
+            self.output("Reloaded scripts".to_string());
+        }
+    }
+
+    /// Read the config file again.  If it has errors, we keep using what we had.
+    fn reload_config(&mut self) {
+        let path = match self.config.path {
+            Some(ref path) => path.clone(),
+            None => return self.output("There's no config file to reload".to_string()),
+        };
+        let config = match Config::load(&path) {
+            Ok(config) => config,
+            Err(why) => return self.output(format!("Config not reloaded: {}", why)),
+        };
+
+        let old = std::mem::replace(&mut self.config, config);
+        if !self.reload_scripts() {
+            self.config = old;
+            return self.output("Config not reloaded, because of errors in the scripts".to_string());
+        }
+        self.load_rules();
+        self.load_gauges();
+        self.seed_variables();
+        self.show_map();
+        self.start_clock();
+        self.output(format!("Reloaded {}", path.display()));
+    }
+
+    /// `/reload`: the config file and scripts.
+    pub(super) fn cmd_reload(&mut self) {
+        match self.config.path {
+            Some(_) => self.reload_config(),
+            None => {
+                if self.reload_scripts() {
+                    self.output("Reloaded scripts".to_string());
+                }
+            },
+        }
+    }
+}
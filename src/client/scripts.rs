@@ -0,0 +1,92 @@
+//! Loading scripts, and dealing with what they ask for.
+
+use crate::net::ConnectionID;
+use crate::scripting::{Request, Scripts};
+use crate::timer::TimerID;
+
+use super::Client;
+
+use std::path::PathBuf;
+
+impl Client {
+    /// The global scripts, then the active world's.
+    pub(super) fn script_paths(&self) -> Vec<PathBuf> {
+        let world = self.world.as_ref().and_then(|name| self.config.worlds.get(name));
+        self.config.scripts.iter()
+            .chain(world.into_iter().flat_map(|world| world.scripts.iter()))
+            .map(|path| self.config.resolve(path))
+            .collect()
+    }
+
+    /// Load the scripts afresh, replacing whatever was loaded before.  Scripts with errors are
+    /// left out.
+    pub(super) fn load_scripts(&mut self) {
+        let (scripts, errors) = self.build_scripts(false);
+        // Dropping the old scripts cancels their timers.
+        self.scripts = scripts;
+        self.script_results(self.active, errors);
+        self.watch_files();
+    }
+
+    /// Load the scripts afresh, but only replace the ones that are loaded if they all load
+    /// without errors.  Returns whether they did.
+    pub(super) fn reload_scripts(&mut self) -> bool {
+        let (mut scripts, errors) = self.build_scripts(true);
+        scripts.copy_gmcp(&self.scripts);
+        if !errors.is_empty() {
+            // Dropping the new scripts gets rid of anything they set up before going wrong,
+            // including the variables they set, which were only staged.
+            drop(scripts);
+            self.script_results(None, errors);
+            return false;
+        }
+        scripts.commit_variables();
+        self.scripts = scripts;
+        self.script_results(self.active, vec![]);
+        self.watch_files();
+        true
+    }
+
+    /// Load the scripts into a new Scripts, with their variables staged if `staged` is set.
+    fn build_scripts(&self, staged: bool) -> (Scripts, Vec<String>) {
+        let mut scripts = Scripts::new(self.variables.clone(), self.timers.clone(), self.world.clone());
+        if staged {
+            scripts.stage_variables();
+        }
+        let errors = self.script_paths().iter().filter_map(|path| scripts.load(path).err()).collect();
+        (scripts, errors)
+    }
+
+    /// Report scripts' errors and do what they've asked.  Anything they send goes to `cid`, or
+    /// else the active connection.
+    pub(super) fn script_results(&mut self, cid: Option<ConnectionID>, errors: Vec<String>) {
+        for why in errors {
+            self.output(why);
+        }
+        for request in self.scripts.take_requests() {
+            match request {
+                Request::Send(line) => match cid {
+                    Some(cid) => self.send_to(cid, line),
+                    None => self.send(line),
+                },
+                Request::Show { window, line } => self.output_to(&window, line),
+            }
+        }
+    }
+
+    /// Pass a GMCP message on to the scripts, if it's from the active connection.
+    pub(super) fn script_gmcp(&mut self, cid: ConnectionID, package: &str, data: &str) {
+        if self.active == Some(cid) {
+            let errors = self.scripts.gmcp(package, data);
+            self.script_results(Some(cid), errors);
+        }
+    }
+
+    /// Run a script's timer.
+    pub(super) fn script_timer(&mut self, id: TimerID) {
+        // Timers that aren't the scripts' would be left over from scripts that have gone.
+        if let Some(result) = self.scripts.timer(id) {
+            self.script_results(None, result.err().into_iter().collect());
+        }
+    }
+}
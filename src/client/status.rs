@@ -0,0 +1,86 @@
+//! The status line: filling in its fields, and keeping them up to date.
+
+use crate::timer::TimerID;
+
+use super::Client;
+
+use std::time::Duration;
+
+impl Client {
+    /// Fill in the status line's fields from `[status]`, and pass them on to the UI if they've
+    /// changed.  `{scroll}` is left for the UI.
+    pub(super) fn update_status(&mut self) {
+        let fields = match self.config.status.show {
+            true => self.config.status.fields.iter().map(|field| self.status_field(field)).collect(),
+            false => vec![],
+        };
+        if fields != self.status {
+            self.status = fields.clone();
+            self.ui.borrow_mut().set_status(fields);
+        }
+    }
+
+    fn status_field(&self, field: &str) -> String {
+        let world = self.active.and_then(|cid| self.names.get(&cid)).map_or("", String::as_str);
+        let state = match self.active.and_then(|cid| self.states.get(&cid)) {
+            Some(state) => state,
+            None => "not connected",
+        };
+        let logging = match self.active {
+            Some(cid) if self.logs.contains_key(&cid) => "logging",
+            _ => "",
+        };
+        let time = chrono::Local::now().format(&self.config.status.time_format).to_string();
+
+        let field = field.replace("{world}", world)
+            .replace("{state}", state)
+            .replace("{log}", logging)
+            .replace("{time}", &time);
+        self.expand_variables(&field)
+    }
+
+    /// Keep the time on the status line right, if it's there and we've got a timer to do it with.
+    pub(super) fn start_clock(&mut self) {
+        let wanted = self.config.status.show && self.config.status.fields.iter().any(|field| field.contains("{time}"));
+        let timers = match self.timers {
+            Some(ref timers) => timers,
+            None => return,
+        };
+        match (wanted, self.clock) {
+            (true, None) => self.clock = Some(timers.borrow_mut().schedule_repeating(Duration::from_secs(1))),
+            (false, Some(clock)) => {
+                timers.borrow_mut().cancel(clock);
+                self.clock = None;
+            },
+            _ => {},
+        }
+    }
+
+    /// Whether timer `id` is the status line's clock.  There's nothing more to do when it goes
+    /// off, since the status line is updated after every event anyway.
+    pub(super) fn is_clock(&self, id: TimerID) -> bool {
+        self.clock == Some(id)
+    }
+}
+
+#[test]
+fn fills_in_status() {
+    use crate::config::Config;
+    use crate::meta::Event;
+    use crate::testing::fake_client;
+
+    let (mut client, _, ui) = fake_client(vec![]);
+    client.set_config(Config::parse(r#"
+        [status]
+        fields = ["{world}", "{state}", "{log}", "hp $hp", "{scroll}"]
+    "#).unwrap());
+    assert_eq!(ui.borrow().status, vec!["", "not connected", "", "hp $hp", "{scroll}"]);
+
+    let cid = client.connect("example.org:4000").unwrap();
+    client.handle_event(Event::UserInput { line: "/set hp 30".to_string(), which: 0 });
+    assert_eq!(ui.borrow().status, vec!["example.org:4000", "connecting", "", "hp 30", "{scroll}"]);
+    client.handle_event(Event::ConnectionStart { which: cid });
+    assert_eq!(ui.borrow().status[1], "connected");
+    client.handle_event(Event::ConnectionEnd { which: cid, reason: "gone".to_string() });
+    assert_eq!(ui.borrow().status[1], "closed");
+}
@@ -0,0 +1,111 @@
+//! What happens to each line from the server on its way to the screen: logging, triggers, gags,
+//! substitutions and highlights.  Also the `/trigger` command.
+
+use crate::aliases::Aliases;
+use crate::ansi::StyledLine;
+use crate::filters::Filters;
+use crate::highlights::Highlights;
+use crate::net::ConnectionID;
+use crate::triggers::Trigger;
+
+use super::Client;
+
+impl Client {
+    /// Rebuild the triggers, gags, substitutions, highlights and aliases from the config: the
+    /// global ones, then the active world's.
+    pub(super) fn load_rules(&mut self) {
+        let world = self.world.as_ref().and_then(|name| self.config.worlds.get(name));
+        let worlds = || world.into_iter();
+
+        // The config was checked when it was loaded, so these all compile.
+        let triggers = self.config.triggers.iter()
+            .chain(worlds().flat_map(|world| world.triggers.iter()))
+            .filter_map(|spec| Trigger::compile(spec).ok())
+            .collect();
+        let gags = self.config.gags.iter().chain(worlds().flat_map(|world| world.gags.iter()));
+        let subs = self.config.subs.iter().chain(worlds().flat_map(|world| world.subs.iter()));
+        let filters = Filters::compile(gags, subs).unwrap_or_default();
+        let highlights = self.config.highlights.iter()
+            .chain(worlds().flat_map(|world| world.highlights.iter()));
+        let highlights = Highlights::compile(highlights).unwrap_or_default();
+        // Except that the world's aliases come first, so they can override the global ones.
+        let aliases = worlds().flat_map(|world| world.aliases.iter())
+            .chain(self.config.aliases.iter());
+        let aliases = Aliases::compile(aliases).unwrap_or_default();
+
+        self.triggers.set(triggers);
+        self.filters = filters;
+        self.highlights = highlights;
+        self.aliases = aliases;
+    }
+
+    /// Deal with a line from connection `cid`.
+    pub(super) fn server_text(&mut self, cid: ConnectionID, line: String) {
+        let style = self.styles.get(&cid).cloned().unwrap_or_default();
+        let (mut styled, style) = StyledLine::parse(&line, style);
+        self.styles.insert(cid, style);
+        let plain = styled.plain();
+
+        let gagged = self.filters.gag(&plain);
+        if gagged != Some(false) {
+            self.log_server(cid, &line);
+        }
+        self.check_login(cid, &line);
+
+        // Triggers see the line as the server sent it, even if it's gagged or rewritten.
+        let outcome = self.triggers.check(&plain);
+
+        if gagged.is_none() {
+            self.filters.substitute(&mut styled);
+            let alert = self.highlights.apply(&mut styled);
+            if alert.bell || alert.flag {
+                let name = self.names.get(&cid).cloned().unwrap_or_else(|| cid.to_string());
+                self.ui.borrow_mut().alert(name, alert.bell, alert.flag);
+            }
+            // What we show is self-contained: it starts and ends in the default style, whatever
+            // the server left switched on.
+            let line = styled.to_ansi();
+            match outcome.window {
+                Some(ref window) => self.output_to(window, line),
+                None => self.output(line),
+            }
+        }
+        // Captures have already been filled in, so a `$1` in a variable stays as it is.
+        for message in outcome.print {
+            let message = self.expand_variables(&message);
+            self.output(message);
+        }
+        for command in outcome.send {
+            let command = self.expand_variables(&command);
+            self.send_to(cid, command);
+        }
+
+        let errors = self.scripts.check_line(&plain);
+        self.script_results(Some(cid), errors);
+    }
+
+    /// `/trigger list`, `/trigger enable GROUP` or `/trigger disable GROUP`.
+    pub(super) fn cmd_trigger(&mut self, args: &str) {
+        let mut args = args.split_whitespace();
+        match (args.next(), args.next()) {
+            (Some("list"), None) | (None, None) => {
+                if self.triggers.triggers().is_empty() {
+                    return self.output("No triggers are defined".to_string());
+                }
+                let lines: Vec<String> = self.triggers.triggers().iter().map(|trigger| {
+                    format!("{:>4} {}{}{}", trigger.priority, trigger.pattern,
+                            trigger.group.as_ref().map_or(String::new(), |g| format!(" [{}]", g)),
+                            if self.triggers.is_enabled(trigger) { "" } else { " (disabled)" })
+                }).collect();
+                for line in lines {
+                    self.output(line);
+                }
+            },
+            (Some(which @ "enable"), Some(group)) | (Some(which @ "disable"), Some(group)) => {
+                self.triggers.enable_group(group, which == "enable");
+                self.output(format!("Triggers in group '{}' {}d", group, which));
+            },
+            _ => self.output("Usage: /trigger [list] | /trigger enable|disable GROUP".to_string()),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! The `/set` and `/unset` commands, and keeping saved variables on disk.
+
+use crate::variables::{self, Scope, Value};
+
+use super::Client;
+
+use std::path::PathBuf;
+
+impl Client {
+    /// Load saved variables from `path`, and save them back there whenever they change.
+    pub fn load_variables(&mut self, path: PathBuf) -> Result<(), String> {
+        let loaded = self.variables.borrow_mut().load(&path);
+        self.variables_path = Some(path);
+        loaded
+    }
+
+    fn save_variables(&mut self) {
+        let saved = match self.variables_path {
+            Some(ref path) => self.variables.borrow().save(path),
+            None => return,
+        };
+        if let Err(why) = saved {
+            self.output(why);
+        }
+    }
+
+    /// Expand variables in `text`, looking in the active world's scope first.
+    pub(super) fn expand_variables(&self, text: &str) -> String {
+        self.variables.borrow().expand(self.world.as_deref(), text)
+    }
+
+    /// Take the `-world` and `-save` options off the front of `/set` or `/unset`'s arguments.
+    fn scope_options<'a>(&self, args: &'a str) -> Result<(Option<String>, bool, &'a str), String> {
+        let mut world = None;
+        let mut save = false;
+        let mut args = args.trim_start();
+
+        loop {
+            let (word, rest) = args.split_at(args.find(char::is_whitespace).unwrap_or(args.len()));
+            match word {
+                "-world" => match self.world {
+                    Some(ref name) => world = Some(name.clone()),
+                    None => return Err("Not connected to a world".to_string()),
+                },
+                "-save" => save = true,
+                _ => return Ok((world, save, args)),
+            }
+            args = rest.trim_start();
+        }
+    }
+
+    /// `/set` lists variables, `/set NAME` shows one and `/set [-world] [-save] NAME VALUE` sets
+    /// one.  `-world` puts it in the active world's scope instead of the global one, and `-save`
+    /// keeps it for next time.
+    pub(super) fn cmd_set(&mut self, args: &str) {
+        let (world, save, args) = match self.scope_options(args) {
+            Ok(options) => options,
+            Err(why) => return self.output(why),
+        };
+        let scope = world.as_deref().map_or(Scope::Global, Scope::World);
+
+        let (name, value) = args.split_at(args.find(char::is_whitespace).unwrap_or(args.len()));
+        let value = value.trim();
+        if name.is_empty() {
+            let mut lines = vec![];
+            let scopes = std::iter::once(Scope::Global).chain(self.world.as_deref().map(Scope::World));
+            for scope in scopes {
+                for (name, var) in self.variables.borrow().list(scope) {
+                    lines.push(format!("{}{} = {} ({}{})",
+                                       if let Scope::World(world) = scope { format!("{}:", world) } else { String::new() },
+                                       name, var.value, var.value.type_name(),
+                                       if var.persist { ", saved" } else { "" }));
+                }
+            }
+            if lines.is_empty() {
+                lines.push("No variables are set".to_string());
+            }
+            for line in lines {
+                self.output(line);
+            }
+            return;
+        }
+
+        if !variables::is_name(name) {
+            return self.output(format!("'{}' isn't a good variable name", name));
+        }
+        if value.is_empty() {
+            let line = match self.variables.borrow().get(self.world.as_deref(), name) {
+                Some(value) => format!("{} = {}", name, value),
+                None => format!("{} isn't set", name),
+            };
+            return self.output(line);
+        }
+
+        if self.variables.borrow_mut().set(scope, name, Value::parse(value), save) {
+            self.save_variables();
+        }
+    }
+
+    /// `/unset [-world] NAME`.
+    pub(super) fn cmd_unset(&mut self, args: &str) {
+        let (world, _, name) = match self.scope_options(args) {
+            Ok(options) => options,
+            Err(why) => return self.output(why),
+        };
+        let scope = world.as_deref().map_or(Scope::Global, Scope::World);
+
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return self.output("Usage: /unset [-world] NAME".to_string());
+        }
+        let removed = self.variables.borrow_mut().unset(scope, name);
+        match removed {
+            Some(var) => {
+                if var.persist {
+                    self.save_variables();
+                }
+            },
+            None => self.output(format!("{} isn't set", name)),
+        }
+    }
+}
@@ -0,0 +1,466 @@
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::aliases::{AliasSpec, Aliases};
+use crate::filters::{Filters, GagSpec, SubSpec};
+use crate::gauges::{GaugeSpec, Gauges};
+use crate::highlights::{HighlightSpec, Highlights};
+use crate::logging::LogFormat;
+use crate::net::encoding::Encoding;
+use crate::triggers::{Trigger, TriggerSpec};
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// How to talk to a world.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    #[default]
+    Plain,
+    Tls,
+}
+
+/// A saved connection profile, from a `[worlds.NAME]` table in the config file.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct World {
+    // Filled in from the table's key, not from the table itself.
+    #[serde(skip)]
+    pub name: String,
+
+    pub host: String,
+    pub port: u16,
+    #[serde(default)]
+    pub transport: Transport,
+    /// What the server's text is in: UTF-8, Latin-1 or CP437.  Servers that support CHARSET
+    /// negotiation get switched to UTF-8 regardless.
+    #[serde(default = "default_encoding")]
+    pub encoding: String,
+    /// The name of the character we usually play here.
+    pub character: Option<String>,
+    /// Anything else that goes with this world and doesn't have a field of its own.
+    #[serde(default)]
+    pub settings: BTreeMap<String, toml::Value>,
+    /// How to log in automatically, if we should.
+    pub login: Option<Login>,
+    /// Triggers, gags, substitutions and highlights that only apply to this world.
+    #[serde(default)]
+    pub triggers: Vec<TriggerSpec>,
+    #[serde(default)]
+    pub gags: Vec<GagSpec>,
+    #[serde(default)]
+    pub subs: Vec<SubSpec>,
+    #[serde(default)]
+    pub highlights: Vec<HighlightSpec>,
+    /// Aliases for this world, which are tried before the global ones.
+    #[serde(default)]
+    pub aliases: Vec<AliasSpec>,
+    /// Scripts to load when connecting to this world, after the global ones.
+    #[serde(default)]
+    pub scripts: Vec<PathBuf>,
+    /// Gauges to show for this world, after the global ones.
+    #[serde(default)]
+    pub gauges: Vec<GaugeSpec>,
+}
+
+/// Automatic login for a world, from a `[worlds.NAME.login]` table.  Each step waits for a line
+/// or prompt from the server matching `expect`, then sends `send`.  In `send`, `$character` is
+/// replaced with the world's character name and `$password` with the password, which never lives
+/// in the config file itself: it comes from the environment variable named by `password_env`, or
+/// else from the world's table in the secrets file (see Config::password_for().)
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Login {
+    pub steps: Vec<LoginStep>,
+    pub password_env: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LoginStep {
+    /// A regular expression.
+    pub expect: String,
+    pub send: String,
+}
+
+fn default_encoding() -> String {
+    "utf-8".to_string()
+}
+
+impl World {
+    /// The address to hand to the TcpConnectionManager.
+    pub fn address(&self) -> String {
+        let scheme = match self.transport {
+            Transport::Plain => "",
+            Transport::Tls => "tls://",
+        };
+        // IPv6 addresses need brackets, which the host might have already.
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        if host.contains(':') {
+            format!("{}[{}]:{}", scheme, host, self.port)
+        } else {
+            format!("{}{}:{}", scheme, host, self.port)
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.host.is_empty() {
+            return Err("host is empty".to_string());
+        }
+        if self.port == 0 {
+            return Err("port can't be 0".to_string());
+        }
+        if Encoding::from_name(&self.encoding).is_none() {
+            return Err(format!("unsupported encoding '{}'", self.encoding));
+        }
+        if let Some(ref login) = self.login {
+            for step in &login.steps {
+                Regex::new(&step.expect).map_err(|e| format!("bad login pattern: {}", e))?;
+                if step.send.contains("$character") && self.character.is_none() {
+                    return Err("login uses $character, but no character is set".to_string());
+                }
+            }
+        }
+        for trigger in &self.triggers {
+            Trigger::compile(trigger)?;
+        }
+        Filters::compile(&self.gags, &self.subs)?;
+        Highlights::compile(&self.highlights)?;
+        Aliases::compile(&self.aliases)?;
+        Gauges::compile(&self.gauges)?;
+        Ok(())
+    }
+}
+
+/// Settings for session logs, from the `[logging]` table.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Where logs go; see Config::log_dir() for the default.
+    pub dir: Option<PathBuf>,
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Whether to start logging every connection as soon as it's made.
+    #[serde(default)]
+    pub auto: bool,
+}
+
+/// How lines of input are taken apart, from the `[input]` table.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct InputConfig {
+    /// What separates several commands typed on one line.  Empty means they can't be.
+    pub separator: String,
+    /// Whether to expand speedwalks like `3n2e`.
+    pub speedwalk: bool,
+}
+
+impl Default for InputConfig {
+    fn default() -> InputConfig {
+        InputConfig { separator: ";".to_string(), speedwalk: true }
+    }
+}
+
+/// The automapper's settings, from the `[map]` table.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct MapConfig {
+    /// Whether to show the map in a pane beside the text, when there's a map to show.
+    pub pane: bool,
+    /// The size of the map, including the line with the room's name.
+    pub width: usize,
+    pub height: usize,
+    /// How many seconds `/goto` waits between arriving in a room and taking the next step.
+    pub walk_delay: f64,
+}
+
+impl Default for MapConfig {
+    fn default() -> MapConfig {
+        MapConfig { pane: true, width: 31, height: 13, walk_delay: 0.5 }
+    }
+}
+
+/// The status line's settings, from the `[status]` table.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields, default)]
+pub struct StatusConfig {
+    /// Whether to show a status line at all.
+    pub show: bool,
+    /// What goes on it, in order.  `{world}`, `{state}`, `{log}`, `{scroll}` and `{time}` are
+    /// replaced with what they say, and `$name` with variables; fields that come out empty are
+    /// left out.
+    pub fields: Vec<String>,
+    /// How `{time}` looks, as a chrono format string.
+    pub time_format: String,
+}
+
+impl Default for StatusConfig {
+    fn default() -> StatusConfig {
+        StatusConfig {
+            show: true,
+            fields: ["{world}", "{state}", "{log}", "{scroll}", "{time}"].iter().map(|f| f.to_string()).collect(),
+            time_format: "%H:%M".to_string(),
+        }
+    }
+}
+
+/// The secrets file: `[worlds.NAME]` tables holding just a `password`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SecretsFile {
+    #[serde(default)]
+    worlds: BTreeMap<String, Secrets>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Secrets {
+    password: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    worlds: BTreeMap<String, World>,
+    #[serde(default)]
+    logging: LoggingConfig,
+    #[serde(default)]
+    input: InputConfig,
+    #[serde(default)]
+    map: MapConfig,
+    #[serde(default)]
+    status: StatusConfig,
+    #[serde(default)]
+    triggers: Vec<TriggerSpec>,
+    #[serde(default)]
+    gags: Vec<GagSpec>,
+    #[serde(default)]
+    subs: Vec<SubSpec>,
+    #[serde(default)]
+    highlights: Vec<HighlightSpec>,
+    #[serde(default)]
+    aliases: Vec<AliasSpec>,
+    #[serde(default)]
+    scripts: Vec<PathBuf>,
+    #[serde(default)]
+    gauges: Vec<GaugeSpec>,
+}
+
+/// Everything read from the config file.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub worlds: BTreeMap<String, World>,
+    pub logging: LoggingConfig,
+    pub input: InputConfig,
+    pub map: MapConfig,
+    pub status: StatusConfig,
+    /// Triggers, gags, substitutions and highlights for every world.
+    pub triggers: Vec<TriggerSpec>,
+    pub gags: Vec<GagSpec>,
+    pub subs: Vec<SubSpec>,
+    pub highlights: Vec<HighlightSpec>,
+    pub aliases: Vec<AliasSpec>,
+    /// Scripts to load whatever world we're connected to.
+    pub scripts: Vec<PathBuf>,
+    /// Gauges to show whatever world we're connected to.
+    pub gauges: Vec<GaugeSpec>,
+    /// Where this came from, if it came from a file.
+    pub path: Option<PathBuf>,
+}
+
+impl Config {
+    /// The default location of the config file: `mint/config.toml` under `$XDG_CONFIG_HOME`, or
+    /// under `~/.config` if that isn't set.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("mint").join("config.toml"))
+    }
+
+    /// Load the config file at `path`.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        let mut config = Config::parse(&text)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Load the config file from its default location.  It's fine for there not to be one.
+    pub fn load_default() -> Result<Config, String> {
+        match Config::default_path() {
+            Some(ref path) if path.exists() => Config::load(path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let file: ConfigFile = toml::from_str(text).map_err(|e| e.message().to_string())?;
+
+        let mut worlds = file.worlds;
+        for (name, world) in worlds.iter_mut() {
+            world.validate().map_err(|e| format!("world '{}': {}", name, e))?;
+            world.name = name.clone();
+        }
+
+        for trigger in &file.triggers {
+            Trigger::compile(trigger)?;
+        }
+        Filters::compile(&file.gags, &file.subs)?;
+        Highlights::compile(&file.highlights)?;
+        Aliases::compile(&file.aliases)?;
+        Gauges::compile(&file.gauges)?;
+        if !file.map.walk_delay.is_finite() || file.map.walk_delay < 0.0 {
+            return Err(format!("map.walk_delay can't be {}", file.map.walk_delay));
+        }
+
+        Ok(Config {
+            worlds,
+            logging: file.logging,
+            input: file.input,
+            map: file.map,
+            status: file.status,
+            triggers: file.triggers,
+            gags: file.gags,
+            subs: file.subs,
+            highlights: file.highlights,
+            aliases: file.aliases,
+            scripts: file.scripts,
+            gauges: file.gauges,
+            path: None,
+        })
+    }
+
+    /// Paths in the config file are relative to the directory it's in.
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        match self.path.as_ref().and_then(|config| config.parent()) {
+            Some(dir) => dir.join(path),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Where we keep things that aren't config: `mint` under `$XDG_DATA_HOME`, or under
+    /// `~/.local/share` if that isn't set.
+    pub fn data_dir() -> Option<PathBuf> {
+        let base = match env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".local").join("share"),
+        };
+        Some(base.join("mint"))
+    }
+
+    /// Where session logs go: the `dir` from `[logging]`, or else `logs` in the data directory.
+    pub fn log_dir(&self) -> Option<PathBuf> {
+        if let Some(ref dir) = self.logging.dir {
+            return Some(dir.clone());
+        }
+        Some(Config::data_dir()?.join("logs"))
+    }
+
+    /// Where saved variables are kept: `variables.toml` in the data directory.
+    pub fn variables_path() -> Option<PathBuf> {
+        Some(Config::data_dir()?.join("variables.toml"))
+    }
+
+    /// Where maps are kept: `maps` in the data directory.
+    pub fn maps_dir() -> Option<PathBuf> {
+        Some(Config::data_dir()?.join("maps"))
+    }
+
+    /// Where the secrets file lives: `secrets.toml` next to the config file.
+    pub fn secrets_path(&self) -> Option<PathBuf> {
+        match self.path {
+            Some(ref path) => Some(path.with_file_name("secrets.toml")),
+            None => Some(Config::default_path()?.with_file_name("secrets.toml")),
+        }
+    }
+
+    /// Find the password for logging in to `world`.  Returns Ok(None) if there isn't one.  The
+    /// secrets file is refused if anyone but its owner can read or write it.
+    pub fn password_for(&self, world: &World) -> Result<Option<String>, String> {
+        let var = world.login.as_ref().and_then(|login| login.password_env.as_ref());
+        if let Some(var) = var {
+            if let Ok(password) = env::var(var) {
+                return Ok(Some(password));
+            }
+        }
+
+        let path = match self.secrets_path() {
+            Some(ref path) if path.exists() => path.clone(),
+            _ => return Ok(None),
+        };
+
+        let mode = fs::metadata(&path)
+            .map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?
+            .permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(format!("{} can be read by other users; run 'chmod 600' on it",
+                               path.display()));
+        }
+
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        let secrets: SecretsFile = toml::from_str(&text)
+            .map_err(|e| format!("{}: {}", path.display(), e.message()))?;
+        Ok(secrets.worlds.get(&world.name).map(|s| s.password.clone()))
+    }
+
+    /// Work out the address for `target`, which is either the name of a world or an address the
+    /// TcpConnectionManager understands.  A `tls://` prefix on a world name forces TLS.
+    pub fn address_for(&self, target: &str) -> Result<String, String> {
+        let (name, tls) = match target.strip_prefix("tls://") {
+            Some(rest) => (rest, true),
+            None => (target, false),
+        };
+
+        match self.worlds.get(name) {
+            Some(world) if tls => {
+                let mut world = world.clone();
+                world.transport = Transport::Tls;
+                Ok(world.address())
+            },
+            Some(world) => Ok(world.address()),
+            None if name.contains(':') => Ok(target.to_string()),
+            None => Err(format!("No world named '{}'", name)),
+        }
+    }
+}
+
+#[test]
+fn parses_worlds() {
+    let config = Config::parse(r#"
+        [worlds.mume]
+        host = "mume.org"
+        port = 4242
+        transport = "tls"
+        character = "Bilbo"
+
+        [worlds.local]
+        host = "::1"
+        port = 4000
+        settings = { colour = true }
+    "#).unwrap();
+
+    assert_eq!(config.worlds["mume"].character, Some("Bilbo".to_string()));
+    assert_eq!(config.address_for("mume"), Ok("tls://mume.org:4242".to_string()));
+    assert_eq!(config.address_for("local"), Ok("[::1]:4000".to_string()));
+    assert_eq!(config.address_for("tls://local"), Ok("tls://[::1]:4000".to_string()));
+    let bracketed = Config::parse("[worlds.a]\nhost = \"[::1]\"\nport = 4000").unwrap();
+    assert_eq!(bracketed.address_for("a"), Ok("[::1]:4000".to_string()));
+    assert_eq!(config.address_for("example.org:23"), Ok("example.org:23".to_string()));
+    assert!(config.address_for("nowhere").is_err());
+
+    assert!(Config::parse("[worlds.a]\nhost = \"a\"\nport = 0").is_err());
+    assert!(Config::parse("[worlds.a]\nhost = \"a\"\nport = 1\ntransport = \"udp\"").is_err());
+    assert!(Config::parse("[worlds.a]\nhots = \"a\"\nport = 1").is_err());
+    assert!(Config::parse("[worlds.a]\nhost = \"a\"\nport = 1\n\
+                           login.steps = [{ expect = \"name\", send = \"$character\" }]").is_err());
+}
@@ -0,0 +1,369 @@
+
+use crate::meta::{Event, EventSource, EventManager, ReadinessPager, Recovery, SourceID, Stopper};
+use crate::meta::Listener as MetaListener;
+
+use std::thread::{self, JoinHandle};
+use std::sync::mpsc;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+pub mod poll;
+
+/// A notice sent by a child thread: 'data is ready', 'error', or (from the thread watching over
+/// it) 'the listener's thread is gone.'
+enum StateNotice {
+    Ready,
+    Error(String),
+    Returned,
+    Panicked,
+}
+
+/// Identifies which listener thread a StateNotice came from.  `generation` is bumped every time a
+/// listener is restarted, so that late notices from a dead thread can't be mistaken for notices
+/// from its replacement.
+#[derive(Clone, Copy)]
+struct Tag {
+    source: usize,
+    listener: usize,
+    generation: usize,
+}
+
+/// Listener for readiness/error notices sent out by child threads.  The type parameter `I`
+/// indicates what type of unique ID to use for disambiguating the events.
+struct Listener<I>
+    where I: Sync + Send + Copy {
+    rx: mpsc::Receiver<(I, StateNotice)>,
+    tx: mpsc::Sender<(I, StateNotice)>,
+}
+
+impl<I> Listener<I>
+    where I: Sync + Send + Copy {
+    fn new() -> Listener<I> {
+        let (tx, rx) = mpsc::channel::<(I, StateNotice)>();
+        Listener { 
+            rx, tx
+        }
+    }
+
+    fn clone_tx(&self, tag: I) -> Pager<I> {
+        Pager {
+            tx: self.tx.clone(),
+            tag,
+        }
+    }
+
+    fn recv(&self) -> (I, StateNotice) {
+        self.rx.recv().expect("Couldn't receive any more readiness signals")
+    }
+
+    fn try_recv(&self) -> Option<(I, StateNotice)> {
+        self.rx.try_recv().ok()
+    }
+}
+
+/// Implementation of ReadinessPager for the ThreadedManager.
+struct Pager<I>
+    where I: Sync + Send + Copy {
+    tx: mpsc::Sender<(I, StateNotice)>,
+    tag: I,
+}
+
+impl<I> Pager<I>
+    where I: Sync + Send + Copy {
+    fn send(&mut self, notice: StateNotice) {
+        self.tx.send((self.tag, notice)).expect("Error send()ing notice to EventManager");
+    }
+}
+
+impl<I> ReadinessPager for Pager<I>
+    where I: Sync + Send + Copy {
+    fn ok(&mut self) {
+        self.send(StateNotice::Ready);
+    }
+
+    fn err(&mut self, why: String) {
+        self.send(StateNotice::Error(why));
+    }
+}
+
+/// Bookkeeping for a single listener thread.
+struct ListenerState {
+    name: String,
+    generation: usize,
+    // Set to false once we've heard that the listener failed, so that any further notices from
+    // the same thread (e.g. it calls err() and *then* returns) don't get reported twice.
+    alive: bool,
+    stopper: Option<Box<dyn Stopper>>,
+    // The thread watching over the listener thread.  Joining it means the listener is gone too.
+    police: Option<JoinHandle<()>>,
+}
+
+/// Bookkeeping for an EventSource and the listeners it asked us to run.
+struct SourceState {
+    src: Rc<RefCell<dyn EventSource>>,
+    listeners: Vec<ListenerState>,
+}
+
+/// System to manage threads listening for data, process the data in an orderly fashion and return
+/// Events to the caller.
+pub struct ThreadedManager {
+    endpoint: Listener<Tag>,
+    // SourceIDs are indexes into this Vec.  Stopped sources leave a None behind rather than being
+    // removed, so that IDs are never reused and stale notices from their threads can be ignored.
+    sources: Vec<Option<SourceState>>,
+    // Has a fatal error occurred?  (If so, we want to refuse to do anything.)
+    poisoned: bool,
+    // Any time we receive more than one event, we 'cache' the events so that we can return one at
+    // a time to the caller.  Hopefully it's fast about consuming them.
+    events_waiting: VecDeque<Event>,
+}
+
+impl ThreadedManager {
+    pub fn new() -> ThreadedManager {
+        ThreadedManager {
+            endpoint: Listener::new(),
+            sources: vec![],
+            poisoned: false,
+            events_waiting: VecDeque::new(),
+        }
+    }
+
+    /// Spawn a thread running `listener`, plus a second thread that watches for it to exit, and
+    /// return the bookkeeping for both.
+    fn spawn_listener(&self, mut listener: Box<dyn MetaListener>, tag: Tag) -> ListenerState {
+        let name = listener.name();
+        let stopper = listener.stopper();
+
+        let citizen_pager = self.endpoint.clone_tx(tag);
+        let citizen = thread::spawn(move || {
+            listener.run(Box::new(citizen_pager));
+        });
+
+        // Check for a badly behaved thread dying in the case that it doesn't actually call err()
+        // on its pager.
+        let mut police_pager = self.endpoint.clone_tx(tag);
+        let police = thread::spawn(move || {
+            match citizen.join() {
+                Ok(_) => police_pager.send(StateNotice::Returned),
+                Err(_) => police_pager.send(StateNotice::Panicked),
+            }
+        });
+
+        ListenerState {
+            name,
+            generation: tag.generation,
+            alive: true,
+            stopper,
+            police: Some(police),
+        }
+    }
+
+    /// Find out what the source wants done about a failed listener, and do it.
+    fn handle_failure(&mut self, tag: Tag, why: String) {
+        let source = match self.sources[tag.source] {
+            Some(ref mut source) => source,
+            // The source was stopped; whatever its threads have to say doesn't matter anymore.
+            None => return,
+        };
+
+        let state = &mut source.listeners[tag.listener];
+        if state.generation != tag.generation || !state.alive {
+            // Old news; we already dealt with this thread.
+            return;
+        }
+        state.alive = false;
+
+        let recovery = source.src.borrow_mut().listener_failed(tag.listener, &why);
+        let what = format!("{} (source {}, listener {}) failed: {}", state.name, tag.source, tag.listener, why);
+
+        match recovery {
+            Recovery::Fatal => {
+                self.poisoned = true;
+                self.events_waiting.push_back(Event::InternalError { what, fatal: true });
+            },
+            Recovery::Abandon => {
+                self.events_waiting.push_back(Event::InternalError { what, fatal: false });
+            },
+            Recovery::Restart(listener) => {
+                let new_tag = Tag { generation: tag.generation + 1, ..tag };
+                let new_state = self.spawn_listener(listener, new_tag);
+                if let Some(ref mut source) = self.sources[tag.source] {
+                    source.listeners[tag.listener] = new_state;
+                }
+
+                self.events_waiting.push_back(Event::InternalError {
+                    what: format!("{}; restarted it", what),
+                    fatal: false,
+                });
+            },
+        }
+    }
+
+    /// Return true if any listener thread is still running (as far as we know.)
+    fn any_alive(&self) -> bool {
+        self.sources.iter().flatten().any(|s| s.listeners.iter().any(|l| l.alive))
+    }
+}
+
+impl Default for ThreadedManager {
+    fn default() -> Self {
+        ThreadedManager::new()
+    }
+}
+
+impl EventManager for ThreadedManager {
+    /// Add a source.  This takes Rc<RefCell<Box<EventSource>>> instead of consuming the value and
+    /// wrapping it internally because the caller needs to maintain a handle to the *specific*
+    /// implementation in some cases, and if the only remaining reference is a dyn EventSource-type
+    /// object, you won't be able to access anything that isn't a generic EventSource method.
+    fn start_source(&mut self, src: Rc<RefCell<dyn EventSource>>) -> SourceID {
+        // Note that len = index of last element + 1 (since indexes start at zero) and so is also
+        // the index of the next element we'll insert into any given list.
+        let new_id: SourceID = self.sources.len();
+
+        let listeners = src.borrow_mut().get_listeners();
+        let states = listeners.into_iter().enumerate().map(|(i, listener)| {
+            self.spawn_listener(listener, Tag { source: new_id, listener: i, generation: 0 })
+        }).collect();
+
+        self.sources.push(Some(SourceState {
+            src,
+            listeners: states,
+        }));
+
+        new_id
+    }
+
+    fn stop_source(&mut self, which: SourceID) -> Result<(), String> {
+        let mut source = match self.sources.get_mut(which).and_then(|s| s.take()) {
+            Some(source) => source,
+            None => return Err(format!("No running source with ID {}", which)),
+        };
+
+        // Ask everyone to stop first and only then wait, so the listeners can all wind down at
+        // the same time.
+        for listener in source.listeners.iter_mut() {
+            if let Some(ref mut stopper) = listener.stopper {
+                stopper.stop();
+            }
+        }
+
+        let mut problems = vec![];
+        for listener in source.listeners.iter_mut() {
+            // Listeners that can't be stopped are left to their own devices; joining them would
+            // just hang.  Their notices are ignored from here on since the source is gone.
+            if listener.stopper.is_none() {
+                continue;
+            }
+            if let Some(police) = listener.police.take() {
+                if police.join().is_err() {
+                    problems.push(listener.name.clone());
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Problems shutting down: {}", problems.join(", ")))
+        }
+    }
+
+    /// Return the next Event.  This will return any Events that are queued up, but if the queue is empty
+    /// it will wait for an Event to arrive.
+    fn next_event(&mut self) -> Result<Event, String> {
+        while self.events_waiting.is_empty() {
+            if self.poisoned {
+                return Err("A fatal error has already occurred".to_string());
+            } else if !self.any_alive() {
+                return Err("No threads are running; would block forever".to_string());
+            } else {
+                match self.endpoint.recv() {
+                    (tag, StateNotice::Ready) => {
+                        if let Some(ref source) = self.sources[tag.source] {
+                            let results = source.src.borrow_mut().process();
+                            self.events_waiting.extend(results);
+                        }
+                    },
+                    (tag, StateNotice::Error(bad_things)) => {
+                        self.handle_failure(tag, bad_things);
+                    },
+                    (tag, StateNotice::Returned) => {
+                        self.handle_failure(tag, "its thread returned, but should run forever".to_string());
+                    },
+                    (tag, StateNotice::Panicked) => {
+                        self.handle_failure(tag, "its thread panicked".to_string());
+                    },
+                }
+            }
+        }
+
+        // The loop above should only exit if self.events_waiting.len() is 1 or more, so
+        // pop_front() should be guaranteed to function and unwrap() should be safe.
+        Ok(self.events_waiting.pop_front().unwrap())
+    }
+}
+
+#[test]
+fn listener_failure_policies() {
+    // A source with one listener that dies straight away (non-fatal) and one that reports an
+    // error (fatal.)
+    struct Quitter;
+    impl MetaListener for Quitter {
+        fn name(&self) -> String { "quitter".to_string() }
+        fn run(&mut self, _flag: Box<dyn ReadinessPager>) { }
+    }
+
+    // The whiner waits until the quitter's failure has been seen, since nothing gets through
+    // after a fatal error.
+    struct Whiner(mpsc::Receiver<()>);
+    impl MetaListener for Whiner {
+        fn name(&self) -> String { "whiner".to_string() }
+        fn run(&mut self, mut flag: Box<dyn ReadinessPager>) {
+            let _ = self.0.recv();
+            flag.err("oh no".to_string());
+        }
+    }
+
+    struct Src(Option<mpsc::Receiver<()>>);
+    impl EventSource for Src {
+        fn process(&mut self) -> Vec<Event> { vec![] }
+        fn get_listeners(&mut self) -> Vec<Box<dyn MetaListener>> {
+            vec![Box::new(Quitter), Box::new(Whiner(self.0.take().unwrap()))]
+        }
+        fn listener_failed(&mut self, which: usize, _why: &str) -> Recovery {
+            if which == 0 { Recovery::Abandon } else { Recovery::Fatal }
+        }
+    }
+
+    let (go, wait) = mpsc::channel();
+    let mut manager = ThreadedManager::new();
+    manager.start_source(Rc::new(RefCell::new(Src(Some(wait)))));
+
+    match manager.next_event() {
+        Ok(Event::InternalError { what, fatal: false }) => assert!(what.starts_with("quitter")),
+        other => panic!("unexpected {:?}", other),
+    }
+    go.send(()).unwrap();
+    match manager.next_event() {
+        Ok(Event::InternalError { what, fatal: true }) => assert!(what.contains("oh no")),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(manager.next_event().is_err());
+}
+
+#[test]
+fn stop_source_joins_listeners() {
+    use crate::timer::TimerManager;
+
+    let timers = Rc::new(RefCell::new(TimerManager::new()));
+    let mut manager = ThreadedManager::new();
+    let id = manager.start_source(timers.clone());
+    timers.borrow_mut().schedule_repeating(std::time::Duration::from_millis(1));
+
+    assert!(manager.stop_source(id).is_ok());
+    assert!(manager.stop_source(id).is_err());
+    // The timer thread is gone, and its parting notices shouldn't show up as errors.
+    assert!(manager.next_event().is_err());
+}
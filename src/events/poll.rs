@@ -0,0 +1,296 @@
+
+use crate::meta::{Event, EventSource, EventManager, Recovery, SourceID};
+use crate::meta::Listener as MetaListener;
+use super::{Listener, Pager, StateNotice, Tag};
+
+use mio::{Events, Poll, Token};
+use std::collections::{BTreeSet, VecDeque};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::time::Instant;
+
+/// A listener being driven by the PollManager, along with what we need to keep track of it.
+struct PolledListener {
+    tag: Tag,
+    name: String,
+    listener: Box<dyn MetaListener>,
+    pager: Pager<Tag>,
+}
+
+impl PolledListener {
+    fn deadline(&mut self) -> Option<Instant> {
+        self.listener.pollable().and_then(|p| p.deadline())
+    }
+}
+
+/// EventManager that runs everything on the main thread, multiplexing all of its listeners on a
+/// single mio Poll instead of giving each of them a thread.  Only listeners that implement
+/// Pollable can be run this way; any others are treated as having failed when they're started.
+///
+/// The listeners still hand their data over to their EventSources through whatever channels they
+/// use normally, and still page us through a ReadinessPager, so EventSources don't need to know
+/// which kind of EventManager they've been given to.
+pub struct PollManager {
+    poll: Poll,
+    // The pagers we give out all report back here.  It's the same setup the ThreadedManager uses,
+    // just without any threads on the other end.
+    endpoint: Listener<Tag>,
+    // SourceIDs are indexes into this Vec, as with the ThreadedManager.
+    sources: Vec<Option<Rc<RefCell<dyn EventSource>>>>,
+    // mio Tokens are indexes into this Vec.  Like SourceIDs, they're never reused.
+    listeners: Vec<Option<PolledListener>>,
+    // Has a fatal error occurred?  (If so, we want to refuse to do anything.)
+    poisoned: bool,
+    events_waiting: VecDeque<Event>,
+}
+
+impl PollManager {
+    pub fn new() -> Result<PollManager, String> {
+        Ok(PollManager {
+            poll: Poll::new().map_err(|e| format!("Couldn't create Poll: {}", e))?,
+            endpoint: Listener::new(),
+            sources: vec![],
+            listeners: vec![],
+            poisoned: false,
+            events_waiting: VecDeque::new(),
+        })
+    }
+
+    /// Register a listener with our Poll and start keeping track of it.
+    fn adopt(&mut self, mut listener: Box<dyn MetaListener>, tag: Tag) {
+        let name = listener.name();
+        let token = Token(self.listeners.len());
+
+        let registered = match listener.pollable() {
+            Some(pollable) => pollable.register(&self.poll, token),
+            None => Err("it can only be run on a thread of its own".to_string()),
+        };
+
+        match registered {
+            Ok(()) => {
+                let pager = self.endpoint.clone_tx(tag);
+                self.listeners.push(Some(PolledListener { tag, name, listener, pager }));
+            },
+            Err(why) => {
+                // Keep the Token numbering in step with the Vec.
+                self.listeners.push(None);
+                self.handle_failure(tag, name, why);
+            },
+        }
+    }
+
+    /// Stop keeping track of the listener under `token`, if there is one.
+    fn abandon(&mut self, token: usize) -> Option<PolledListener> {
+        let mut polled = self.listeners.get_mut(token).and_then(|l| l.take())?;
+        if let Some(pollable) = polled.listener.pollable() {
+            pollable.deregister(&self.poll);
+        }
+        Some(polled)
+    }
+
+    /// Find out what the source wants done about a failed listener, and do it.  The listener
+    /// must already have been abandon()ed.
+    fn handle_failure(&mut self, tag: Tag, name: String, why: String) {
+        let source = match self.sources.get(tag.source) {
+            Some(Some(source)) => source.clone(),
+            // The source was stopped, or hasn't finished starting up yet.
+            _ => return,
+        };
+
+        let recovery = source.borrow_mut().listener_failed(tag.listener, &why);
+        let what = format!("{} (source {}, listener {}) failed: {}", name, tag.source, tag.listener, why);
+
+        match recovery {
+            Recovery::Fatal => {
+                self.poisoned = true;
+                self.events_waiting.push_back(Event::InternalError { what, fatal: true });
+            },
+            Recovery::Abandon => {
+                self.events_waiting.push_back(Event::InternalError { what, fatal: false });
+            },
+            Recovery::Restart(listener) => {
+                self.events_waiting.push_back(Event::InternalError {
+                    what: format!("{}; restarted it", what),
+                    fatal: false,
+                });
+                self.adopt(listener, Tag { generation: tag.generation + 1, ..tag });
+            },
+        }
+    }
+
+    /// Return the token of the listener with this tag, if it's still around.
+    fn find(&self, tag: Tag) -> Option<usize> {
+        self.listeners.iter().position(|l| match l {
+            Some(l) => l.tag.source == tag.source && l.tag.listener == tag.listener
+                && l.tag.generation == tag.generation,
+            None => false,
+        })
+    }
+
+    /// Deal with every notice the listeners have sent since we last checked.
+    fn drain_notices(&mut self) {
+        while let Some((tag, notice)) = self.endpoint.try_recv() {
+            match notice {
+                StateNotice::Ready => {
+                    if let Some(Some(source)) = self.sources.get(tag.source) {
+                        let results = source.borrow_mut().process();
+                        self.events_waiting.extend(results);
+                    }
+                },
+                StateNotice::Error(why) => {
+                    if let Some(polled) = self.find(tag).and_then(|token| self.abandon(token)) {
+                        self.handle_failure(tag, polled.name, why);
+                    }
+                },
+                // Nobody's running on a thread, so nobody can return or panic behind our back.
+                StateNotice::Returned | StateNotice::Panicked => { },
+            }
+        }
+    }
+
+    /// Wait until something is ready (or a deadline passes) and give the listeners involved a
+    /// chance to deal with it.
+    fn poll_listeners(&mut self) -> Result<(), String> {
+        let timeout = self.listeners.iter_mut().flatten()
+            .filter_map(|l| l.deadline())
+            .min()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
+        let mut events = Events::with_capacity(128);
+        match self.poll.poll(&mut events, timeout) {
+            Ok(_) => { },
+            // Signals (like SIGWINCH) interrupt poll(); that's not a problem.
+            Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => { },
+            Err(e) => return Err(format!("Couldn't poll: {}", e)),
+        }
+
+        let now = Instant::now();
+        let mut ready: BTreeSet<usize> = events.iter().map(|e| e.token().0).collect();
+        for (token, l) in self.listeners.iter_mut().enumerate() {
+            if let Some(l) = l {
+                if l.deadline().is_some_and(|d| d <= now) {
+                    ready.insert(token);
+                }
+            }
+        }
+
+        let mut finished = vec![];
+        for token in ready {
+            if let Some(Some(polled)) = self.listeners.get_mut(token) {
+                let keep_going = match polled.listener.pollable() {
+                    Some(pollable) => pollable.poll_once(&mut polled.pager),
+                    None => false,
+                };
+                if !keep_going {
+                    finished.push(token);
+                }
+            }
+        }
+
+        // Notices go first, since a listener that gave up probably paged us an error explaining
+        // why before it did.
+        self.drain_notices();
+
+        for token in finished {
+            if let Some(polled) = self.abandon(token) {
+                self.handle_failure(polled.tag, polled.name,
+                                    "it finished, but should run forever".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl EventManager for PollManager {
+    fn start_source(&mut self, src: Rc<RefCell<dyn EventSource>>) -> SourceID {
+        let new_id: SourceID = self.sources.len();
+        self.sources.push(Some(src.clone()));
+
+        let listeners = src.borrow_mut().get_listeners();
+        for (i, listener) in listeners.into_iter().enumerate() {
+            self.adopt(listener, Tag { source: new_id, listener: i, generation: 0 });
+        }
+
+        new_id
+    }
+
+    fn stop_source(&mut self, which: SourceID) -> Result<(), String> {
+        match self.sources.get_mut(which).and_then(|s| s.take()) {
+            Some(_) => { },
+            None => return Err(format!("No running source with ID {}", which)),
+        }
+
+        // Nothing's running behind our backs, so there's nothing to ask to stop; we can just
+        // let go of the listeners.
+        let tokens: Vec<usize> = self.listeners.iter().enumerate()
+            .filter(|(_, l)| l.as_ref().is_some_and(|l| l.tag.source == which))
+            .map(|(token, _)| token)
+            .collect();
+        for token in tokens {
+            self.abandon(token);
+        }
+
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> Result<Event, String> {
+        while self.events_waiting.is_empty() {
+            if self.poisoned {
+                return Err("A fatal error has already occurred".to_string());
+            } else if self.listeners.iter().all(|l| l.is_none()) {
+                return Err("No listeners are running; would block forever".to_string());
+            } else {
+                self.poll_listeners()?;
+            }
+        }
+
+        Ok(self.events_waiting.pop_front().unwrap())
+    }
+}
+
+#[test]
+fn polls_timers_and_sockets() {
+    use crate::net::{ConnectionInterface, tcp::TcpConnectionManager};
+    use crate::timer::TimerManager;
+    use std::io::Write;
+    use std::time::Duration;
+
+    let mut manager = PollManager::new().unwrap();
+
+    let timers = Rc::new(RefCell::new(TimerManager::new()));
+    let timer_source = manager.start_source(timers.clone());
+    let tcp = Rc::new(RefCell::new(TcpConnectionManager::new()));
+    manager.start_source(tcp.clone());
+
+    let server = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap().to_string();
+    // The connection isn't actually made until the manager gets around to polling, so the
+    // server end has to be on another thread.
+    let server_thread = std::thread::spawn(move || {
+        let (mut stream, _) = server.accept().unwrap();
+        stream.write_all(b"hello\n").unwrap();
+        stream
+    });
+    let cid = tcp.borrow_mut().start_connection(address).unwrap();
+
+    let timer = timers.borrow_mut().schedule(Duration::from_millis(10));
+
+    let mut got_timer = false;
+    let mut got_text = false;
+    while !(got_timer && got_text) {
+        match manager.next_event().unwrap() {
+            Event::Timer { id } => { assert_eq!(id, timer); got_timer = true; },
+            Event::ConnectionStart { which } => assert_eq!(which, cid),
+            Event::ServerText { line, which } => {
+                assert_eq!((line.as_str(), which), ("hello", cid));
+                got_text = true;
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    assert!(manager.stop_source(timer_source).is_ok());
+    assert!(manager.stop_source(timer_source).is_err());
+    drop(server_thread.join());
+}
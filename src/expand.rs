@@ -0,0 +1,113 @@
+//! Taking a line of input apart into the commands it stands for: several commands separated by
+//! `;`, a command repeated with `#5 kill rat`, or a speedwalk like `3n2e`.
+
+/// The most times `#N` can repeat a command, so a typo can't flood the server.
+pub const MAX_REPEAT: usize = 100;
+
+/// Split `line` into separate commands wherever `separator` appears, unless it has a backslash
+/// in front of it.  An empty separator means the line is never split.
+pub fn split_commands(line: &str, separator: &str) -> Vec<String> {
+    if separator.is_empty() {
+        return vec![line.to_string()];
+    }
+
+    let mut commands = vec![];
+    let mut current = String::new();
+    let mut rest = line;
+
+    while let Some(i) = rest.find(separator) {
+        if rest[..i].ends_with('\\') {
+            current.push_str(&rest[..i - 1]);
+            current.push_str(separator);
+        } else {
+            current.push_str(&rest[..i]);
+            commands.push(std::mem::take(&mut current));
+        }
+        rest = &rest[i + separator.len()..];
+    }
+
+    current.push_str(rest);
+    commands.push(current);
+    commands
+}
+
+/// Take a `#N` repeat count off the front of `line`, if there is one.  Returns the count (1 if
+/// there isn't one) and the rest of the line.
+pub fn repeat_count(line: &str) -> Result<(usize, &str), String> {
+    let rest = match line.strip_prefix('#') {
+        Some(rest) => rest,
+        None => return Ok((1, line)),
+    };
+
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    if digits == 0 || !rest[digits..].starts_with(char::is_whitespace) {
+        // Something like `#define`, which is probably meant for the server.
+        return Ok((1, line));
+    }
+
+    match rest[..digits].parse() {
+        Ok(count) if count <= MAX_REPEAT => Ok((count, rest[digits..].trim_start())),
+        _ => Err(format!("Can't repeat a command more than {} times", MAX_REPEAT)),
+    }
+}
+
+/// Expand a speedwalk: a run of directions (`n`, `s`, `e`, `w`, `u` and `d`), each of which can
+/// have a count in front of it, like `3n2e`.  So that words like `news` aren't taken for
+/// speedwalks, there has to be at least one count.  Returns None if `line` isn't a speedwalk.
+pub fn speedwalk(line: &str) -> Option<Vec<String>> {
+    let line = line.trim();
+    if !line.contains(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut steps = vec![];
+    let mut count = String::new();
+    for c in line.chars() {
+        match c {
+            '0'..='9' => count.push(c),
+            'n' | 's' | 'e' | 'w' | 'u' | 'd' => {
+                let n: usize = if count.is_empty() { 1 } else { count.parse().ok()? };
+                if n > MAX_REPEAT - steps.len() {
+                    return None;
+                }
+                steps.extend(std::iter::repeat_n(c.to_string(), n));
+                count.clear();
+            },
+            _ => return None,
+        }
+    }
+
+    // A count with no direction after it isn't a speedwalk.
+    if !count.is_empty() {
+        return None;
+    }
+    Some(steps)
+}
+
+#[test]
+fn expands_input() {
+    assert_eq!(split_commands("kill rat;get all from corpse; say hi\\; bye", ";"),
+               vec!["kill rat", "get all from corpse", " say hi; bye"]);
+    assert_eq!(split_commands("a;b", ""), vec!["a;b"]);
+    assert_eq!(split_commands("a&&b", "&&"), vec!["a", "b"]);
+
+    assert_eq!(repeat_count("#5 kill rat"), Ok((5, "kill rat")));
+    assert_eq!(repeat_count("#define x"), Ok((1, "#define x")));
+    assert_eq!(repeat_count("kill rat"), Ok((1, "kill rat")));
+    assert!(repeat_count("#5000 kill rat").is_err());
+    assert!(repeat_count("#99999999999999999999 kill rat").is_err());
+    assert_eq!(repeat_count(&format!("#{} kill rat", MAX_REPEAT)), Ok((MAX_REPEAT, "kill rat")));
+    assert!(repeat_count(&format!("#{} kill rat", MAX_REPEAT + 1)).is_err());
+
+    assert_eq!(speedwalk("3n2eu").unwrap(), vec!["n", "n", "n", "e", "e", "u"]);
+    assert_eq!(speedwalk("news"), None);
+    assert_eq!(speedwalk("2nx"), None);
+    assert_eq!(speedwalk("n2"), None);
+    assert_eq!(speedwalk("1n18446744073709551615n"), None);
+    assert_eq!(speedwalk("99999999999999999999n"), None);
+    // MAX_REPEAT steps in all, however they're split up, but no more.
+    assert_eq!(speedwalk(&format!("{}n", MAX_REPEAT)).map(|steps| steps.len()), Some(MAX_REPEAT));
+    assert_eq!(speedwalk(&format!("{}n1e", MAX_REPEAT - 1)).map(|steps| steps.len()), Some(MAX_REPEAT));
+    assert_eq!(speedwalk(&format!("{}n", MAX_REPEAT + 1)), None);
+    assert_eq!(speedwalk(&format!("{}ne", MAX_REPEAT)), None);
+}
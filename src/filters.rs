@@ -0,0 +1,116 @@
+//! Gags, which hide lines from the server, and substitutions, which rewrite them before they're
+//! shown.
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::ansi::StyledLine;
+use crate::triggers::expand_captures;
+
+/// A gag from a `[[gags]]` or `[[worlds.NAME.gags]]` table.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GagSpec {
+    pub pattern: String,
+    /// Still write gagged lines to the log.
+    #[serde(default)]
+    pub log: bool,
+}
+
+/// A substitution from a `[[subs]]` or `[[worlds.NAME.subs]]` table.  Every match of `pattern`
+/// is replaced with `replace`, in which `$1` and so on are the capture groups.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SubSpec {
+    pub pattern: String,
+    pub replace: String,
+}
+
+/// A set of gags and substitutions, ready to use.
+#[derive(Default)]
+pub struct Filters {
+    gags: Vec<(Regex, bool)>,
+    subs: Vec<(Regex, String)>,
+}
+
+impl Filters {
+    pub fn new() -> Filters {
+        Filters::default()
+    }
+
+    pub fn compile<'a>(gags: impl IntoIterator<Item = &'a GagSpec>,
+                       subs: impl IntoIterator<Item = &'a SubSpec>) -> Result<Filters, String> {
+        let compile = |pattern: &str, what: &str| Regex::new(pattern)
+            .map_err(|e| format!("bad {} pattern '{}': {}", what, pattern, e));
+
+        let mut filters = Filters::new();
+        for gag in gags {
+            filters.gags.push((compile(&gag.pattern, "gag")?, gag.log));
+        }
+        for sub in subs {
+            filters.subs.push((compile(&sub.pattern, "substitution")?, sub.replace.clone()));
+        }
+        Ok(filters)
+    }
+
+    /// Check whether a line (without colour codes) should be hidden.  Returns None if it
+    /// shouldn't, or whether it should still be logged if it should.
+    pub fn gag(&self, line: &str) -> Option<bool> {
+        let mut matched = None;
+        for (pattern, log) in &self.gags {
+            if pattern.is_match(line) {
+                // If any gag that matches wants the line logged, it gets logged.
+                matched = Some(matched.unwrap_or(false) || *log);
+            }
+        }
+        matched
+    }
+
+    /// Apply the substitutions to `line`, one after another.  The colours of the text they don't
+    /// touch are kept.  Returns true if anything changed.
+    pub fn substitute(&self, line: &mut StyledLine) -> bool {
+        let mut changed = false;
+
+        for (pattern, replace) in &self.subs {
+            let plain = line.plain();
+            let matches: Vec<_> = pattern.captures_iter(&plain)
+                .filter(|caps| !caps[0].is_empty())
+                .map(|caps| (caps.get(0).expect("group 0").range(), expand_captures(replace, &caps)))
+                .collect();
+
+            // Work backwards, so the earlier offsets stay right.
+            for (range, with) in matches.into_iter().rev() {
+                line.replace(range, &with);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+#[test]
+fn gags_and_substitutes() {
+    use crate::ansi::{Colour, Style};
+
+    let gag = |pattern: &str, log| GagSpec { pattern: pattern.to_string(), log };
+    let sub = |pattern: &str, replace: &str| SubSpec { pattern: pattern.to_string(), replace: replace.to_string() };
+    let filters = Filters::compile(&[gag("^\\[OOC\\]", false), gag("^\\[OOC\\] Bob", true)],
+                                   &[sub("(\\w+) hits you", "$1 HITS YOU"), sub("rat", "")]).unwrap();
+
+    assert_eq!(filters.gag("[OOC] Alice: hi"), Some(false));
+    assert_eq!(filters.gag("[OOC] Bob: hi"), Some(true));
+    assert_eq!(filters.gag("Alice says hi"), None);
+
+    let (mut line, _) = StyledLine::parse("The \x1b[31mrat\x1b[0m hits you. The rat hits you.", Style::default());
+    assert!(filters.substitute(&mut line));
+    assert_eq!(line.plain(), "The  HITS YOU. The  HITS YOU.");
+    assert_eq!(line.spans[0].text, "The ");
+    assert_eq!(line.spans[0].style, Style::default());
+
+    let (mut line, _) = StyledLine::parse("\x1b[32mThe orc hits you\x1b[0m!", Style::default());
+    filters.substitute(&mut line);
+    assert_eq!(line.plain(), "The orc HITS YOU!");
+    assert_eq!(line.spans[0].style.fg, Some(Colour::Indexed(2)));
+    assert_eq!(line.spans[1].text, "!");
+}
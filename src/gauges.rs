@@ -0,0 +1,167 @@
+//! Gauges: bars showing how full something is, like hit points, kept up to date from what the
+//! server sends over GMCP or MSDP rather than by reading prompts.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::ansi::Colour;
+
+/// Where a gauge is drawn.  Gauges at the top or bottom share a line, and gauges in the pane go
+/// one above the other beside the text, under any other panes.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Place {
+    Top,
+    /// Just above the status line.
+    #[default]
+    Bottom,
+    Pane,
+}
+
+/// A gauge from a `[[gauges]]` or `[[worlds.NAME.gauges]]` table.  `value` and `max` say where
+/// the numbers come from: a GMCP package followed by the field, like `Char.Vitals.hp`, or `MSDP.`
+/// and a variable, like `MSDP.HEALTH`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GaugeSpec {
+    pub label: String,
+    pub value: String,
+    pub max: String,
+    /// The colour of the bar, written as for Colour::from_name().
+    #[serde(default = "default_colour")]
+    pub colour: String,
+    #[serde(default)]
+    pub place: Place,
+    /// How wide the gauge is, label and all.  Gauges on a line share it out between those that
+    /// don't say, and gauges in the pane are 20 wide unless they do.
+    pub width: Option<usize>,
+}
+
+fn default_colour() -> String {
+    "green".to_string()
+}
+
+/// A gauge as it should be drawn now.  The numbers are missing until the server's sent them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub label: String,
+    pub value: Option<f64>,
+    pub max: Option<f64>,
+    pub colour: Colour,
+    pub place: Place,
+    pub width: Option<usize>,
+}
+
+/// The gauges, and the numbers they're showing.
+#[derive(Default)]
+pub struct Gauges {
+    specs: Vec<GaugeSpec>,
+    readings: Vec<Reading>,
+}
+
+impl Gauges {
+    pub fn new() -> Gauges {
+        Gauges::default()
+    }
+
+    pub fn compile(specs: &[GaugeSpec]) -> Result<Gauges, String> {
+        let readings = specs.iter()
+            .map(|spec| Ok(Reading {
+                label: spec.label.clone(),
+                value: None,
+                max: None,
+                colour: Colour::from_name(&spec.colour)
+                    .ok_or_else(|| format!("unknown colour '{}' in gauge '{}'", spec.colour, spec.label))?,
+                place: spec.place,
+                width: spec.width,
+            }))
+            .collect::<Result<_, String>>()?;
+        Ok(Gauges { specs: specs.to_vec(), readings })
+    }
+
+    pub fn readings(&self) -> &[Reading] {
+        &self.readings
+    }
+
+    /// Forget the numbers, for when they're about to start coming from somewhere else.
+    pub fn clear(&mut self) {
+        for reading in &mut self.readings {
+            reading.value = None;
+            reading.max = None;
+        }
+    }
+
+    /// Take whatever the gauges want out of a GMCP message.  Returns true if any of them changed.
+    pub fn gmcp(&mut self, package: &str, data: &str) -> bool {
+        let data = match serde_json::from_str(data) {
+            Ok(data) => data,
+            Err(_) => return false,
+        };
+        self.update(package, &data)
+    }
+
+    /// The same as gmcp(), for an MSDP variable.
+    pub fn msdp(&mut self, variable: &str, data: &str) -> bool {
+        self.gmcp(&format!("MSDP.{}", variable), data)
+    }
+
+    fn update(&mut self, package: &str, data: &Value) -> bool {
+        let mut changed = false;
+        for (spec, reading) in self.specs.iter().zip(self.readings.iter_mut()) {
+            for (path, number) in [(&spec.value, &mut reading.value), (&spec.max, &mut reading.max)] {
+                // Servers don't always send every field, so those that aren't there keep their
+                // last value.
+                if let Some(found) = lookup(package, data, path) {
+                    changed |= *number != Some(found);
+                    *number = Some(found);
+                }
+            }
+        }
+        changed
+    }
+}
+
+/// Find the number `path` refers to in `data`, if `data` is the message it refers to.  Numbers
+/// sent as strings count too, since plenty of servers send them that way.
+fn lookup(package: &str, data: &Value, path: &str) -> Option<f64> {
+    let value = if path == package {
+        data
+    } else {
+        let rest = path.strip_prefix(package)?.strip_prefix('.')?;
+        rest.split('.').try_fold(data, |value, key| value.get(key))?
+    };
+
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+#[test]
+fn gauges_follow_data() {
+    let spec = |label: &str, value: &str, max: &str| GaugeSpec {
+        label: label.to_string(),
+        value: value.to_string(),
+        max: max.to_string(),
+        colour: default_colour(),
+        place: Place::default(),
+        width: None,
+    };
+    let mut gauges = Gauges::compile(&[
+        spec("HP", "Char.Vitals.hp", "Char.Vitals.maxhp"),
+        spec("SP", "MSDP.MANA", "MSDP.MANA_MAX"),
+    ]).unwrap();
+    assert!(Gauges::compile(&[GaugeSpec { colour: "mauve".to_string(), ..spec("HP", "a", "b") }]).is_err());
+
+    assert!(gauges.gmcp("Char.Vitals", r#"{"hp": "45", "maxhp": 100}"#));
+    assert!(!gauges.gmcp("Char.Vitals", r#"{"hp": 45}"#));
+    assert!(!gauges.gmcp("Char.Vitalsx", r#"{"hp": 5}"#));
+    assert!(gauges.msdp("MANA", r#""12""#));
+    assert_eq!(gauges.readings()[0].value, Some(45.0));
+    assert_eq!(gauges.readings()[0].max, Some(100.0));
+    assert_eq!((gauges.readings()[1].value, gauges.readings()[1].max), (Some(12.0), None));
+
+    gauges.clear();
+    assert_eq!(gauges.readings()[0].value, None);
+}
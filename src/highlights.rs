@@ -0,0 +1,149 @@
+//! Highlights: regular expressions whose matches in lines from the server are recoloured, and
+//! which can also ring the bell or flag the world as wanting attention.
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::ansi::{Colour, Style, StyledLine};
+
+/// A highlight from a `[[highlights]]` or `[[worlds.NAME.highlights]]` table.  Colours are
+/// written as for Colour::from_name(); attributes that aren't set are left as the server had
+/// them.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct HighlightSpec {
+    pub pattern: String,
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub italic: bool,
+    #[serde(default)]
+    pub underline: bool,
+    #[serde(default)]
+    pub reverse: bool,
+    /// Ring the terminal bell when this matches.
+    #[serde(default)]
+    pub bell: bool,
+    /// Flag the world as having something worth looking at when this matches.
+    #[serde(default)]
+    pub flag: bool,
+}
+
+struct Highlight {
+    pattern: Regex,
+    fg: Option<Colour>,
+    bg: Option<Colour>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    reverse: bool,
+    bell: bool,
+    flag: bool,
+}
+
+impl Highlight {
+    fn compile(spec: &HighlightSpec) -> Result<Highlight, String> {
+        let pattern = Regex::new(&spec.pattern)
+            .map_err(|e| format!("bad highlight pattern '{}': {}", spec.pattern, e))?;
+        let colour = |name: &Option<String>| match name {
+            Some(name) => Colour::from_name(name).map(Some)
+                .ok_or_else(|| format!("unknown colour '{}' in highlight '{}'", name, spec.pattern)),
+            None => Ok(None),
+        };
+
+        Ok(Highlight {
+            pattern,
+            fg: colour(&spec.fg)?,
+            bg: colour(&spec.bg)?,
+            bold: spec.bold,
+            italic: spec.italic,
+            underline: spec.underline,
+            reverse: spec.reverse,
+            bell: spec.bell,
+            flag: spec.flag,
+        })
+    }
+
+    fn restyle(&self, style: Style) -> Style {
+        Style {
+            fg: self.fg.or(style.fg),
+            bg: self.bg.or(style.bg),
+            bold: self.bold || style.bold,
+            italic: self.italic || style.italic,
+            underline: self.underline || style.underline,
+            reverse: self.reverse || style.reverse,
+        }
+    }
+}
+
+/// What the highlights that matched a line want done besides recolouring it.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Alert {
+    pub bell: bool,
+    pub flag: bool,
+}
+
+/// A set of highlights, ready to use.
+#[derive(Default)]
+pub struct Highlights {
+    highlights: Vec<Highlight>,
+}
+
+impl Highlights {
+    pub fn new() -> Highlights {
+        Highlights::default()
+    }
+
+    pub fn compile<'a>(specs: impl IntoIterator<Item = &'a HighlightSpec>) -> Result<Highlights, String> {
+        let highlights = specs.into_iter().map(Highlight::compile).collect::<Result<_, _>>()?;
+        Ok(Highlights { highlights })
+    }
+
+    /// Recolour every match of every highlight in `line`, in order, so later highlights win
+    /// where they overlap.
+    pub fn apply(&self, line: &mut StyledLine) -> Alert {
+        let mut alert = Alert::default();
+        let plain = line.plain();
+
+        for highlight in &self.highlights {
+            let mut matched = false;
+            for m in highlight.pattern.find_iter(&plain).filter(|m| !m.as_str().is_empty()) {
+                line.restyle(m.range(), |style| highlight.restyle(style));
+                matched = true;
+            }
+            if matched {
+                alert.bell |= highlight.bell;
+                alert.flag |= highlight.flag;
+            }
+        }
+
+        alert
+    }
+}
+
+#[test]
+fn highlights_matches() {
+    let spec = |pattern: &str, fg: &str| HighlightSpec {
+        pattern: pattern.to_string(),
+        fg: Some(fg.to_string()),
+        ..HighlightSpec::default()
+    };
+    let mut bob = spec("Bob", "bright-yellow");
+    bob.bold = true;
+    bob.flag = true;
+    let highlights = Highlights::compile(&[spec("\\d+ gold", "#ffd700"), bob]).unwrap();
+    assert!(Highlights::compile(&[spec("x", "mauve")]).is_err());
+
+    let (mut line, _) = StyledLine::parse("\x1b[34mBob\x1b[0m gives you 12 gold.", Style::default());
+    assert_eq!(highlights.apply(&mut line), Alert { bell: false, flag: true });
+    assert_eq!(line.plain(), "Bob gives you 12 gold.");
+    assert_eq!(line.spans[0].style, Style { fg: Some(Colour::Indexed(11)), bold: true, ..Style::default() });
+    assert_eq!(line.spans[2].text, "12 gold");
+    assert_eq!(line.spans[2].style.fg, Some(Colour::Rgb(255, 215, 0)));
+
+    let (mut line, _) = StyledLine::parse("Nothing here.", Style::default());
+    assert_eq!(highlights.apply(&mut line), Alert::default());
+    assert_eq!(line.spans.len(), 1);
+}
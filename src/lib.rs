@@ -1,9 +1,26 @@
 #![deny(unused_must_use)]
 
 pub mod meta;
+pub mod aliases;
+pub mod ansi;
+pub mod client;
+pub mod config;
 pub mod events;
+pub mod expand;
+pub mod filters;
+pub mod gauges;
+pub mod highlights;
+pub mod logging;
+pub mod map;
 pub mod net;
+pub mod scripting;
+pub mod timer;
+pub mod triggers;
+#[cfg(test)]
+pub mod testing;
 pub mod ui;
+pub mod variables;
+pub mod watcher;
 
 extern crate mio;
 extern crate termion;
@@ -0,0 +1,257 @@
+//! Session logs: files recording what a connection's server sent and what we sent back.
+
+use chrono::{Local, NaiveDate};
+use serde::Deserialize;
+
+use crate::ansi::{self, Style, StyledLine};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// What closes off an HTML log.  It's taken off again when more is added to the log later.
+const HTML_FOOTER: &str = "</body></html>\n";
+
+/// What a log file looks like.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Plain text, with the colours stripped out.
+    #[default]
+    Text,
+    /// Text with the ANSI escape sequences left in, for viewing with `less -R` and the like.
+    Ansi,
+    /// An HTML page with the colours kept.
+    Html,
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Option<LogFormat> {
+        match name {
+            "text" => Some(LogFormat::Text),
+            "ansi" => Some(LogFormat::Ansi),
+            "html" => Some(LogFormat::Html),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from a file's extension, falling back to plain text.
+    pub fn for_path(path: &Path) -> LogFormat {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("html") | Some("htm") => LogFormat::Html,
+            Some("ansi") => LogFormat::Ansi,
+            _ => LogFormat::Text,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            LogFormat::Text => "txt",
+            LogFormat::Ansi => "ansi",
+            LogFormat::Html => "html",
+        }
+    }
+}
+
+/// Where a Logger writes to.
+enum Target {
+    /// Always the same file.
+    Fixed(PathBuf),
+    /// A new file in `dir` every day, named after `name` and the date.
+    Daily { dir: PathBuf, name: String },
+}
+
+/// Writes one connection's log.
+pub struct Logger {
+    target: Target,
+    format: LogFormat,
+    // The file we're writing to now, and (for daily logs) the day it's for.
+    file: Option<File>,
+    date: NaiveDate,
+    path: PathBuf,
+    // The style in effect at the end of the last line from the server, since styles carry over.
+    style: Style,
+}
+
+impl Logger {
+    /// Log to `path`, appending if it already exists.
+    pub fn to_file(path: &Path, format: LogFormat) -> Result<Logger, String> {
+        Logger::start(Target::Fixed(path.to_path_buf()), format)
+    }
+
+    /// Log to a new file in `dir` every day, named like `name-2019-01-31.txt`.
+    pub fn daily(dir: &Path, name: &str, format: LogFormat) -> Result<Logger, String> {
+        // Don't let an odd world name or address put the file somewhere strange.
+        let name: String = name.chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+            .collect();
+        Logger::start(Target::Daily { dir: dir.to_path_buf(), name }, format)
+    }
+
+    fn start(target: Target, format: LogFormat) -> Result<Logger, String> {
+        let mut logger = Logger {
+            target,
+            format,
+            file: None,
+            date: Local::now().date_naive(),
+            path: PathBuf::new(),
+            style: Style::default(),
+        };
+        // Open the file now, so that if we can't we find out straight away.
+        match logger.file().map(|_| ()) {
+            Ok(()) => Ok(logger),
+            Err(e) => Err(format!("Couldn't open log {}: {}", logger.path.display(), e)),
+        }
+    }
+
+    /// The file currently being written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Log a line from the server, ANSI escapes and all.
+    pub fn server_text(&mut self, line: &str) -> io::Result<()> {
+        let (styled, style) = StyledLine::parse(line, self.style);
+        self.style = style;
+
+        let text = match self.format {
+            LogFormat::Text => styled.plain(),
+            LogFormat::Ansi => styled.to_ansi(),
+            LogFormat::Html => styled.to_html(),
+        };
+        self.write_line(&text)
+    }
+
+    /// Log a line we sent.
+    pub fn input(&mut self, line: &str) -> io::Result<()> {
+        match self.format {
+            LogFormat::Html => {
+                let line = format!("<span style=\"font-style:italic;\">{}</span>", ansi::escape_html(line));
+                self.write_line(&line)
+            },
+            _ => self.write_line(line),
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let file = self.file()?;
+        writeln!(file, "{}", line)?;
+        file.flush()
+    }
+
+    /// Get the file to write to, starting a new one if the day has changed.
+    fn file(&mut self) -> io::Result<&mut File> {
+        let now = Local::now();
+        let rotate = match self.target {
+            Target::Daily { .. } => now.date_naive() != self.date,
+            Target::Fixed(_) => false,
+        };
+        if rotate {
+            self.finish();
+            self.date = now.date_naive();
+        }
+
+        if self.file.is_none() {
+            self.path = match self.target {
+                Target::Fixed(ref path) => path.clone(),
+                Target::Daily { ref dir, ref name } => {
+                    fs::create_dir_all(dir)?;
+                    dir.join(format!("{}-{}.{}", name, self.date.format("%Y-%m-%d"), self.format.extension()))
+                },
+            };
+
+            let mut file = OpenOptions::new().create(true).read(true).append(true).open(&self.path)?;
+            let stamp = now.format("%Y-%m-%d %H:%M:%S");
+            match self.format {
+                LogFormat::Html => {
+                    if file.metadata()?.len() == 0 {
+                        writeln!(file, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"></head>\n\
+                                        <body style=\"background-color:#000000;color:#e5e5e5;\">")?;
+                    } else {
+                        strip_footer(&mut file)?;
+                    }
+                    writeln!(file, "<p>Log started {}</p>\n<pre>", stamp)?;
+                },
+                _ => writeln!(file, "--- Log started {} ---", stamp)?,
+            }
+            self.file = Some(file);
+        }
+
+        Ok(self.file.as_mut().expect("log file"))
+    }
+
+    /// Close off the current file.
+    fn finish(&mut self) {
+        if let Some(mut file) = self.file.take() {
+            let stamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+            // If this fails there's nothing much to be done about it.
+            let _ = match self.format {
+                LogFormat::Html => write!(file, "</pre>\n<p>Log stopped {}</p>\n{}", stamp, HTML_FOOTER),
+                _ => writeln!(file, "--- Log stopped {} ---", stamp),
+            };
+        }
+    }
+}
+
+/// Take the footer off the end of an HTML log, if it's there, so that what's added to it goes
+/// inside the document.
+fn strip_footer(file: &mut File) -> io::Result<()> {
+    let (len, footer) = (file.metadata()?.len(), HTML_FOOTER.len() as u64);
+    if len < footer {
+        return Ok(());
+    }
+    let mut tail = vec![0; HTML_FOOTER.len()];
+    file.seek(SeekFrom::Start(len - footer))?;
+    file.read_exact(&mut tail)?;
+    if tail == HTML_FOOTER.as_bytes() {
+        file.set_len(len - footer)?;
+    }
+    Ok(())
+}
+
+impl Drop for Logger {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[test]
+fn writes_formats() {
+    let dir = std::env::temp_dir().join(format!("mint-log-test-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut logger = Logger::daily(&dir, "some/world", LogFormat::Html).unwrap();
+    let path = logger.path().to_path_buf();
+    assert!(path.file_name().unwrap().to_str().unwrap().starts_with("some_world-"));
+    logger.server_text("\x1b[31mred <tag>").unwrap();
+    logger.server_text("still red\x1b[0m").unwrap();
+    logger.input("kill rat").unwrap();
+    drop(logger);
+
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(text.contains("<span style=\"color:#cd0000;\">red &lt;tag&gt;</span>\n"));
+    assert!(text.contains("<span style=\"color:#cd0000;\">still red</span>\n"));
+    assert!(text.contains("kill rat</span>\n</pre>"));
+    assert!(text.ends_with("</p>\n</body></html>\n"));
+
+    // Starting a new day's file closes off the old one; here they're the same file, so it's
+    // carried on with instead, and still ends up with just the one footer.
+    let mut logger = Logger::daily(&dir, "some/world", LogFormat::Html).unwrap();
+    logger.date = logger.date.pred_opt().unwrap();
+    logger.server_text("tomorrow").unwrap();
+    assert_eq!(logger.path(), path);
+    drop(logger);
+    let text = fs::read_to_string(&path).unwrap();
+    assert_eq!(text.matches("Log started").count(), 3);
+    assert_eq!(text.matches("Log stopped").count(), 3);
+    assert_eq!(text.matches("</body></html>").count(), 1);
+    assert!(text.ends_with("</p>\n</body></html>\n"));
+
+    let plain = dir.join("plain.txt");
+    let mut logger = Logger::to_file(&plain, LogFormat::for_path(&plain)).unwrap();
+    logger.server_text("\x1b[1mbold\x1b[0m text").unwrap();
+    drop(logger);
+    assert!(fs::read_to_string(&plain).unwrap().contains("\nbold text\n"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,64 +1,153 @@
-
 use mint;
 use mint::meta::*;
-use mint::events::ThreadedManager;
+use mint::client::Client;
+use mint::config::Config;
+use mint::logging::{LogFormat, Logger};
+use mint::events::{ThreadedManager, poll::PollManager};
 
-use mint::net::{ConnectionInterface, tcp::TcpConnectionManager};
-use mint::ui::{UserInterface, term::TermUiManager};
+use mint::net::ConnectionInterface;
+use mint::net::replay::ReplayConnectionManager;
+use mint::net::tcp::TcpConnectionManager;
+use mint::timer::TimerManager;
+use mint::watcher::FileWatcher;
+use mint::ui::UserInterface;
+use mint::ui::stdio::StdioUiManager;
+use mint::ui::term::TermUiManager;
 
 use std::env;
+use std::process;
 use std::{cell::RefCell, rc::Rc};
 
+mod cli;
+use cli::{Action, EventsKind};
+
 fn wrap<T>(x: T) -> Rc<RefCell<T>> {
     Rc::new(RefCell::new(x))
 }
 
+/// Start a ConnectionInterface on the event manager.
+fn start_conns<T>(manager: &mut dyn EventManager, conns: T) -> Rc<RefCell<dyn ConnectionInterface>>
+        where T: EventSource + ConnectionInterface + 'static {
+    let conns = wrap(conns);
+    manager.start_source(conns.clone());
+    conns
+}
+
+/// Start a UI on the event manager and hand it back as a UserInterface.
+fn start_ui<T>(manager: &mut dyn EventManager, ui: T) -> Rc<RefCell<dyn UserInterface>>
+        where T: EventSource + UserInterface + 'static {
+    let ui = wrap(ui);
+    manager.start_source(ui.clone());
+    ui
+}
+
+/// Print a message and exit.  Exit status 2 means we were called wrong; 1 means something went
+/// wrong while running.
+fn die(status: i32, why: &str) -> ! {
+    eprintln!("mint: {}", why);
+    if status == 2 {
+        eprintln!("Try 'mint --help' for more information.");
+    }
+    process::exit(status);
+}
+
 fn main() {
-    let address: String;
-    if let Some(arg1) = env::args().nth(1) {
-        address = arg1;
+    let opts = match cli::parse(env::args().skip(1)) {
+        Ok(Action::Run(opts)) => opts,
+        Ok(Action::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        },
+        Ok(Action::Version) => {
+            println!("mint {}", env!("CARGO_PKG_VERSION"));
+            return;
+        },
+        Err(why) => die(2, &why),
+    };
+
+    let target = opts.target().unwrap_or_else(|why| die(2, &why));
+
+    // A broken config file only stops us here if we need it to find the world we were asked
+    // for; otherwise we carry on without it and say what's wrong once the UI is up.
+    let config = match opts.config {
+        Some(ref path) => Config::load(path),
+        None => Config::load_default(),
+    };
+    let (config, config_error) = match config {
+        Ok(config) => (config, None),
+        Err(why) => (Config::default(), Some(why)),
+    };
+    if let Some(ref target) = target {
+        if let Err(why) = config.address_for(target) {
+            die(2, config_error.as_ref().unwrap_or(&why));
+        }
+    }
+
+    // Open the log before touching the terminal, so that if it fails the message is readable.
+    let log = opts.log.as_ref().map(|path| {
+        Logger::to_file(path, LogFormat::for_path(path)).unwrap_or_else(|why| die(1, &why))
+    });
+
+    let mut manager: Box<dyn EventManager> = match opts.events {
+        EventsKind::Poll => Box::new(PollManager::new().unwrap_or_else(|why| die(1, &why))),
+        EventsKind::Threaded => Box::new(ThreadedManager::new()),
+    };
+
+    let conns = match opts.replay {
+        Some(_) => start_conns(manager.as_mut(), ReplayConnectionManager::new(opts.speed)),
+        None => start_conns(manager.as_mut(), TcpConnectionManager::new()),
+    };
+
+    let ui = if opts.tui {
+        let tui = TermUiManager::new()
+            .unwrap_or_else(|why| die(1, &format!("Couldn't start terminal UI: {}", why)));
+        start_ui(manager.as_mut(), tui)
     } else {
-        panic!("Expected at least one command line argument (ip:port)");
+        start_ui(manager.as_mut(), StdioUiManager::new())
+    };
+
+    let timers = wrap(TimerManager::new());
+    manager.start_source(timers.clone());
+    let watcher = wrap(FileWatcher::new());
+    manager.start_source(watcher.clone());
+
+    let mut client = Client::new(manager, conns.clone(), ui);
+    client.set_timers(timers);
+    client.set_watcher(watcher);
+    client.set_config(config);
+    if let Some(why) = config_error {
+        client.output(why);
+    }
+    if let Some(path) = Config::variables_path() {
+        if let Err(why) = client.load_variables(path) {
+            client.output(why);
+        }
+    }
+    if let Some(dir) = Config::maps_dir() {
+        client.set_map_dir(dir);
     }
 
-    let mut manager = ThreadedManager::new();
-
-    let tcp = wrap(TcpConnectionManager::new());
-    manager.start_source(tcp.clone());
-    let cid = tcp.borrow_mut().start_connection(address.to_string())
-         .unwrap();
-
-    let tui = wrap(TermUiManager::new());
-    manager.start_source(tui.clone());
-
-    let mut event = manager.next_event();
-    loop {
-        match event.unwrap() {
-            Event::ServerText { line: l, which: _c } => {
-                tui.borrow_mut().push_to_window("default".to_string(), l);
-            },
-            Event::QuitRequest => {
-                break;
-            },
-            Event::UserInput { mut line, which: _ } => {
-                // Since we don't have real window management or multiple connections yet, we do
-                // ...this
-                // Obviously needs more error handling too, like everything else in this program.
-                line.push_str("\n");
-                match tcp.borrow_mut().write_to_connection(cid, line) {
-                    Ok(_) => { },
-                    Err(_) => { tui.borrow_mut().push_to_window("default".to_string(),
-                            format!("Couldn't write to connection")); }
-                }
+    let connected = match (target, opts.replay.as_ref()) {
+        (Some(target), _) => client.connect(&target).map(Some),
+        (None, Some(path)) => client.connect_address(path.to_string_lossy().to_string()).map(Some),
+        (None, None) => Ok(None),
+    };
+    let result = connected.and_then(|cid| {
+        if let Some(cid) = cid {
+            if let Some(log) = log {
+                client.start_log(cid, log);
+            }
+            if let Some(ref path) = opts.record {
+                conns.borrow_mut().start_recording(cid, path)?;
             }
-            ref event => {
-                tui.borrow_mut().push_to_window("default".to_string(),
-                        format!("Unhandled event: {:?}", event));
-            },
         }
-        event = manager.next_event();
-    }
+        client.run()
+    });
 
-    println!("At end of main() due to QuitRequest (probably.)");
+    // Dropping the client puts the terminal back the way it was, so anything we print after this
+    // will actually be visible.
+    drop(client);
+    if let Err(why) = result {
+        die(1, &why);
+    }
 }
-
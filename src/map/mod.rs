@@ -0,0 +1,340 @@
+//! The automapper: a graph of the rooms we've been in, built from what the server tells us about
+//! each room over GMCP (`Room.Info`) or MSDP (`ROOM`), and kept on disk between sessions.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+mod path;
+mod render;
+
+pub use self::path::Step;
+pub use self::render::render;
+
+/// What the server told us about the room we're in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoomInfo {
+    pub id: String,
+    pub name: String,
+    pub area: String,
+    /// Directions, and the rooms they go to.
+    pub exits: BTreeMap<String, String>,
+}
+
+impl RoomInfo {
+    /// Read a GMCP `Room.Info` message.  Servers disagree about the details, so this takes `num`
+    /// or `vnum` for the room's number and `area` or `zone` for its area, and room numbers can be
+    /// numbers or strings.
+    pub fn from_gmcp(data: &str) -> Option<RoomInfo> {
+        let data: Value = serde_json::from_str(data).ok()?;
+        RoomInfo::from_fields(&data, &["num", "vnum", "id"], &["name"], &["area", "zone"], &["exits"])
+    }
+
+    /// Read the value of the MSDP `ROOM` variable.
+    pub fn from_msdp(data: &str) -> Option<RoomInfo> {
+        let data: Value = serde_json::from_str(data).ok()?;
+        RoomInfo::from_fields(&data, &["VNUM"], &["NAME"], &["AREA"], &["EXITS"])
+    }
+
+    fn from_fields(data: &Value, id: &[&str], name: &[&str], area: &[&str], exits: &[&str]) -> Option<RoomInfo> {
+        let field = |names: &[&str]| names.iter().find_map(|name| data.get(name));
+        let exits = match field(exits) {
+            Some(Value::Object(exits)) => exits.iter()
+                .filter_map(|(dir, to)| Some((normalise(dir), text(to)?)))
+                .collect(),
+            _ => BTreeMap::new(),
+        };
+
+        Some(RoomInfo {
+            id: text(field(id)?)?,
+            name: field(name).and_then(text).unwrap_or_default(),
+            area: field(area).and_then(text).unwrap_or_default(),
+            exits,
+        })
+    }
+}
+
+/// A string or number from JSON, as a string.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// The short name for a direction, if it has one.
+fn normalise(dir: &str) -> String {
+    let dir = dir.trim().to_lowercase();
+    let short = match dir.as_str() {
+        "north" => "n",
+        "south" => "s",
+        "east" => "e",
+        "west" => "w",
+        "northeast" => "ne",
+        "northwest" => "nw",
+        "southeast" => "se",
+        "southwest" => "sw",
+        "up" => "u",
+        "down" => "d",
+        _ => return dir,
+    };
+    short.to_string()
+}
+
+/// Which way a direction goes, as (x, y, z), with north being -y.  Exits like `enter portal`
+/// don't go any way in particular.
+pub fn offset(dir: &str) -> Option<(i32, i32, i32)> {
+    Some(match dir {
+        "n" => (0, -1, 0),
+        "s" => (0, 1, 0),
+        "e" => (1, 0, 0),
+        "w" => (-1, 0, 0),
+        "ne" => (1, -1, 0),
+        "nw" => (-1, -1, 0),
+        "se" => (1, 1, 0),
+        "sw" => (-1, 1, 0),
+        "u" => (0, 0, 1),
+        "d" => (0, 0, -1),
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exit {
+    pub to: String,
+    /// The door in the way, if there is one: we `open` it before going through.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub door: Option<String>,
+    /// Locked exits are never used to find a way anywhere.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub locked: bool,
+    /// How much going this way costs, compared to the usual 1, when finding a way somewhere.
+    #[serde(default = "default_weight", skip_serializing_if = "is_default_weight")]
+    pub weight: u32,
+}
+
+impl Exit {
+    pub fn new(to: String) -> Exit {
+        Exit { to, door: None, locked: false, weight: default_weight() }
+    }
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn is_default_weight(weight: &u32) -> bool {
+    *weight == default_weight()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Room {
+    pub name: String,
+    pub area: String,
+    pub exits: BTreeMap<String, Exit>,
+    /// Where we've put the room on the map.
+    pub pos: (i32, i32, i32),
+    /// What the user calls the room, for `/goto`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// Every room we know about in one world, and where we are.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Map {
+    rooms: BTreeMap<String, Room>,
+    current: Option<String>,
+}
+
+impl Map {
+    pub fn new() -> Map {
+        Map::default()
+    }
+
+    /// Load a map saved with save().  A map that was never saved is empty.
+    pub fn load(path: &Path) -> Result<Map, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Map::new()),
+            Err(e) => return Err(format!("Couldn't read map {}: {}", path.display(), e)),
+        };
+        serde_json::from_str(&text).map_err(|e| format!("Couldn't read map {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = serde_json::to_string(self).expect("maps can always be written as JSON");
+        path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, text))
+            .map_err(|e| format!("Couldn't save map {}: {}", path.display(), e))
+    }
+
+    pub fn rooms(&self) -> &BTreeMap<String, Room> {
+        &self.rooms
+    }
+
+    pub fn room(&self, id: &str) -> Option<&Room> {
+        self.rooms.get(id)
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// We're in the room described by `info`.  Returns true if that told us anything new about
+    /// the map, so that it's worth saving.
+    pub fn enter(&mut self, info: RoomInfo) -> bool {
+        let previous = self.current.replace(info.id.clone());
+        let old = self.rooms.get(&info.id).map(|room| &room.exits);
+        // Whatever the user's said about an exit still holds if it still goes to the same place.
+        let exits: BTreeMap<String, Exit> = info.exits.into_iter()
+            .map(|(dir, to)| {
+                let exit = match old.and_then(|exits| exits.get(&dir)) {
+                    Some(exit) if exit.to == to => exit.clone(),
+                    _ => Exit::new(to),
+                };
+                (dir, exit)
+            })
+            .collect();
+
+        if let Some(room) = self.rooms.get_mut(&info.id) {
+            if room.name == info.name && room.area == info.area && room.exits == exits {
+                return false;
+            }
+            room.name = info.name;
+            room.area = info.area;
+            room.exits = exits;
+            return true;
+        }
+
+        let pos = self.place(&info.id, previous.as_deref(), &exits);
+        self.rooms.insert(info.id, Room { name: info.name, area: info.area, exits, pos, label: None });
+        true
+    }
+
+    /// Change the exit `dir` from the current room with `change`.  Returns false if there's no
+    /// such exit.
+    pub fn change_exit(&mut self, dir: &str, change: impl FnOnce(&mut Exit)) -> bool {
+        let current = match self.current {
+            Some(ref current) => current,
+            None => return false,
+        };
+        match self.rooms.get_mut(current).and_then(|room| room.exits.get_mut(&normalise(dir))) {
+            Some(exit) => {
+                change(exit);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Call the current room `label`, or nothing, taking the label off any other room.
+    pub fn set_label(&mut self, label: Option<String>) -> bool {
+        let current = match self.current {
+            Some(ref current) => current.clone(),
+            None => return false,
+        };
+        if let Some(ref label) = label {
+            for room in self.rooms.values_mut().filter(|room| room.label.as_ref() == Some(label)) {
+                room.label = None;
+            }
+        }
+        match self.rooms.get_mut(&current) {
+            Some(room) => {
+                room.label = label;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Find the room the user means by `what`: the one with that label, or that number, or
+    /// else the nearest one with `what` in its name.
+    pub fn find(&self, what: &str) -> Option<&str> {
+        if let Some((id, _)) = self.rooms.iter().find(|(_, room)| room.label.as_deref() == Some(what)) {
+            return Some(id);
+        }
+        if let Some((id, _)) = self.rooms.get_key_value(what) {
+            return Some(id);
+        }
+
+        let what = what.to_lowercase();
+        let here = self.current.as_ref().and_then(|id| self.rooms.get(id)).map_or((0, 0, 0), |room| room.pos);
+        let distance = |room: &Room| {
+            (room.pos.0 - here.0).abs() + (room.pos.1 - here.1).abs() + (room.pos.2 - here.2).abs()
+        };
+        self.rooms.iter()
+            .filter(|(_, room)| room.name.to_lowercase().contains(&what))
+            .min_by_key(|(_, room)| distance(room))
+            .map(|(id, _)| id.as_str())
+    }
+
+    /// Work out where a new room goes: next to the room we came from if we can tell which way
+    /// we went, or else next to any room it connects to.
+    fn place(&self, id: &str, previous: Option<&str>, exits: &BTreeMap<String, Exit>) -> (i32, i32, i32) {
+        let step = |(x, y, z): (i32, i32, i32), (dx, dy, dz): (i32, i32, i32), sign: i32| {
+            (x + sign * dx, y + sign * dy, z + sign * dz)
+        };
+
+        let from = |room: &Room| room.exits.iter()
+            .find(|(_, exit)| exit.to == id)
+            .and_then(|(dir, _)| Some(step(room.pos, offset(dir)?, 1)));
+        let came_from = previous.and_then(|prev| self.rooms.get(prev)).and_then(from);
+        let any_from = || self.rooms.values().find_map(from);
+        let to = || exits.iter().find_map(|(dir, exit)| {
+            Some(step(self.rooms.get(&exit.to)?.pos, offset(dir)?, -1))
+        });
+
+        came_from.or_else(any_from).or_else(to).unwrap_or_else(|| {
+            // Somewhere we can't connect to anything we know: start a new patch off to the side.
+            let right = self.rooms.values().map(|room| room.pos.0).max().map_or(0, |x| x + 3);
+            (right, 0, 0)
+        })
+    }
+}
+
+#[test]
+fn builds_maps() {
+    let info = |id: &str, exits: &[(&str, &str)]| RoomInfo {
+        id: id.to_string(),
+        name: format!("Room {}", id),
+        area: "Town".to_string(),
+        exits: exits.iter().map(|(d, to)| (d.to_string(), to.to_string())).collect(),
+    };
+
+    assert_eq!(RoomInfo::from_gmcp(r#"{"num": 1, "name": "Square", "zone": "Town", "exits": {"north": 2, "e": "3"}}"#),
+               Some(RoomInfo {
+                   id: "1".to_string(),
+                   name: "Square".to_string(),
+                   area: "Town".to_string(),
+                   exits: vec![("e".to_string(), "3".to_string()), ("n".to_string(), "2".to_string())].into_iter().collect(),
+               }));
+    assert_eq!(RoomInfo::from_msdp(r#"{"VNUM": "7", "NAME": "Gate", "EXITS": {"s": "1"}}"#).unwrap().exits["s"], "1");
+    assert_eq!(RoomInfo::from_gmcp(r#"{"name": "Nowhere"}"#), None);
+
+    let mut map = Map::new();
+    assert!(map.enter(info("1", &[("n", "2"), ("e", "3")])));
+    assert!(map.enter(info("2", &[("s", "1"), ("u", "4")])));
+    assert!(map.enter(info("4", &[("d", "2")])));
+    assert!(!map.enter(info("4", &[("d", "2")])));
+    // We haven't been to 3, but we know where it is from 1's exits.
+    assert!(map.enter(info("3", &[])));
+    assert_eq!(map.room("2").unwrap().pos, (0, -1, 0));
+    assert_eq!(map.room("3").unwrap().pos, (1, 0, 0));
+    assert_eq!(map.room("4").unwrap().pos, (0, -1, 1));
+    assert_eq!(map.current(), Some("3"));
+
+    let path = std::env::temp_dir().join(format!("mint-map-test-{}", std::process::id())).join("map.json");
+    map.save(&path).unwrap();
+    let loaded = Map::load(&path).unwrap();
+    assert_eq!(loaded.rooms(), map.rooms());
+    assert_eq!(loaded.current(), Some("3"));
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
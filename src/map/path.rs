@@ -0,0 +1,117 @@
+//! Finding the way from one room to another.
+
+use super::Map;
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// One step along a path: an exit to go through, and where it should take us.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub dir: String,
+    pub door: Option<String>,
+    pub to: String,
+}
+
+impl Step {
+    /// What to send to take the step.
+    pub fn commands(&self) -> Vec<String> {
+        let mut commands = vec![];
+        if let Some(ref door) = self.door {
+            commands.push(format!("open {}", door));
+        }
+        commands.push(self.dir.clone());
+        commands
+    }
+}
+
+impl Map {
+    /// The cheapest way from room `from` to room `to`, going by the exits' weights and avoiding
+    /// locked exits.  There's no way if we don't know one, and no steps if we're already there.
+    pub fn path(&self, from: &str, to: &str) -> Option<Vec<Step>> {
+        // Dijkstra's algorithm, remembering how we first got to each room at its lowest cost.
+        let mut costs: HashMap<&str, u64> = HashMap::new();
+        let mut came_by: HashMap<&str, (&str, &str)> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        costs.insert(from, 0);
+        queue.push(Reverse((0, from)));
+
+        while let Some(Reverse((cost, id))) = queue.pop() {
+            if id == to {
+                break;
+            }
+            if costs.get(id).is_some_and(|&best| cost > best) {
+                continue;
+            }
+            let room = match self.rooms.get(id) {
+                Some(room) => room,
+                None => continue,
+            };
+
+            for (dir, exit) in room.exits.iter().filter(|(_, exit)| !exit.locked) {
+                let next = cost + u64::from(exit.weight);
+                if costs.get(exit.to.as_str()).is_none_or(|&best| next < best) {
+                    costs.insert(&exit.to, next);
+                    came_by.insert(&exit.to, (id, dir));
+                    queue.push(Reverse((next, &exit.to)));
+                }
+            }
+        }
+
+        if !costs.contains_key(to) {
+            return None;
+        }
+        let mut steps = vec![];
+        let mut at = to;
+        while at != from {
+            let (prev, dir) = came_by[at];
+            let door = self.rooms[prev].exits[dir].door.clone();
+            steps.push(Step { dir: dir.to_string(), door, to: at.to_string() });
+            at = prev;
+        }
+        steps.reverse();
+        Some(steps)
+    }
+}
+
+#[test]
+fn finds_paths() {
+    use super::RoomInfo;
+
+    let mut map = Map::new();
+    let room = |map: &mut Map, id: &str, exits: &[(&str, &str)]| map.enter(RoomInfo {
+        id: id.to_string(),
+        name: format!("Room {}", id),
+        area: String::new(),
+        exits: exits.iter().map(|(d, to)| (d.to_string(), to.to_string())).collect(),
+    });
+    // 1 - 2 - 3
+    // |       |
+    // 4 ----- 5
+    room(&mut map, "4", &[("n", "1"), ("e", "5")]);
+    room(&mut map, "5", &[("w", "4"), ("n", "3")]);
+    room(&mut map, "3", &[("s", "5"), ("w", "2")]);
+    room(&mut map, "2", &[("e", "3"), ("w", "1")]);
+    room(&mut map, "1", &[("e", "2"), ("s", "4")]);
+
+    let dirs = |path: Option<Vec<Step>>| path.unwrap().iter().map(|step| step.dir.clone()).collect::<Vec<_>>();
+    assert_eq!(dirs(map.path("1", "3")), vec!["e", "e"]);
+    assert_eq!(dirs(map.path("1", "1")), Vec::<String>::new());
+    assert_eq!(map.path("1", "6"), None);
+
+    map.change_exit("e", |exit| exit.weight = 5);
+    assert_eq!(dirs(map.path("1", "3")), vec!["s", "e", "n"]);
+    map.change_exit("s", |exit| exit.door = Some("gate".to_string()));
+    assert_eq!(map.path("1", "3").unwrap()[0].commands(), vec!["open gate", "s"]);
+    map.change_exit("s", |exit| exit.locked = true);
+    assert_eq!(dirs(map.path("1", "3")), vec!["e", "e"]);
+
+    // Annotations survive the room being seen again.
+    room(&mut map, "1", &[("e", "2"), ("s", "4")]);
+    assert!(map.room("1").unwrap().exits["s"].locked);
+
+    assert!(map.set_label(Some("home".to_string())));
+    assert_eq!(map.find("home"), Some("1"));
+    assert_eq!(map.find("5"), Some("5"));
+    assert_eq!(map.find("room 3"), Some("3"));
+}
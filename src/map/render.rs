@@ -0,0 +1,109 @@
+//! Drawing the map as text.  Each room is drawn as `[ ]`, four columns and two rows from its
+//! neighbours, with the exits between them drawn as lines:
+//!
+//! ```text
+//! [ ]-[^]
+//!  | /
+//! [*]
+//! ```
+//!
+//! The room we're in has a `*`, and rooms with exits up or down have `^`, `v` or `+`.
+
+use super::Map;
+
+/// Draw the part of the map on the same level as the current room, centred on it, as `height`
+/// lines of exactly `width` characters.  The first line is the room's name.  There's nothing to
+/// draw if we don't know where we are.
+pub fn render(map: &Map, width: usize, height: usize) -> Vec<String> {
+    let here = match map.current().and_then(|id| map.room(id)) {
+        Some(room) => room,
+        None => return vec![],
+    };
+    if height == 0 {
+        return vec![];
+    }
+
+    let mut grid = vec![vec![' '; width]; height - 1];
+    let (centre_x, centre_y) = ((width / 2) as i64, ((height - 1) / 2) as i64);
+    let mut put = |x: i64, y: i64, c: char| {
+        if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height - 1 {
+            grid[y as usize][x as usize] = c;
+        }
+    };
+
+    let level = map.rooms().iter().filter(|(_, room)| room.pos.2 == here.pos.2);
+    for (id, room) in level {
+        let x = centre_x + 4 * i64::from(room.pos.0 - here.pos.0);
+        let y = centre_y + 2 * i64::from(room.pos.1 - here.pos.1);
+
+        let up = room.exits.contains_key("u");
+        let down = room.exits.contains_key("d");
+        let mark = match (map.current() == Some(id.as_str()), up, down) {
+            (true, _, _) => '*',
+            (false, true, true) => '+',
+            (false, true, false) => '^',
+            (false, false, true) => 'v',
+            _ => ' ',
+        };
+        put(x - 1, y, '[');
+        put(x, y, mark);
+        put(x + 1, y, ']');
+
+        for dir in room.exits.keys() {
+            let (dx, dy, c) = match dir.as_str() {
+                "n" => (0, -1, '|'),
+                "s" => (0, 1, '|'),
+                "e" => (2, 0, '-'),
+                "w" => (-2, 0, '-'),
+                "ne" => (2, -1, '/'),
+                "sw" => (-2, 1, '/'),
+                "nw" => (-2, -1, '\\'),
+                "se" => (2, 1, '\\'),
+                _ => continue,
+            };
+            put(x + dx, y + dy, c);
+        }
+    }
+
+    let title = match here.area.as_str() {
+        "" => here.name.clone(),
+        area => format!("{} ({})", here.name, area),
+    };
+    let mut lines = vec![fit(&title, width)];
+    lines.extend(grid.into_iter().map(|row| row.into_iter().collect()));
+    lines
+}
+
+/// Cut `text` down or pad it out to `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    let mut text: String = text.chars().take(width).collect();
+    let len = text.chars().count();
+    text.extend(std::iter::repeat_n(' ', width - len));
+    text
+}
+
+#[test]
+fn renders_maps() {
+    use super::RoomInfo;
+
+    let info = |id: &str, exits: &[(&str, &str)]| RoomInfo {
+        id: id.to_string(),
+        name: "Square".to_string(),
+        area: String::new(),
+        exits: exits.iter().map(|(d, to)| (d.to_string(), to.to_string())).collect(),
+    };
+    let mut map = Map::new();
+    assert!(render(&map, 11, 5).is_empty());
+
+    map.enter(info("2", &[("s", "1"), ("e", "3"), ("u", "5")]));
+    map.enter(info("3", &[("w", "2"), ("sw", "1")]));
+    map.enter(info("1", &[("n", "2"), ("ne", "3")]));
+    assert_eq!(render(&map, 11, 6), vec![
+        "Square     ",
+        "    [^]-[ ]",
+        "     | /   ",
+        "    [*]    ",
+        "           ",
+        "           ",
+    ]);
+}
@@ -1,9 +1,12 @@
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::path::PathBuf;
+use std::time::Instant;
 
 use crate::ui::Command;
 
 use crate::net::ConnectionID;
+use crate::timer::TimerID;
 pub type WindowID = usize;
 
 /// Fragments of data about something that's happened--user input, text sent by a remote server,
@@ -18,11 +21,25 @@ pub enum Event {
     UserInput { line: String, which: WindowID },
 
     ServerText { line: String, which: ConnectionID },
+    /// A prompt: text the server sent without finishing the line, and then either marked with
+    /// a GA or EOR or stopped sending after.  It's dealt with like any other line.
+    ServerPrompt { text: String, which: ConnectionID },
     ConnectionStart { which: ConnectionID },
     ConnectionEnd { which: ConnectionID, reason: String },
+    /// A GMCP message from the server: its package and message name (like `Char.Vitals`) and
+    /// its data, as JSON.
+    Gmcp { which: ConnectionID, package: String, data: String },
+    /// An MSDP variable reported by the server, with its value as JSON.
+    Msdp { which: ConnectionID, variable: String, data: String },
 
-    /// A serious internal problem, e.g., a listening thread panicked or died.
-    InternalError { what: String },
+    /// A timer scheduled with the TimerManager went off.
+    Timer { id: TimerID },
+    /// Files being watched by the FileWatcher have changed.
+    FilesChanged { paths: Vec<PathBuf> },
+
+    /// A serious internal problem, e.g., a listening thread panicked or died.  `fatal` is true
+    /// if the EventManager can't carry on after this and will refuse to produce more Events.
+    InternalError { what: String, fatal: bool },
     QuitRequest,
 }
 
@@ -37,18 +54,82 @@ pub trait EventSource {
 
     /// Return a list of objects representing the state and data required for every individual
     /// listening thread this EventSource wants to run.
-    fn get_listeners(&mut self) -> Vec<Box<Listener>>;
+    fn get_listeners(&mut self) -> Vec<Box<dyn Listener>>;
+
+    /// Called when one of this source's listeners dies or reports an error.  `which` is the
+    /// listener's index in the Vec returned by get_listeners().  By default every failure is
+    /// treated as fatal, since most sources can't do anything useful without their listeners.
+    fn listener_failed(&mut self, _which: usize, _why: &str) -> Recovery {
+        Recovery::Fatal
+    }
+}
+
+/// What an EventSource wants done about a listener that failed.
+pub enum Recovery {
+    /// The whole program is in trouble; the EventManager should stop producing Events.
+    Fatal,
+    /// Report the error, but otherwise carry on without this listener.
+    Abandon,
+    /// Report the error and run this replacement listener in place of the one that failed.
+    Restart(Box<dyn Listener>),
 }
 
 /// Object encapsulating the state and functionality for listening for new data, I/O, file writes,
 /// or whatever else.  This thread should perform only minimal processing; it must return its data
 /// by some internal method (probably a Mutex shared by the parent EventSource...)
 pub trait Listener: Send {
+    /// A short human-readable description of what this listener does, used in error messages
+    /// (e.g. "terminal resize listener".)
+    fn name(&self) -> String;
+
+    /// Return an object that can be used (from another thread) to ask this listener to shut down.
+    /// This is called once, before run().  Listeners that block somewhere they can't be woken
+    /// from may return None, in which case they'll be abandoned rather than joined when their
+    /// EventSource is stopped.
+    fn stopper(&mut self) -> Option<Box<dyn Stopper>> {
+        None
+    }
+
+    /// Return this listener as a Pollable, if it can be driven from a shared Poll instead of
+    /// running on a thread of its own.
+    fn pollable(&mut self) -> Option<&mut dyn Pollable> {
+        None
+    }
+
     /// This method should listen for data, transfer it into the associated EventSource by whatever
     /// synchronization method the implementor chooses, and page the ReadinessPager when either
     /// this has been done and the data needs to be processed (by the EventSource), or an error
     /// occurs.
-    fn run(&mut self, flag: Box<ReadinessPager>);
+    fn run(&mut self, flag: Box<dyn ReadinessPager>);
+}
+
+/// Listeners that can be run by a single-threaded EventManager implement this as well.  Instead
+/// of blocking in run(), the listener registers whatever it waits on with the manager's Poll and
+/// gets poll_once() called whenever any of it becomes ready (or its deadline passes.)
+pub trait Pollable {
+    /// Register everything this listener waits on with `poll`, under `token`.  Registrations
+    /// should be level-triggered, as poll_once() isn't obliged to drain everything in one go.
+    fn register(&mut self, poll: &mio::Poll, token: mio::Token) -> Result<(), String>;
+
+    /// Undo register().  Only things that wouldn't be closed by dropping the listener (stdin,
+    /// say) need any attention here.
+    fn deregister(&mut self, _poll: &mio::Poll) { }
+
+    /// The time at which poll_once() should be called even if nothing has become ready.
+    fn deadline(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Do whatever run() would do about its registered objects being ready, without blocking.
+    /// Returns false if the listener is finished (the equivalent of run() returning.)
+    fn poll_once(&mut self, flag: &mut dyn ReadinessPager) -> bool;
+}
+
+/// Handle allowing the EventManager to tell a running Listener to wrap things up.
+pub trait Stopper {
+    /// Ask the listener to stop.  Once this has been called, run() should return promptly and
+    /// without paging any errors.
+    fn stop(&mut self);
 }
 
 /// Object allowing its owner to notify the parent thread that either data has been successfully
@@ -61,9 +142,17 @@ pub trait ReadinessPager: Send {
     fn err(&mut self, why: String);
 }
 
+/// Handle returned by EventManager::start_source, used to stop the source later.
+pub type SourceID = usize;
+
 /// Trait implemented by an object that manages various sources of Events.
 pub trait EventManager {
-    fn start_source(&mut self, src: Rc<RefCell<EventSource>>);
+    fn start_source(&mut self, src: Rc<RefCell<dyn EventSource>>) -> SourceID;
+
+    /// Stop a source: tell its listeners to shut down, wait for the ones that can be stopped, and
+    /// forget about the source.  No more Events will be produced from it.
+    fn stop_source(&mut self, which: SourceID) -> Result<(), String>;
+
     fn next_event(&mut self) -> Result<Event, String>;
 }
 
@@ -0,0 +1,83 @@
+//! The character sets servers send text in.  Most modern MUDs use UTF-8, but older ones often
+//! use Latin-1, or CP437 for their line-drawing characters.
+
+use std::convert::TryFrom;
+
+/// What CP437 has in place of bytes 0x80 to 0xFF.  The bytes below that are ASCII, since in a
+/// telnet stream the low ones are control characters rather than CP437's pictures.
+const CP437_HIGH: &str = "\
+    ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»\
+    ░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀\
+    αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Latin1,
+    Cp437,
+}
+
+impl Encoding {
+    /// Look up an encoding by any of its usual names, ignoring case.
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "latin-1" | "latin1" | "iso-8859-1" | "iso8859-1" => Some(Encoding::Latin1),
+            "cp437" | "ibm437" => Some(Encoding::Cp437),
+            _ => None,
+        }
+    }
+
+    /// Its IANA name, which is what telnet CHARSET negotiation uses.
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Latin1 => "ISO-8859-1",
+            Encoding::Cp437 => "IBM437",
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).to_string(),
+            Encoding::Latin1 => bytes.iter().map(|&b| char::from(b)).collect(),
+            Encoding::Cp437 => bytes.iter()
+                .map(|&b| match b {
+                    0..=0x7f => char::from(b),
+                    _ => CP437_HIGH.chars().nth(usize::from(b - 0x80)).expect("CP437 has 128 high characters"),
+                })
+                .collect(),
+        }
+    }
+
+    /// Encode `text`, with `?` in place of anything the encoding doesn't have.
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 => text.as_bytes().to_vec(),
+            Encoding::Latin1 => text.chars()
+                .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+                .collect(),
+            Encoding::Cp437 => text.chars()
+                .map(|c| match c {
+                    '\0'..='\x7f' => c as u8,
+                    _ => CP437_HIGH.chars().position(|high| high == c).map_or(b'?', |i| 0x80 + i as u8),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[test]
+fn converts_encodings() {
+    assert_eq!(Encoding::from_name("Latin-1"), Some(Encoding::Latin1));
+    assert_eq!(Encoding::from_name("ebcdic"), None);
+
+    assert_eq!(Encoding::Utf8.decode(b"caf\xc3\xa9 \xff"), "café \u{fffd}");
+    assert_eq!(Encoding::Latin1.decode(b"caf\xe9 \xff"), "café ÿ");
+    assert_eq!(Encoding::Cp437.decode(b"caf\x82 \xc9\xcd\xbb \xff"), "café ╔═╗ \u{a0}");
+
+    assert_eq!(Encoding::Latin1.encode("café ÿ ☃"), b"caf\xe9 \xff ?");
+    assert_eq!(Encoding::Cp437.encode("café ╔═╗ ☃"), b"caf\x82 \xc9\xcd\xbb ?");
+    assert_eq!(CP437_HIGH.chars().count(), 128);
+}
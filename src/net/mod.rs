@@ -1,4 +1,8 @@
 
+use crate::net::encoding::Encoding;
+
+use std::path::Path;
+
 pub type ConnectionID = usize;
 
 /// This type of object knows about servers and contains the low-level logic for connecting and
@@ -7,11 +11,34 @@ pub type ConnectionID = usize;
 ///
 /// The `address' is provided in a single String with an implementation-defined format to
 /// accomodate those types of server that may not be able to be satisfied with a traditional
-/// host/port pair.
+/// host/port pair.  (For instance, the TcpConnectionManager takes `host:port`, optionally
+/// prefixed with `tls://`.)
 pub trait ConnectionInterface {
     fn start_connection(&mut self, address: String) -> Result<ConnectionID, String>;
     fn stop_connection(&mut self, which: ConnectionID) -> Result<(), ()>;
     fn write_to_connection(&mut self, which: ConnectionID, what: String) -> Result<(), ()>;
+
+    /// Use `encoding` for the text connection `which` sends and receives, until the server and
+    /// we agree on something else.
+    fn set_encoding(&mut self, _which: ConnectionID, _encoding: Encoding) {
+    }
+
+    /// Start recording everything connection `which` receives to a file at `path`, in the format
+    /// described in the `record` module.
+    fn start_recording(&mut self, _which: ConnectionID, _path: &Path) -> Result<(), String> {
+        Err("Connections of this kind can't be recorded".to_string())
+    }
+
+    /// Stop recording connection `which`.  Returns false if it wasn't being recorded.
+    fn stop_recording(&mut self, _which: ConnectionID) -> bool {
+        false
+    }
 }
 
+pub mod encoding;
+pub mod record;
+pub mod replay;
+pub mod stream;
 pub mod tcp;
+pub mod telnet;
+pub mod tls;
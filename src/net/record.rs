@@ -0,0 +1,70 @@
+//! Raw session recordings: everything a server sent, in the chunks it arrived in, with the time
+//! each chunk arrived.  These can be played back with the ReplayConnectionManager.
+//!
+//! The file format is the line `MINTREC1`, followed by one record per chunk: the time since
+//! recording started in microseconds (u64, little-endian), the chunk's length (u32, little-endian)
+//! and then the chunk itself.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8] = b"MINTREC1\n";
+
+/// Writes a recording.
+pub struct Recorder {
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    /// Start recording to `path`, replacing whatever's there.
+    pub fn create(path: &Path) -> Result<Recorder, String> {
+        let mut file = File::create(path).map(BufWriter::new)
+            .map_err(|e| format!("Couldn't create {}: {}", path.display(), e))?;
+        file.write_all(MAGIC).map_err(|e| format!("Couldn't write to {}: {}", path.display(), e))?;
+        Ok(Recorder { file, start: Instant::now() })
+    }
+
+    /// Record a chunk of data that just arrived.
+    pub fn record(&mut self, data: &[u8]) -> io::Result<()> {
+        let micros = self.start.elapsed().as_micros() as u64;
+        self.file.write_all(&micros.to_le_bytes())?;
+        self.file.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.file.write_all(data)?;
+        // Flush every time, so a crash doesn't lose the part of the recording we most want.
+        self.file.flush()
+    }
+}
+
+/// Read a whole recording, as (time since the start, data) pairs.
+pub fn load(path: &Path) -> Result<Vec<(Duration, Vec<u8>)>, String> {
+    let bad = |what: &str| format!("{} isn't a recording: {}", path.display(), what);
+    let mut file = File::open(path).map(BufReader::new)
+        .map_err(|e| format!("Couldn't open {}: {}", path.display(), e))?;
+
+    let mut magic = [0; 9];
+    file.read_exact(&mut magic).map_err(|_| bad("too short"))?;
+    if magic != MAGIC {
+        return Err(bad("wrong header"));
+    }
+
+    let mut chunks = vec![];
+    loop {
+        let mut time = [0; 8];
+        match file.read_exact(&mut time) {
+            Ok(()) => { },
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(format!("Couldn't read {}: {}", path.display(), e)),
+        }
+        let mut len = [0; 4];
+        file.read_exact(&mut len).map_err(|_| bad("cut off in the middle of a chunk"))?;
+        let mut data = vec![0; u32::from_le_bytes(len) as usize];
+        file.read_exact(&mut data).map_err(|_| bad("cut off in the middle of a chunk"))?;
+
+        chunks.push((Duration::from_micros(u64::from_le_bytes(time)), data));
+    }
+
+    Ok(chunks)
+}
@@ -0,0 +1,354 @@
+use crate::meta::{Event, EventSource, Listener, Pollable, ReadinessPager, Stopper};
+use crate::net::{ConnectionInterface, ConnectionID};
+use crate::net::encoding::Encoding;
+use crate::net::record;
+use crate::net::stream::{ServerStream, PROMPT_DELAY};
+
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How fast to play a recording back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    /// A multiple of the speed it was recorded at: 1.0 is real time, 10.0 is ten times as fast.
+    Scaled(f64),
+    /// Only as fast as the user asks: each line sent to the connection plays one more chunk, or
+    /// however many the line says if it's a number.
+    Stepped,
+}
+
+/// A recording being played back, shared between the manager and its listener thread.
+struct Playback {
+    cid: ConnectionID,
+    chunks: VecDeque<(Duration, Vec<u8>)>,
+    start: Instant,
+    // How many chunks the user has asked for, in stepped mode.
+    steps: usize,
+    // Chunks that are due, but which process() hasn't dealt with yet, and whether the server
+    // went quiet after each one when it was recorded.
+    released: Vec<(Vec<u8>, bool)>,
+}
+
+struct Shared {
+    playback: Option<Playback>,
+    speed: Speed,
+    // Set when there's something for process() to do that the clock didn't cause.
+    news: bool,
+    stopping: bool,
+}
+
+impl Shared {
+    /// When the next chunk is due, if we know.
+    fn next_deadline(&self) -> Option<Instant> {
+        if self.news {
+            return Some(Instant::now());
+        }
+        let playback = self.playback.as_ref()?;
+        let (at, _) = playback.chunks.front()?;
+
+        match self.speed {
+            Speed::Scaled(factor) => Some(playback.start + at.div_f64(factor)),
+            Speed::Stepped if playback.steps > 0 => Some(Instant::now()),
+            Speed::Stepped => None,
+        }
+    }
+
+    /// Release whatever's due.  Returns true if process() has anything to do.
+    fn release_due(&mut self, now: Instant) -> bool {
+        let mut any = mem::replace(&mut self.news, false);
+        let speed = self.speed;
+        let playback = match self.playback {
+            Some(ref mut playback) => playback,
+            None => return any,
+        };
+
+        while let Some((at, _)) = playback.chunks.front() {
+            let due = match speed {
+                Speed::Scaled(factor) => playback.start + at.div_f64(factor) <= now,
+                Speed::Stepped => playback.steps > 0,
+            };
+            if !due {
+                break;
+            }
+
+            let (at, data) = playback.chunks.pop_front().expect("front() was Some");
+            let quiet = playback.chunks.front().is_none_or(|(next, _)| *next >= at + PROMPT_DELAY);
+            playback.released.push((data, quiet));
+            playback.steps = playback.steps.saturating_sub(1);
+            any = true;
+        }
+
+        any
+    }
+}
+
+/// A ConnectionInterface that plays back recordings made by the TcpConnectionManager (see the
+/// `record` module) instead of talking to a real server.  The "address" of a connection is the
+/// path to the recording.  Only one recording can play at a time.
+pub struct ReplayConnectionManager {
+    shared: Arc<(Mutex<Shared>, Condvar)>,
+    last_connection_id: ConnectionID,
+    streams: HashMap<ConnectionID, ServerStream>,
+    // Connections that have started, but which we haven't produced a ConnectionStart for yet.
+    starting: Vec<ConnectionID>,
+}
+
+impl ReplayConnectionManager {
+    pub fn new(speed: Speed) -> ReplayConnectionManager {
+        ReplayConnectionManager {
+            shared: Arc::new((Mutex::new(Shared {
+                playback: None,
+                speed,
+                news: false,
+                stopping: false,
+            }), Condvar::new())),
+            last_connection_id: 1,
+            streams: HashMap::new(),
+            starting: vec![],
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.shared.0.lock().expect("Replay lock poisoned")
+    }
+}
+
+impl ConnectionInterface for ReplayConnectionManager {
+    fn start_connection(&mut self, address: String) -> Result<ConnectionID, String> {
+        let chunks = record::load(Path::new(&address))?;
+        let cid = self.last_connection_id;
+
+        let mut shared = self.lock();
+        if shared.playback.is_some() {
+            return Err("Already playing a recording".to_string());
+        }
+        shared.playback = Some(Playback {
+            cid,
+            chunks: chunks.into(),
+            start: Instant::now(),
+            steps: 0,
+            released: vec![],
+        });
+        shared.news = true;
+        drop(shared);
+        self.shared.1.notify_one();
+
+        self.last_connection_id += 1;
+        self.starting.push(cid);
+        Ok(cid)
+    }
+
+    fn stop_connection(&mut self, which: ConnectionID) -> Result<(), ()> {
+        let mut shared = self.lock();
+        match shared.playback {
+            Some(ref playback) if playback.cid == which => {
+                shared.playback = None;
+                drop(shared);
+                self.shared.1.notify_one();
+                Ok(())
+            },
+            _ => Err(()),
+        }
+    }
+
+    fn write_to_connection(&mut self, which: ConnectionID, what: String) -> Result<(), ()> {
+        let mut shared = self.lock();
+        if shared.speed != Speed::Stepped {
+            // There's no server to hear it.
+            return Ok(());
+        }
+
+        match shared.playback {
+            Some(ref mut playback) if playback.cid == which => {
+                playback.steps = playback.steps.saturating_add(what.trim().parse().unwrap_or(1));
+                drop(shared);
+                self.shared.1.notify_one();
+                Ok(())
+            },
+            _ => Err(()),
+        }
+    }
+
+    fn set_encoding(&mut self, which: ConnectionID, encoding: Encoding) {
+        self.streams.entry(which).or_default().set_encoding(encoding);
+    }
+}
+
+impl EventSource for ReplayConnectionManager {
+    fn get_listeners(&mut self) -> Vec<Box<dyn Listener>> {
+        vec![Box::new(ReplayListener {
+            shared: self.shared.clone(),
+        })]
+    }
+
+    fn process(&mut self) -> Vec<Event> {
+        let mut events: Vec<Event> = self.starting.drain(..)
+            .map(|which| Event::ConnectionStart { which })
+            .collect();
+
+        let mut shared = self.lock();
+        let (cid, released, finished) = match shared.playback {
+            Some(ref mut playback) => {
+                (playback.cid, mem::take(&mut playback.released), playback.chunks.is_empty())
+            },
+            None => return events,
+        };
+        if finished {
+            shared.playback = None;
+        }
+        drop(shared);
+
+        let stream = self.streams.entry(cid).or_default();
+        for (data, quiet) in released {
+            events.extend(stream.feed(cid, &data));
+            if quiet {
+                events.extend(stream.idle(cid));
+            }
+        }
+        // The recording's server isn't listening to our side of any telnet negotiation.
+        stream.take_replies();
+        if finished {
+            self.streams.remove(&cid);
+            events.push(Event::ConnectionEnd { which: cid, reason: "End of recording".to_string() });
+        }
+
+        events
+    }
+}
+
+/// Listener for ReplayConnectionManager: sleeps until the next chunk is due, then pages the
+/// main thread.
+struct ReplayListener {
+    shared: Arc<(Mutex<Shared>, Condvar)>,
+}
+
+impl Listener for ReplayListener {
+    fn name(&self) -> String {
+        "replay listener".to_string()
+    }
+
+    fn stopper(&mut self) -> Option<Box<dyn Stopper>> {
+        Some(Box::new(ReplayStopper {
+            shared: self.shared.clone(),
+        }))
+    }
+
+    fn pollable(&mut self) -> Option<&mut dyn Pollable> {
+        Some(self)
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) {
+        let (lock, cvar) = &*self.shared;
+        let mut shared = match lock.lock() {
+            Ok(s) => s,
+            Err(_) => { return flag.err("Replay lock poisoned".to_string()); },
+        };
+
+        loop {
+            if shared.stopping {
+                return;
+            }
+
+            let now = Instant::now();
+            if shared.release_due(now) {
+                // Don't hold the lock while paging; process() is going to want it.
+                drop(shared);
+                flag.ok();
+                shared = match lock.lock() {
+                    Ok(s) => s,
+                    Err(_) => { return flag.err("Replay lock poisoned".to_string()); },
+                };
+                continue;
+            }
+
+            let result = match shared.next_deadline() {
+                Some(deadline) => cvar.wait_timeout(shared, deadline.saturating_duration_since(now))
+                    .map(|(s, _)| s).map_err(|_| ()),
+                None => cvar.wait(shared).map_err(|_| ()),
+            };
+            shared = match result {
+                Ok(s) => s,
+                Err(_) => { return flag.err("Replay lock poisoned".to_string()); },
+            };
+        }
+    }
+}
+
+impl Pollable for ReplayListener {
+    fn register(&mut self, _poll: &mio::Poll, _token: mio::Token) -> Result<(), String> {
+        // Like the timer listener, all we wait on is the clock.
+        Ok(())
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.shared.0.lock().ok().and_then(|shared| shared.next_deadline())
+    }
+
+    fn poll_once(&mut self, flag: &mut dyn ReadinessPager) -> bool {
+        let any = match self.shared.0.lock() {
+            Ok(mut shared) => shared.release_due(Instant::now()),
+            Err(_) => {
+                flag.err("Replay lock poisoned".to_string());
+                return false;
+            },
+        };
+
+        if any {
+            flag.ok();
+        }
+        true
+    }
+}
+
+/// Stopper for ReplayListener.
+struct ReplayStopper {
+    shared: Arc<(Mutex<Shared>, Condvar)>,
+}
+
+impl Stopper for ReplayStopper {
+    fn stop(&mut self) {
+        let (lock, cvar) = &*self.shared;
+        if let Ok(mut shared) = lock.lock() {
+            shared.stopping = true;
+        }
+        cvar.notify_one();
+    }
+}
+
+#[test]
+fn replays_recordings() {
+    use crate::events::ThreadedManager;
+    use crate::meta::EventManager;
+    use crate::net::record::Recorder;
+    use std::{cell::RefCell, rc::Rc};
+
+    let path = std::env::temp_dir().join(format!("mint-replay-test-{}", std::process::id()));
+    let mut recorder = Recorder::create(&path).unwrap();
+    recorder.record(b"Hello, ").unwrap();
+    recorder.record(b"world.\nWhat is your name? ").unwrap();
+    drop(recorder);
+
+    let replay = Rc::new(RefCell::new(ReplayConnectionManager::new(Speed::Stepped)));
+    let mut manager = ThreadedManager::new();
+    manager.start_source(replay.clone());
+    let cid = replay.borrow_mut().start_connection(path.to_str().unwrap().to_string()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut next = || match manager.next_event() {
+        Ok(Event::ConnectionStart { which }) => format!("start {}", which),
+        Ok(Event::ServerText { line, .. }) => format!("text {}", line),
+        Ok(Event::ServerPrompt { text, .. }) => format!("prompt {}", text),
+        Ok(Event::ConnectionEnd { reason, .. }) => format!("end {}", reason),
+        other => panic!("unexpected {:?}", other),
+    };
+
+    assert_eq!(next(), format!("start {}", cid));
+    // Nothing plays until we step.
+    replay.borrow_mut().write_to_connection(cid, "2".to_string()).unwrap();
+    assert_eq!(next(), "text Hello, world.");
+    assert_eq!(next(), "prompt What is your name? ");
+    assert_eq!(next(), "end End of recording");
+}
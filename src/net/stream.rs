@@ -0,0 +1,191 @@
+use crate::meta::Event;
+use crate::net::ConnectionID;
+use crate::net::encoding::Encoding;
+use crate::net::telnet::{self, Piece, Telnet};
+
+use std::time::Duration;
+
+/// How long a server has to go without sending anything before an unfinished line counts as a
+/// prompt, for servers that don't mark their prompts with GA or EOR.
+pub const PROMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Turns the bytes a server sends into Events.  Data arrives in arbitrary chunks, so this keeps
+/// hold of any incomplete line until the rest of it turns up, or until it's clear that it's a
+/// prompt: the server has said so with a GA or EOR, or has gone quiet (see idle().)
+///
+/// Lines can end in `\n`, `\r\n` or `\n\r`.  Any other `\r` is a carriage return, and what
+/// comes after it overwrites the start of the line, as it would on a terminal.
+#[derive(Default)]
+pub struct ServerStream {
+    buffer: Vec<u8>,
+    telnet: Telnet,
+    encoding: Encoding,
+}
+
+impl ServerStream {
+    pub fn new() -> ServerStream {
+        ServerStream::default()
+    }
+
+    /// Take a chunk of data from connection `which` and return the Events it makes.
+    pub fn feed(&mut self, which: ConnectionID, data: &[u8]) -> Vec<Event> {
+        let mut events = vec![];
+
+        for piece in self.telnet.parse(data) {
+            match piece {
+                Piece::Text(text) => {
+                    self.buffer.extend_from_slice(&text);
+                    self.drain_lines(which, &mut events);
+                },
+                Piece::Gmcp(package, data) => events.push(Event::Gmcp { which, package, data }),
+                Piece::Msdp(variable, data) => events.push(Event::Msdp { which, variable, data }),
+                Piece::Charset(encoding) => self.encoding = encoding,
+                Piece::Prompt => events.extend(self.idle(which)),
+            }
+        }
+
+        events
+    }
+
+    /// The server has stopped sending for now, so whatever's left of a line must be a prompt
+    /// waiting for us to answer it.
+    pub fn idle(&mut self, which: ConnectionID) -> Option<Event> {
+        let text = overwrite(&self.encoding.decode(&self.buffer));
+        self.buffer.clear();
+        if text.is_empty() {
+            return None;
+        }
+        Some(Event::ServerPrompt { which, text })
+    }
+
+    /// Drain all the *complete* lines out of the buffer and turn them into Event::ServerText
+    /// objects.
+    fn drain_lines(&mut self, which: ConnectionID, events: &mut Vec<Event>) {
+        let mut from = 0;
+        while let Some(i) = self.buffer[from..].iter().position(|&c| c == b'\n' || c == b'\r') {
+            let i = from + i;
+            let end = match (self.buffer[i], self.buffer.get(i + 1)) {
+                (b'\n', _) => i + 1,
+                (b'\r', Some(b'\n')) => i + 2,
+                // Telnet sends a bare carriage return as `\r\0`.
+                (b'\r', Some(0)) => {
+                    self.buffer.remove(i + 1);
+                    from = i + 1;
+                    continue;
+                },
+                (b'\r', Some(_)) => {
+                    from = i + 1;
+                    continue;
+                },
+                // Wait and see whether this is the start of a `\r\n`.
+                _ => break,
+            };
+
+            // A `\r` straight after a `\n` (the `\n\r` old Diku servers send) ends up at the start
+            // of the next line, where it's harmless.
+            let line: Vec<u8> = self.buffer.drain(..end).take(i).collect();
+            events.push(Event::ServerText {
+                which,
+                line: overwrite(&self.encoding.decode(&line)),
+            });
+            from = 0;
+        }
+    }
+
+    /// The encoding text from the server is in, and that text sent to it should be in.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Turn a line of text into what to send to the server.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        telnet::escape(self.encoding.encode(text))
+    }
+
+    /// Take what the telnet layer needs sent back to the server.
+    pub fn take_replies(&mut self) -> Vec<u8> {
+        self.telnet.take_replies()
+    }
+}
+
+/// Carry out the carriage returns in `line`: the text after each one covers up as much of what's
+/// already there as it's long.  Escape sequences take up no room, and the ones in text that gets
+/// covered up are kept, so the rest of the line stays in the colours it was in.
+fn overwrite(line: &str) -> String {
+    if !line.contains('\r') {
+        return line.to_string();
+    }
+
+    let mut shown: Vec<&str> = vec![];
+    for part in line.split('\r') {
+        let new = tokens(part);
+        let width = new.iter().filter(|token| !is_escape(token)).count();
+        let covered = shown.iter()
+            .enumerate()
+            .filter(|(_, token)| !is_escape(token))
+            .nth(width)
+            .map_or(shown.len(), |(i, _)| i);
+        let kept: Vec<&str> = shown.drain(..covered).filter(|token| is_escape(token)).collect();
+        shown = new.into_iter().chain(kept).chain(shown).collect();
+    }
+    shown.concat()
+}
+
+/// Split text into escape sequences and single characters.
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let len = match rest.strip_prefix("\x1b[") {
+            // A CSI sequence runs up to a final byte in '@'..='~'.
+            Some(csi) => csi.find(|c| ('@'..='~').contains(&c)).map_or(rest.len(), |end| end + 3),
+            None => c.len_utf8(),
+        };
+        tokens.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+    tokens
+}
+
+fn is_escape(token: &str) -> bool {
+    token.starts_with('\x1b')
+}
+
+#[test]
+fn splits_lines() {
+    // Each chunk is followed by the server going quiet.
+    let lines = |chunks: &[&[u8]]| {
+        let mut stream = ServerStream::new();
+        chunks.iter()
+            .flat_map(|chunk| {
+                let mut events = stream.feed(0, chunk);
+                events.extend(stream.idle(0));
+                events
+            })
+            .map(|event| match event {
+                Event::ServerText { line, .. } => line,
+                Event::ServerPrompt { text, .. } => format!("prompt: {}", text),
+                _ => panic!("unexpected event"),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(lines(&[b"one\ntwo\r\nthree\n\rfour\n\r\n\r"]), vec!["one", "two", "three", "four", ""]);
+    assert_eq!(lines(&[b"one\ntwo\r", b"\rthree\r\n"]), vec!["one", "prompt: two", "three"]);
+    assert_eq!(lines(&[b"10%\r20%\rdone\r\0ok\n"]), vec!["okne"]);
+    assert_eq!(lines(&[b"\x1b[31mlong red\x1b[0m text\rshort\n"]), vec!["short\x1b[31mred\x1b[0m text"]);
+    assert_eq!(lines(&[b"HP: 10\r"]), vec!["prompt: HP: 10"]);
+
+    // A line split across reads is only a prompt if the server says so.
+    let mut stream = ServerStream::new();
+    assert!(stream.feed(0, b"Hello, ").is_empty());
+    assert!(matches!(&stream.feed(0, b"world\nName?\xff\xf9")[..], [
+        Event::ServerText { line, .. },
+        Event::ServerPrompt { text, .. },
+    ] if line == "Hello, world" && text == "Name?"));
+    assert!(stream.idle(0).is_none());
+}
@@ -1,19 +1,27 @@
 
-use crate::meta::{Event, EventSource, ReadinessPager, Listener};
+use crate::meta::{Event, EventSource, ReadinessPager, Listener, Pollable, Stopper};
 use crate::net::{ConnectionInterface, ConnectionID}; 
+use crate::net::encoding::Encoding;
+use crate::net::record::Recorder;
+use crate::net::stream::{ServerStream, PROMPT_DELAY};
+use crate::net::tls::TlsSession;
 
 use mio::{Events, Poll, Ready, PollOpt, Token};
 use mio::net::TcpStream;
+use mio::unix::EventedFd;
 use std::net::{SocketAddr, ToSocketAddrs};
 use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use std::sync::mpsc;
 
 const BUFFER_SIZE: usize = 4096;
-// 10 is ASCII newline
-const LINE_SEPARATOR: u8 = 10;
+// Addresses starting with this are connected to using TLS.
+const TLS_PREFIX: &str = "tls://";
 
 /// Internal event type for events sent back from the listening thread.
 enum LinkEvt {
@@ -22,6 +30,10 @@ enum LinkEvt {
     Data(ConnectionID, Vec<u8>),
     Error(ConnectionID, String),
     Eof(ConnectionID),
+    /// The server hasn't sent anything for PROMPT_DELAY since it last did.
+    Quiet(ConnectionID),
+    /// A link we asked about with ListenerRequest::Writable can be written to again.
+    Writable(ConnectionID),
 }
 
 /// EventSource for TCP connections.
@@ -35,11 +47,11 @@ pub struct TcpConnectionManager {
     // want to register along a channel, and use mio's Registraton/SetReadiness mechanism to alert
     // the polling loop.
 
-    socketreg_tx: mpsc::Sender<ConnectionRequest>,
+    socketreg_tx: mpsc::Sender<ListenerRequest>,
     // This is wrapped in an Option because we want to create it when calling new(), but it does
     // need to be moved into a struct later.  (Ultimately, it is moved across thread boundaries and
     // the reader thread registers it to a Poll instance.)
-    socketreg_rx: Option<mpsc::Receiver<ConnectionRequest>>,
+    socketreg_rx: Option<mpsc::Receiver<ListenerRequest>>,
 
     socketreg_sr: mio::SetReadiness,
     // This is in an Option for the same reason.
@@ -49,8 +61,19 @@ pub struct TcpConnectionManager {
     listener_rx: mpsc::Receiver<LinkEvt>,
     listener_tx: mpsc::Sender<LinkEvt>,
 
-    // A HashMap of vec<u8> used for buffering input from remote servers.
-    input_buffers: HashMap<ConnectionID, Vec<u8>>
+    // Turns what the servers send into Events, buffering partial lines.
+    streams: HashMap<ConnectionID, ServerStream>,
+    // The connections whose data we're recording.
+    recorders: HashMap<ConnectionID, Recorder>,
+
+    // Host names for connections that want TLS but aren't established yet...
+    tls_hosts: HashMap<ConnectionID, String>,
+    // ...and the TLS sessions for the ones that are.  All the encryption and decryption happens
+    // here on the main thread; the listener thread only ever sees the encrypted bytes.
+    tls_sessions: HashMap<ConnectionID, TlsSession>,
+    // TLS connections with encrypted output the socket wasn't ready for, which we've asked the
+    // listener to tell us about when it is.
+    tls_waiting: HashSet<ConnectionID>,
 }
 
 /// This struct represents a request to the listening thread that a new connection be started.
@@ -64,13 +87,22 @@ struct ConnectionRequest {
     cid: ConnectionID,
 }
 
+/// Requests sent to the listening thread along the socket registration channel.
+enum ListenerRequest {
+    Connect(ConnectionRequest),
+    /// Send a LinkEvt::Writable once this link can be written to.
+    Writable(ConnectionID),
+    /// Drop every connection and return from run().
+    Stop,
+}
+
 impl TcpConnectionManager {
     pub fn new() -> TcpConnectionManager {
         let (registration, set_readiness) = mio::Registration::new2();
-        let (tx, rx) = mpsc::channel::<ConnectionRequest>();
+        let (tx, rx) = mpsc::channel::<ListenerRequest>();
         let (tx2, rx2) = mpsc::channel::<LinkEvt>();
 
-        return TcpConnectionManager {
+        TcpConnectionManager {
             links: HashMap::new(),
             // We use 1 since the listener thread wants to use 0 for its 'alert me when there's a
             // new socket to register' Token.
@@ -84,8 +116,88 @@ impl TcpConnectionManager {
             listener_tx: tx2,
             listener_rx: rx2,
 
-            input_buffers: HashMap::new(),
+            streams: HashMap::new(),
+            recorders: HashMap::new(),
+
+            tls_hosts: HashMap::new(),
+            tls_sessions: HashMap::new(),
+            tls_waiting: HashSet::new(),
+        }
+    }
+
+    /// Forget about a connection that's gone.
+    fn drop_link(&mut self, cid: ConnectionID) {
+        self.links.remove(&cid); // We...probably don't care if this fails? XXX
+        self.tls_sessions.remove(&cid);
+        self.tls_hosts.remove(&cid);
+        self.tls_waiting.remove(&cid);
+        self.streams.remove(&cid);
+        self.recorders.remove(&cid);
+    }
+
+    /// Set up TLS on a newly established connection, if it asked for it.
+    fn start_tls(&mut self, cid: ConnectionID) -> Result<(), String> {
+        if let Some(host) = self.tls_hosts.remove(&cid) {
+            self.tls_sessions.insert(cid, TlsSession::new(&host)?);
+            // This sends the ClientHello.
+            self.flush_tls(cid).map_err(|e| format!("Couldn't start TLS: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Write out what a TLS connection has waiting to go.  Whatever the socket won't take yet is
+    /// sent once the listener tells us it's writable again.
+    fn flush_tls(&mut self, cid: ConnectionID) -> std::io::Result<()> {
+        let (session, link) = match (self.tls_sessions.get_mut(&cid), self.links.get_mut(&cid)) {
+            (Some(session), Some(link)) => (session, link),
+            _ => return Ok(()),
+        };
+        session.flush(link)?;
+
+        if session.wants_write() && self.tls_waiting.insert(cid) {
+            self.socketreg_tx.send(ListenerRequest::Writable(cid))
+                .expect("TcpConnectionManager internal error: Couldn't send() writable request");
+            self.socketreg_sr.set_readiness(Ready::readable())
+                .expect("TcpConnectionManager internal error: Couldn't set_readiness() for writable request");
         }
+        Ok(())
+    }
+
+    /// Send some bytes down a connection, encrypting them if it's a TLS one.
+    fn send_bytes(&mut self, which: ConnectionID, what: &[u8]) -> Result<(), ()> {
+        // TODO: Error handling here should probably be better; it ought to return a type that
+        // allows using the ? operator on I/O most likely
+        let link = match self.links.get_mut(&which) {
+            Some(link) => link,
+            None => return Err(()),
+        };
+
+        match self.tls_sessions.get_mut(&which) {
+            Some(session) => {
+                session.send(what).map_err(|_| ())?;
+                self.flush_tls(which).map_err(|_| ())
+            },
+            None => link.write_all(what).map_err(|_| ()),
+        }
+    }
+
+    /// Decrypt data received on a TLS connection.  Data on plain connections is passed through.
+    fn decrypt(&mut self, cid: ConnectionID, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        match self.tls_sessions.get_mut(&cid) {
+            Some(session) => {
+                let plaintext = session.receive(&data)?;
+                // Reading can leave us with things to say back (key updates, alerts, etc.)
+                self.flush_tls(cid).map_err(|e| format!("TLS error: {}", e))?;
+                Ok(plaintext)
+            },
+            None => Ok(data),
+        }
+    }
+}
+
+impl Default for TcpConnectionManager {
+    fn default() -> Self {
+        TcpConnectionManager::new()
     }
 }
 
@@ -93,19 +205,33 @@ impl ConnectionInterface for TcpConnectionManager {
     fn start_connection(&mut self, address: String) -> Result<ConnectionID, String> {
         let cid = self.last_connection_id;
 
-        let addrs: Vec<SocketAddr> = match address.as_str().to_socket_addrs() {
+        let (address, tls) = match address.strip_prefix(TLS_PREFIX) {
+            Some(rest) => (rest, true),
+            None => (address.as_str(), false),
+        };
+
+        let addrs: Vec<SocketAddr> = match address.to_socket_addrs() {
             Ok(results) => results.collect(),
             Err(_) => { return Err(format!("Couldn't get address for {}", address)) },
         };
 
+        if tls {
+            // We need the host name by itself to check the server's certificate against.
+            let host = match address.rfind(':') {
+                Some(i) => &address[..i],
+                None => address,
+            };
+            self.tls_hosts.insert(cid, host.trim_start_matches('[').trim_end_matches(']').to_string());
+        }
+
         // I consider it OKAY-ISH to panic here? and in similar cases? because if the threads are
         // unwinding in that way it means something is pretty seriously wrong with the entire
         // program. IT MIGHT BE A TERRIBLE IDEA.  This might be able to be turned into a ? some
         // day, when we get to issue 9.
-        self.socketreg_tx.send(ConnectionRequest {
+        self.socketreg_tx.send(ListenerRequest::Connect(ConnectionRequest {
             addrs,
             cid: self.last_connection_id,
-        }).expect("TcpConnectionManager internal error: Couldn't send() fd to reader for registration");
+        })).expect("TcpConnectionManager internal error: Couldn't send() fd to reader for registration");
 
         self.socketreg_sr.set_readiness(Ready::readable())
               .expect("TcpConnectionManager internal error: Couldn't set_readiness() for socket registration");
@@ -120,22 +246,30 @@ impl ConnectionInterface for TcpConnectionManager {
     }
 
     fn write_to_connection(&mut self, which: ConnectionID, what: String) -> Result<(), ()> {
-        // TODO: Error handling here should probably be better; it ought to return a type that
-        // allows using the ? operator on I/O most likely
-        match self.links.get_mut(&which) {
-            Some(link) => {
-                match link.write(what.as_bytes()) {
-                    Err(_) => Err(()),
-                    Ok(_) => Ok(()),
-                }
-            },
-            None => Err(()),
+        let data = self.streams.entry(which).or_default().encode(&what);
+        self.send_bytes(which, &data)
+    }
+
+    fn set_encoding(&mut self, which: ConnectionID, encoding: Encoding) {
+        self.streams.entry(which).or_default().set_encoding(encoding);
+    }
+
+    fn start_recording(&mut self, which: ConnectionID, path: &Path) -> Result<(), String> {
+        // Connections that are still being made can be recorded too, so that nothing is missed.
+        if which == 0 || which >= self.last_connection_id {
+            return Err(format!("No connection {}", which));
         }
+        self.recorders.insert(which, Recorder::create(path)?);
+        Ok(())
+    }
+
+    fn stop_recording(&mut self, which: ConnectionID) -> bool {
+        self.recorders.remove(&which).is_some()
     }
 }
 
 impl EventSource for TcpConnectionManager {
-    fn get_listeners(&mut self) -> Vec<Box<Listener>> {
+    fn get_listeners(&mut self) -> Vec<Box<dyn Listener>> {
         // Just return an event listener, but we can only do this once as it's not possible to have
         // two rx ends.  (It would actually be a logical error if this was ever called twice on
         // anything I think? Unless you were restarting it...)
@@ -143,8 +277,13 @@ impl EventSource for TcpConnectionManager {
             (Some(rx), Some(alert)) => vec![Box::new(TcpListener {
                 socketreg_rx: rx,
                 socketreg_alert: alert,
+                socketreg_tx: self.socketreg_tx.clone(),
+                socketreg_sr: self.socketreg_sr.clone(),
                 data_tx: self.listener_tx.clone(),
                 pending_requests: HashMap::new(),
+                poll: None,
+                links: HashMap::new(),
+                quiet: HashMap::new(),
             })],
             _ => { panic!("Cannot call listener() on ConnectionInterface more than once.") }
         }
@@ -158,19 +297,36 @@ impl EventSource for TcpConnectionManager {
 
         loop {
             match self.listener_rx.try_recv() {
-                Ok(LinkEvt::Data(cid, mut what)) => {
-                    let buffer = self.input_buffers.entry(cid).or_insert(Vec::new());
-                    buffer.append(&mut what);
-
-                    // Drain all the *complete* lines out of the buffer and push them into the
-                    // queue as Event::ServerText objects.
-                    while buffer.contains(&LINE_SEPARATOR) {
-                        let line = buffer.split(|c| *c == LINE_SEPARATOR).next().unwrap();
-                        queue.push(Event::ServerText {
-                            which: cid,
-                            line: String::from_utf8_lossy(&line).to_string(),
+                Ok(LinkEvt::Data(cid, what)) => {
+                    let what = match self.decrypt(cid, what) {
+                        Ok(what) => what,
+                        Err(msg) => {
+                            queue.push(Event::ConnectionEnd { which: cid, reason: msg });
+                            self.drop_link(cid);
+                            continue;
+                        },
+                    };
+
+                    // Recordings get what's been decrypted, since that's what's useful to
+                    // play back.
+                    if let Some(recorder) = self.recorders.get_mut(&cid) {
+                        if let Err(e) = recorder.record(&what) {
+                            queue.push(Event::InternalError {
+                                what: format!("Couldn't record connection {}, so stopped: {}", cid, e),
+                                fatal: false,
+                            });
+                            self.recorders.remove(&cid);
+                        }
+                    }
+
+                    let stream = self.streams.entry(cid).or_default();
+                    queue.extend(stream.feed(cid, &what));
+                    let replies = stream.take_replies();
+                    if !replies.is_empty() && self.send_bytes(cid, &replies).is_err() {
+                        queue.push(Event::InternalError {
+                            what: format!("Couldn't answer connection {}'s telnet negotiation", cid),
+                            fatal: false,
                         });
-                        buffer.drain(0..line.len() + 1);
                     }
                 },
                 Ok(LinkEvt::Error(cid, msg)) => {
@@ -178,27 +334,46 @@ impl EventSource for TcpConnectionManager {
                         which: cid,
                         reason: format!("Link error: {}", msg),
                     });
-                    self.links.remove(&cid); // We...probably don't care if this fails? XXX
+                    self.drop_link(cid);
                 },
                 Ok(LinkEvt::Established(cid, stream)) => {
-                    queue.push(Event::ConnectionStart {
-                        which: cid,
-                    });
                     self.links.insert(cid, stream);
+                    match self.start_tls(cid) {
+                        Ok(()) => queue.push(Event::ConnectionStart {
+                            which: cid,
+                        }),
+                        Err(msg) => {
+                            queue.push(Event::ConnectionEnd { which: cid, reason: msg });
+                            self.drop_link(cid);
+                        },
+                    }
                 },
                 Ok(LinkEvt::CouldntEstablish(cid)) => {
+                    self.tls_hosts.remove(&cid);
                     // TODO: Should this have its own event?
                     queue.push(Event::ConnectionEnd {
                         which: cid,
                         reason: "Could not establish connection".to_string(),
                     });
                 },
+                Ok(LinkEvt::Quiet(cid)) => {
+                    if let Some(stream) = self.streams.get_mut(&cid) {
+                        queue.extend(stream.idle(cid));
+                    }
+                },
+                Ok(LinkEvt::Writable(cid)) => {
+                    self.tls_waiting.remove(&cid);
+                    if let Err(e) = self.flush_tls(cid) {
+                        queue.push(Event::ConnectionEnd { which: cid, reason: format!("TLS error: {}", e) });
+                        self.drop_link(cid);
+                    }
+                },
                 Ok(LinkEvt::Eof(cid)) => {
                     queue.push(Event::ConnectionEnd {
                         which: cid,
-                        reason: format!("End of connection"),
+                        reason: "End of connection".to_string(),
                     });
-                    self.links.remove(&cid);
+                    self.drop_link(cid);
                 },
                 Err(_) => break,
             }
@@ -213,8 +388,11 @@ impl EventSource for TcpConnectionManager {
 /// Listener impl for TcpConnectionManager; data/object for the listener thread for TCP
 /// connections.
 struct TcpListener {
-    socketreg_rx: mpsc::Receiver<ConnectionRequest>,
+    socketreg_rx: mpsc::Receiver<ListenerRequest>,
     socketreg_alert: mio::Registration,
+    // Only kept around so we can hand them to a TcpStopper.
+    socketreg_tx: mpsc::Sender<ListenerRequest>,
+    socketreg_sr: mio::SetReadiness,
     data_tx: mpsc::Sender<LinkEvt>,
 
     // This is a list of pending connection requests.  We need it because any given address string,
@@ -224,21 +402,32 @@ struct TcpListener {
     // when a read or write on a connection succeeds, we remove it from pending_requests if it's
     // there.
     pending_requests: HashMap<ConnectionID, Vec<SocketAddr>>,
+
+    // Our own Poll, for the sockets and the request alert.  It's created by setup() so that it's
+    // created on whatever thread is going to use it.  (When we're being driven by a
+    // single-threaded EventManager, this whole Poll is registered with the manager's Poll.)
+    poll: Option<Poll>,
+    links: HashMap<ConnectionID, TcpStream>,
+    // When each link that's sent us something will have been quiet long enough for whatever it
+    // left unfinished to count as a prompt.
+    quiet: HashMap<ConnectionID, Instant>,
 }
 
 
 impl TcpListener {
+    /// Create our Poll.
+    fn setup(&mut self) -> Result<(), String> {
+        self.poll = Some(Poll::new().map_err(|e| format!("Couldn't create Poll: {}", e))?);
+        Ok(())
+    }
+
     /// Try to connect to the next option available 
     fn try_request(&mut self, req: ConnectionID) -> Option<TcpStream> {
         if let Some(opts_left) = self.pending_requests.get_mut(&req) {
-            while opts_left.len() > 0 {
-                // Can unwrap() here because we know len > 0.
-                let address_to_try = opts_left.pop().unwrap();
-
-                match TcpStream::connect(&address_to_try) {
-                    Ok(stream) => return Some(stream),
-                    Err(_) => { },
-                };
+            while let Some(address_to_try) = opts_left.pop() {
+                if let Ok(stream) = TcpStream::connect(&address_to_try) {
+                    return Some(stream);
+                }
             }
         }
 
@@ -253,13 +442,15 @@ impl TcpListener {
 
     /// Deal with trying a connection request and taking the appropriate actions.  Called
     /// internally.
-    fn handle_request(&mut self, poll: &mio::Poll, links: &mut HashMap<ConnectionID, TcpStream>, flag: &mut Box<ReadinessPager>, cid: ConnectionID) {
+    fn handle_request(&mut self, flag: &mut dyn ReadinessPager, cid: ConnectionID) {
         match self.try_request(cid) {
             Some(stream) => {
-                // We don't send Established here; it would be premature.  It can fail
-                // on a read() still.
-                poll.register(&stream, Token(cid), Ready::readable(), PollOpt::level()).unwrap();
-                links.insert(cid, stream);
+                // We don't send Established here; it would be premature, since the connection
+                // is still being made in the background.  Once it's writable, it's done (see
+                // finish_connect().)
+                self.poll.as_ref().expect("TcpListener used before setup()")
+                    .register(&stream, Token(cid), Ready::readable() | Ready::writable(), PollOpt::level()).unwrap();
+                self.links.insert(cid, stream);
             },
             None => {
                 self.data_tx.send(LinkEvt::CouldntEstablish(cid))
@@ -268,117 +459,335 @@ impl TcpListener {
             }
         }
     }
+
+    /// Deal with any requests that have arrived.  (Several can pile up before we get woken, so
+    /// take everything that's waiting.)  Returns false if we've been asked to stop.
+    fn handle_requests(&mut self, flag: &mut dyn ReadinessPager) -> bool {
+        while let Ok(request) = self.socketreg_rx.try_recv() {
+            match request {
+                ListenerRequest::Connect(request) => {
+                    let cid = request.cid;
+                    self.pending_requests.insert(cid, request.addrs);
+                    self.handle_request(flag, cid);
+                },
+                ListenerRequest::Writable(cid) => {
+                    if let Some(stream) = self.links.get(&cid) {
+                        self.poll.as_ref().expect("TcpListener used before setup()")
+                            .reregister(stream, Token(cid), Ready::readable() | Ready::writable(), PollOpt::level())
+                            .expect("reregister");
+                    }
+                },
+                ListenerRequest::Stop => {
+                    // Dropping the links closes the sockets.
+                    self.links.clear();
+                    return false;
+                },
+            }
+        }
+
+        true
+    }
+
+    /// Deal with a batch of events from our Poll.  Returns false if we've been asked to stop.
+    fn handle_events(&mut self, events: &Events, flag: &mut dyn ReadinessPager) -> bool {
+        for event in events {
+            if event.token() == Token(0) {
+                if !self.handle_requests(flag) {
+                    return false;
+                }
+            } else {
+                let cid = event.token().0;
+                if self.pending_requests.contains_key(&cid) {
+                    self.finish_connect(cid, flag);
+                } else if event.readiness().is_writable() && self.links.contains_key(&cid) {
+                    self.link_writable(cid, flag);
+                }
+                if event.readiness().is_readable() && self.links.contains_key(&cid) {
+                    self.read_link(cid, flag);
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Tell the main thread about links that have gone quiet.
+    fn check_quiet(&mut self, flag: &mut dyn ReadinessPager) {
+        let now = Instant::now();
+        let due: Vec<ConnectionID> = self.quiet.iter()
+            .filter(|(_, at)| **at <= now).map(|(cid, _)| *cid).collect();
+        for cid in due {
+            self.quiet.remove(&cid);
+            self.data_tx.send(LinkEvt::Quiet(cid)).expect("Couldn't send LinkEvt::Quiet");
+            flag.ok();
+        }
+    }
+
+    fn next_quiet(&self) -> Option<Instant> {
+        self.quiet.values().min().copied()
+    }
+
+    /// Check on a connection that's still being made.  If it worked, let the main thread know
+    /// it's established; if it failed, move on to the next address.
+    fn finish_connect(&mut self, cid: ConnectionID, flag: &mut dyn ReadinessPager) {
+        let poll = self.poll.as_ref().expect("TcpListener used before setup()");
+        let stream = self.links.get(&cid).expect("links.get");
+
+        match stream.take_error() {
+            Ok(None) => {
+                if stream.peer_addr().is_err() {
+                    // Not connected yet, and no error either.  Keep waiting.
+                    return;
+                }
+
+                // We're connected; from now on we only care about reading.
+                poll.reregister(stream, Token(cid), Ready::readable(), PollOpt::level())
+                    .expect("reregister");
+                let new_link = stream.try_clone().expect("clone link");
+                self.pending_requests.remove(&cid);
+                self.data_tx.send(LinkEvt::Established(cid, new_link))
+                    .expect("Couldn't send LinkEvt::Established");
+                flag.ok();
+            },
+            _ => {
+                // This address didn't work out.  Quietly try the next one; the main thread will
+                // hear about it if we run out.
+                poll.deregister(stream).expect("deregister");
+                self.links.remove(&cid);
+                self.handle_request(flag, cid);
+            },
+        }
+    }
+
+    /// A link the main thread asked about can be written to.  We go back to only caring about
+    /// reading it until we're asked again.
+    fn link_writable(&mut self, cid: ConnectionID, flag: &mut dyn ReadinessPager) {
+        let stream = self.links.get(&cid).expect("links.get");
+        self.poll.as_ref().expect("TcpListener used before setup()")
+            .reregister(stream, Token(cid), Ready::readable(), PollOpt::level())
+            .expect("reregister");
+        self.data_tx.send(LinkEvt::Writable(cid)).expect("Couldn't send LinkEvt::Writable");
+        flag.ok();
+    }
+
+    /// Read everything that's available from a socket.  Full disclosure: This code is heavily
+    /// based on an example I found randomly in mio's Token documentation.
+    fn read_link(&mut self, cid: ConnectionID, flag: &mut dyn ReadinessPager) {
+        let poll = self.poll.as_ref().expect("TcpListener used before setup()");
+        let mut buffer = [0u8; BUFFER_SIZE];
+        loop {
+            // TODO: IMPORTANT -- Don't panic if it doesn't exist in the links.  Do
+            // something else, like sending an internal error and closing/deregistering
+            // or whatever seems most appropriate.
+            match self.links.get_mut(&cid).expect("links.get_mut").read(&mut buffer) {
+                Ok(0) => {
+                    // End of the link.  Drop it on this end.  When we send the Error
+                    // event, the code that owns the other copy of the connection
+                    // should also drop it.
+                    poll.deregister(self.links.get(&cid).expect("links.get"))
+                        .expect("deregister");
+
+                    // We PROBABLY don't want to try the next address in a pending
+                    // request here ... if it immediately closed the connection, it's
+                    // likely whoever set up the server doesn't want us there?  In that
+                    // case, it's rude to instantly poke them on another IP.  If
+                    // there's a real error case where this is the very first result,
+                    // that would change.
+
+                    self.links.remove(&cid);
+                    self.quiet.remove(&cid);
+                    self.data_tx.send(LinkEvt::Eof(cid))
+                        .expect("Couldn't send Eof back to main thread");
+                    flag.ok();
+                    break;
+                },
+                Ok(num_bytes) => {
+                    // The buffering is done after it's sent across the thread (see
+                    // above code.) XXX Maybe we should have the buffering-for-lines
+                    // here anyway; think about it. (For example, a misbehaving server
+                    // could send us a tremendous amount of data and clog up the main
+                    // thread with buffering, whereas if it clogged up the TCP I/O
+                    // thread, the user might be able to notice in some cases and close
+                    // the link, which would call close() on the main-thread side and
+                    // put a stop to it.)
+                    let mut vec = Vec::new();
+                    vec.extend_from_slice(&buffer[..num_bytes]);
+
+                    self.data_tx.send(LinkEvt::Data(cid, vec))
+                        .expect("Couldn't send LinkEvt::Data");
+                    self.quiet.insert(cid, Instant::now() + PROMPT_DELAY);
+
+                    flag.ok();
+                },
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    // The socket is not ready anymore, stop reading
+                    break;
+                },
+                Err(ref e) => {
+                    // We assume the link wrapped up here--that an error means we
+                    // probably can't keep using it.  TODO: Do we need to (or should
+                    // we) do anything to make sure e.g. close()ing?
+                    poll.deregister(self.links.get(&cid).expect("links.get")).expect("deregister");
+                    self.links.remove(&cid);
+                    self.quiet.remove(&cid);
+
+                    // Let the main thread know things went sideways.
+                    self.data_tx.send(LinkEvt::Error(cid, format!("Problem calling read(): {}", e)))
+                        .expect("Couldn't send Error back to main thread");
+
+                    // If there are more addresses in a pending_request, we'll try the
+                    // next one of those.
+                    self.handle_request(flag, cid);
+
+                    flag.ok();
+
+                    break;
+                },
+            }
+        }
+    }
 }
 
 impl Listener for TcpListener {
-    fn run(&mut self, mut flag: Box<ReadinessPager>) {
-        // TODO: See the comment in ThreadedManager (events.rs).  Make this thread return an
-        // appropriate Result type to where we can use `?` unstead of unwrap(), and watch for that
-        // as noted there.
-        let poll = Poll::new().unwrap();
-        let mut events = Events::with_capacity(128);
-        let mut links = HashMap::new();
+    fn name(&self) -> String {
+        "TCP socket listener".to_string()
+    }
+
+    fn stopper(&mut self) -> Option<Box<dyn Stopper>> {
+        Some(Box::new(TcpStopper {
+            socketreg_tx: self.socketreg_tx.clone(),
+            socketreg_sr: self.socketreg_sr.clone(),
+        }))
+    }
+
+    fn pollable(&mut self) -> Option<&mut dyn Pollable> {
+        Some(self)
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) {
+        if let Err(e) = self.setup() {
+            return flag.err(e);
+        }
 
         // Register the alert object we're using to wake up when it's time to add a socket to our
         // inventory (e.g. register it with the poll.)
-        poll.register(&self.socketreg_alert, Token(0), Ready::readable(), PollOpt::edge()).unwrap();
+        let registered = self.poll.as_ref().unwrap()
+            .register(&self.socketreg_alert, Token(0), Ready::readable(), PollOpt::edge());
+        if let Err(e) = registered {
+            return flag.err(format!("Couldn't register alert: {}", e));
+        }
 
+        let mut events = Events::with_capacity(128);
         loop {
-            poll.poll(&mut events, None).unwrap();
-            for event in &events {
-                if event.token() == Token(0) {
-                    // A ConnectionRequest has arrived.  Deal with it.
-                    let request: ConnectionRequest = self.socketreg_rx.recv().unwrap();
-                    let cid = request.cid;
-                    self.pending_requests.insert(cid, request.addrs);
-                    self.handle_request(&poll, &mut links, &mut flag, cid);
-                } else {
-                    // Read from a socket.  Full disclosure: This code is heavily based on an
-                    // example I found randomly in mio's Token documentation.
-                    //
-                    // TODO: These indents are excessive, figure out how to factor out some of
-                    // this.
-                    let cid: usize = event.token().0;
-                    let mut buffer = [0u8; BUFFER_SIZE];
-                    loop {
-                        // TODO: IMPORTANT -- Don't panic if it doesn't exist in the links.  Do
-                        // something else, like sending an internal error and closing/deregistering
-                        // or whatever seems most appropriate.
-                        match links.get_mut(&cid).expect("links.get_mut").read(&mut buffer) {
-                            Ok(0) => {
-                                // End of the link.  Drop it on this end.  When we send the Error
-                                // event, the code that owns the other copy of the connection
-                                // should also drop it.
-                                poll.deregister(links.get(&cid).expect("links.get"))
-                                    .expect("deregister");
-
-                                // We PROBABLY don't want to try the next address in a pending
-                                // request here ... if it immediately closed the connection, it's
-                                // likely whoever set up the server doesn't want us there?  In that
-                                // case, it's rude to instantly poke them on another IP.  If
-                                // there's a real error case where this is the very first result,
-                                // that would change.
-
-                                links.remove(&cid);
-                                self.data_tx.send(LinkEvt::Eof(cid))
-                                    .expect("Couldn't send Eof back to main thread");
-                                flag.ok();
-                                break;
-                            },
-                            Ok(num_bytes) => {
-                                // The buffering is done after it's sent across the thread (see
-                                // above code.) XXX Maybe we should have the buffering-for-lines
-                                // here anyway; think about it. (For example, a misbehaving server
-                                // could send us a tremendous amount of data and clog up the main
-                                // thread with buffering, whereas if it clogged up the TCP I/O
-                                // thread, the user might be able to notice in some cases and close
-                                // the link, which would call close() on the main-thread side and
-                                // put a stop to it.)
-                                let mut vec = Vec::new();
-                                vec.extend_from_slice(&buffer[..num_bytes]);
-
-                                // See the comment on pending_requests for explanation.
-                                if let Some(_) = self.pending_requests.get(&cid) {
-                                    let new_link = links.get_mut(&cid).expect("links.get_mut")
-                                        .try_clone().expect("clone link");
-                                    self.data_tx.send(LinkEvt::Established(cid, new_link))
-                                        .expect("Couldn't send LinkEvt::Established");
-                                    self.pending_requests.remove(&cid);
-                                }
-
-                                self.data_tx.send(LinkEvt::Data(cid, vec))
-                                    .expect("Couldn't send LinkEvt::Data");
-
-                                flag.ok();
-                            },
-                            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                                // The socket is not ready anymore, stop reading
-                                break;
-                            },
-                            Err(ref e) => {
-                                // We assume the link wrapped up here--that an error means we
-                                // probably can't keep using it.  TODO: Do we need to (or should
-                                // we) do anything to make sure e.g. close()ing?
-                                poll.deregister(links.get(&cid).expect("links.get")).expect("deregister");
-                                links.remove(&cid);
-
-                                // Let the main thread know things went sideways.
-                                self.data_tx.send(LinkEvt::Error(cid, format!("Problem calling read(): {}", e)))
-                                    .expect("Couldn't send Error back to main thread");
-
-                                // If there are more addresses in a pending_request, we'll try the
-                                // next one of those.
-                                self.handle_request(&poll, &mut links, &mut flag, cid);
-
-                                flag.ok();
-
-                                break;
-                            },
-                        }
-                    }
-                }
+            // TODO: Most of the unwrap()s and expect()s in here ought to become flag.err() calls
+            // now that the EventManager can tell us apart from other listeners.
+            let timeout = self.next_quiet().map(|at| at.saturating_duration_since(Instant::now()));
+            self.poll.as_ref().unwrap().poll(&mut events, timeout).unwrap();
+            if !self.handle_events(&events, &mut *flag) {
+                return;
             }
+            self.check_quiet(&mut *flag);
         }
     }
 }
 
+/// When being polled, our own Poll (which then only has sockets in it) gets registered with the
+/// EventManager's by its file descriptor.  The alert for new requests has to be registered with
+/// the EventManager's Poll directly, though, since mio only bothers to notify a Poll about those
+/// when something is actually sitting in poll() on it.
+impl Pollable for TcpListener {
+    fn register(&mut self, poll: &Poll, token: Token) -> Result<(), String> {
+        self.setup()?;
+        let fd = self.poll.as_ref().unwrap().as_raw_fd();
+        poll.register(&EventedFd(&fd), token, Ready::readable(), PollOpt::level())
+            .and_then(|_| poll.register(&self.socketreg_alert, token, Ready::readable(), PollOpt::edge()))
+            .map_err(|e| format!("Couldn't register TCP listener with Poll: {}", e))
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.next_quiet()
+    }
+
+    fn poll_once(&mut self, flag: &mut dyn ReadinessPager) -> bool {
+        // We can't tell which of our registrations woke us up, so just check everything.
+        if !self.handle_requests(flag) {
+            return false;
+        }
+
+        let mut events = Events::with_capacity(128);
+        if let Err(e) = self.poll.as_ref().unwrap().poll(&mut events, Some(Duration::from_secs(0))) {
+            flag.err(format!("Couldn't poll TCP sockets: {}", e));
+            return false;
+        }
+        if !self.handle_events(&events, flag) {
+            return false;
+        }
+        self.check_quiet(flag);
+        true
+    }
+}
+
+/// Stopper for TcpListener; sends it a Stop request the same way new connections are requested.
+struct TcpStopper {
+    socketreg_tx: mpsc::Sender<ListenerRequest>,
+    socketreg_sr: mio::SetReadiness,
+}
+
+impl Stopper for TcpStopper {
+    fn stop(&mut self) {
+        // If either of these fails, the listener is already gone, which is what we wanted anyway.
+        let _ = self.socketreg_tx.send(ListenerRequest::Stop);
+        let _ = self.socketreg_sr.set_readiness(Ready::readable());
+    }
+}
+
+#[test]
+fn flushes_tls_once_writable() {
+    use std::io::ErrorKind;
+    use std::net::{TcpListener as StdListener, TcpStream as StdStream};
+
+    let server = StdListener::bind("127.0.0.1:0").unwrap();
+    let link = StdStream::connect(server.local_addr().unwrap()).unwrap();
+    let (mut far, _) = server.accept().unwrap();
+    let mut link = TcpStream::from_stream(link).unwrap();
+
+    // Fill the socket up so that the ClientHello has nowhere to go.
+    let mut filled = 0;
+    loop {
+        match link.write(&[0; BUFFER_SIZE]) {
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    let mut manager = TcpConnectionManager::new();
+    let cid = 2;
+    manager.links.insert(cid, link);
+    manager.tls_hosts.insert(cid, "example.org".to_string());
+    manager.start_tls(cid).unwrap();
+    assert!(manager.tls_sessions[&cid].wants_write());
+    match manager.socketreg_rx.as_ref().unwrap().try_recv() {
+        Ok(ListenerRequest::Writable(which)) => assert_eq!(which, cid),
+        _ => panic!("the listener wasn't asked to watch for the link becoming writable"),
+    }
+
+    // Empty the socket out and tell the manager, like the listener would.
+    let mut received = vec![];
+    let mut buffer = [0; BUFFER_SIZE];
+    while received.len() < filled {
+        let n = far.read(&mut buffer).unwrap();
+        received.extend_from_slice(&buffer[..n]);
+    }
+    manager.listener_tx.send(LinkEvt::Writable(cid)).unwrap();
+    assert!(manager.process().is_empty());
+    assert!(!manager.tls_sessions[&cid].wants_write());
+    assert!(manager.tls_waiting.is_empty());
+
+    // What follows the filler is the start of the TLS handshake.
+    while received.len() <= filled {
+        let n = far.read(&mut buffer).unwrap();
+        received.extend_from_slice(&buffer[..n]);
+    }
+    assert_eq!(received[filled], 0x16);
+}
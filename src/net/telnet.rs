@@ -0,0 +1,457 @@
+//! Just enough of the telnet protocol (RFC 854) to talk to MUD servers: we take the commands out
+//! of what they send, turn down every option they offer except GMCP, MSDP, CHARSET and EOR, and
+//! pick out the messages sent with those and where prompts end.  See
+//! https://www.gammon.com.au/gmcp for GMCP, https://tintin.mudhalla.net/protocols/msdp/ for
+//! MSDP, and RFC 2066 for CHARSET, which we only use to switch to UTF-8.
+
+use crate::net::encoding::Encoding;
+
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+pub const SB: u8 = 250;
+pub const GA: u8 = 249;
+pub const SE: u8 = 240;
+pub const EOR: u8 = 239;
+pub const GMCP: u8 = 201;
+pub const MSDP: u8 = 69;
+pub const CHARSET: u8 = 42;
+/// The option that lets servers mark the ends of prompts with EOR instead of GA (RFC 885.)
+pub const TELOPT_EOR: u8 = 25;
+
+// CHARSET subnegotiations.
+const CHARSET_REQUEST: u8 = 1;
+const CHARSET_ACCEPTED: u8 = 2;
+const CHARSET_REJECTED: u8 = 3;
+
+// What MSDP data is made of.
+const MSDP_VAR: u8 = 1;
+const MSDP_VAL: u8 = 2;
+const MSDP_TABLE_OPEN: u8 = 3;
+const MSDP_TABLE_CLOSE: u8 = 4;
+const MSDP_ARRAY_OPEN: u8 = 5;
+const MSDP_ARRAY_CLOSE: u8 = 6;
+
+/// The GMCP packages we ask the server to send us.
+const SUPPORTS: &str = r#"["Char 1", "Char.Vitals 1", "Room 1", "Comm 1"]"#;
+
+/// Something that came out of the data a server sent.
+#[derive(Debug, PartialEq)]
+pub enum Piece {
+    /// Ordinary text.
+    Text(Vec<u8>),
+    /// A GMCP message: the package and message name, like `Char.Vitals`, and its JSON data,
+    /// which may be empty.
+    Gmcp(String, String),
+    /// An MSDP variable and its value, turned into JSON like GMCP's.
+    Msdp(String, String),
+    /// The server and we have agreed on an encoding for everything after this.
+    Charset(Encoding),
+    /// A GA or EOR, which servers send at the end of a prompt.
+    Prompt,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Data,
+    // After an IAC.
+    Command,
+    // After IAC WILL, WONT, DO or DONT; the byte is which.
+    Option(u8),
+    // In a subnegotiation; the bool is whether we've just seen an IAC.
+    Sub(bool),
+}
+
+/// Telnet state for one connection.
+pub struct Telnet {
+    state: State,
+    // The subnegotiation we're in the middle of, starting with its option.
+    sub: Vec<u8>,
+    gmcp: bool,
+    msdp: bool,
+    charset: bool,
+    eor: bool,
+    // What we need to send back.
+    replies: Vec<u8>,
+}
+
+impl Default for Telnet {
+    fn default() -> Telnet {
+        Telnet { state: State::Data, sub: vec![], gmcp: false, msdp: false, charset: false, eor: false, replies: vec![] }
+    }
+}
+
+impl Telnet {
+    pub fn new() -> Telnet {
+        Telnet::default()
+    }
+
+    /// Take the telnet commands out of a chunk of data.  Commands can be split across chunks, so
+    /// this remembers where it was.
+    pub fn parse(&mut self, data: &[u8]) -> Vec<Piece> {
+        let mut pieces = vec![];
+        let mut text = vec![];
+
+        for &byte in data {
+            self.state = match (self.state, byte) {
+                (State::Data, IAC) => State::Command,
+                (State::Data, _) => {
+                    text.push(byte);
+                    State::Data
+                },
+                (State::Command, IAC) => {
+                    text.push(IAC);
+                    State::Data
+                },
+                (State::Command, WILL) | (State::Command, WONT) | (State::Command, DO) | (State::Command, DONT) => {
+                    State::Option(byte)
+                },
+                (State::Command, SB) => {
+                    self.sub.clear();
+                    State::Sub(false)
+                },
+                (State::Command, GA) | (State::Command, EOR) => {
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Prompt);
+                    State::Data
+                },
+                // No-ops and the like don't mean anything to us.
+                (State::Command, _) => State::Data,
+                (State::Option(command), option) => {
+                    self.negotiate(command, option);
+                    State::Data
+                },
+                (State::Sub(false), IAC) => State::Sub(true),
+                (State::Sub(true), SE) => {
+                    let found = self.subnegotiation();
+                    if !found.is_empty() && !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.extend(found);
+                    State::Data
+                },
+                // Including IAC IAC, which is an escaped 255.
+                (State::Sub(_), _) => {
+                    self.sub.push(byte);
+                    State::Sub(false)
+                },
+            };
+        }
+
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        pieces
+    }
+
+    /// Take what needs to be sent back to the server.
+    pub fn take_replies(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.replies)
+    }
+
+    /// Whether the server has agreed to send us GMCP.
+    pub fn gmcp(&self) -> bool {
+        self.gmcp
+    }
+
+    fn negotiate(&mut self, command: u8, option: u8) {
+        match (command, option) {
+            (WILL, GMCP) if !self.gmcp => {
+                self.gmcp = true;
+                self.replies.extend_from_slice(&[IAC, DO, GMCP]);
+                let hello = format!(r#"{{"client": "mint", "version": "{}"}}"#, env!("CARGO_PKG_VERSION"));
+                self.replies.extend(gmcp_message("Core.Hello", &hello));
+                self.replies.extend(gmcp_message("Core.Supports.Set", SUPPORTS));
+            },
+            (WONT, GMCP) => self.gmcp = false,
+            (WILL, MSDP) if !self.msdp => {
+                self.msdp = true;
+                self.replies.extend_from_slice(&[IAC, DO, MSDP]);
+                // Ask to be told about the room whenever it changes.
+                self.replies.extend_from_slice(&[IAC, SB, MSDP, MSDP_VAR]);
+                self.replies.extend_from_slice(b"REPORT");
+                self.replies.push(MSDP_VAL);
+                self.replies.extend_from_slice(b"ROOM");
+                self.replies.extend_from_slice(&[IAC, SE]);
+            },
+            (WONT, MSDP) => self.msdp = false,
+            // Either side can ask for a character set, so whichever way the server offers it,
+            // we get the chance to ask for UTF-8.
+            (DO, CHARSET) if !self.charset => {
+                self.charset = true;
+                self.replies.extend_from_slice(&[IAC, WILL, CHARSET]);
+            },
+            (WILL, CHARSET) if !self.charset => {
+                self.charset = true;
+                self.replies.extend_from_slice(&[IAC, DO, CHARSET, IAC, SB, CHARSET, CHARSET_REQUEST]);
+                self.replies.extend_from_slice(b";UTF-8");
+                self.replies.extend_from_slice(&[IAC, SE]);
+            },
+            (WONT, CHARSET) | (DONT, CHARSET) => self.charset = false,
+            (WILL, TELOPT_EOR) if !self.eor => {
+                self.eor = true;
+                self.replies.extend_from_slice(&[IAC, DO, TELOPT_EOR]);
+            },
+            (WONT, TELOPT_EOR) => self.eor = false,
+            (WILL, _) if ![GMCP, MSDP, CHARSET, TELOPT_EOR].contains(&option) => self.replies.extend_from_slice(&[IAC, DONT, option]),
+            (DO, _) if option != CHARSET => self.replies.extend_from_slice(&[IAC, WONT, option]),
+            // Agreeing that something's off, which it already is, or that GMCP is on, which
+            // it already is.
+            _ => {},
+        }
+    }
+
+    fn subnegotiation(&mut self) -> Vec<Piece> {
+        match self.sub.split_first() {
+            Some((&GMCP, body)) if self.gmcp => {
+                let body = String::from_utf8_lossy(body);
+                let (package, data) = match body.find(char::is_whitespace) {
+                    Some(i) => (&body[..i], body[i..].trim()),
+                    None => (&body[..], ""),
+                };
+                vec![Piece::Gmcp(package.to_string(), data.to_string())]
+            },
+            Some((&MSDP, body)) if self.msdp => {
+                let mut body = body.iter().copied().peekable();
+                msdp_table(&mut body, None).into_iter()
+                    .map(|(name, value)| Piece::Msdp(name, value.to_string()))
+                    .collect()
+            },
+            Some((&CHARSET, body)) if self.charset => {
+                let sub = body.to_vec();
+                self.charset_subnegotiation(&sub).into_iter().collect()
+            },
+            _ => vec![],
+        }
+    }
+
+    fn charset_subnegotiation(&mut self, body: &[u8]) -> Option<Piece> {
+        let (&command, rest) = body.split_first()?;
+        match command {
+            CHARSET_REQUEST => {
+                // A list of character sets, each one preceded by the separator, possibly after a
+                // translation table version we don't care about.
+                let rest = match rest.strip_prefix(b"[TTABLE]") {
+                    Some(rest) => rest.get(1..)?,
+                    None => rest,
+                };
+                let (&separator, names) = rest.split_first()?;
+                let utf8 = names.split(|&b| b == separator)
+                    .any(|name| Encoding::from_name(&String::from_utf8_lossy(name)) == Some(Encoding::Utf8));
+
+                if utf8 {
+                    self.replies.extend_from_slice(&[IAC, SB, CHARSET, CHARSET_ACCEPTED]);
+                    self.replies.extend_from_slice(Encoding::Utf8.name().as_bytes());
+                    self.replies.extend_from_slice(&[IAC, SE]);
+                    Some(Piece::Charset(Encoding::Utf8))
+                } else {
+                    self.replies.extend_from_slice(&[IAC, SB, CHARSET, CHARSET_REJECTED, IAC, SE]);
+                    None
+                }
+            },
+            CHARSET_ACCEPTED => Encoding::from_name(&String::from_utf8_lossy(rest)).map(Piece::Charset),
+            _ => None,
+        }
+    }
+}
+
+type MsdpBytes<'a> = std::iter::Peekable<std::iter::Copied<std::slice::Iter<'a, u8>>>;
+
+/// Read MSDP variables and their values, up to `end` if we're in a table.
+fn msdp_table(bytes: &mut MsdpBytes, end: Option<u8>) -> Vec<(String, serde_json::Value)> {
+    let mut vars = vec![];
+    loop {
+        match bytes.next() {
+            Some(MSDP_VAR) => {
+                let name = msdp_string(bytes);
+                // A variable with several values is an old-style array.
+                let mut values = vec![];
+                while bytes.peek() == Some(&MSDP_VAL) {
+                    bytes.next();
+                    values.push(msdp_value(bytes));
+                }
+                let value = match values.len() {
+                    0 => serde_json::Value::Null,
+                    1 => values.remove(0),
+                    _ => serde_json::Value::Array(values),
+                };
+                vars.push((name, value));
+            },
+            Some(byte) if Some(byte) == end => return vars,
+            Some(_) => {},
+            None => return vars,
+        }
+    }
+}
+
+fn msdp_value(bytes: &mut MsdpBytes) -> serde_json::Value {
+    match bytes.peek() {
+        Some(&MSDP_TABLE_OPEN) => {
+            bytes.next();
+            serde_json::Value::Object(msdp_table(bytes, Some(MSDP_TABLE_CLOSE)).into_iter().collect())
+        },
+        Some(&MSDP_ARRAY_OPEN) => {
+            bytes.next();
+            let mut items = vec![];
+            loop {
+                match bytes.next() {
+                    Some(MSDP_VAL) => items.push(msdp_value(bytes)),
+                    Some(MSDP_ARRAY_CLOSE) | None => return serde_json::Value::Array(items),
+                    Some(_) => {},
+                }
+            }
+        },
+        _ => serde_json::Value::String(msdp_string(bytes)),
+    }
+}
+
+fn msdp_string(bytes: &mut MsdpBytes) -> String {
+    let mut text = vec![];
+    while let Some(&byte) = bytes.peek() {
+        if (MSDP_VAR..=MSDP_ARRAY_CLOSE).contains(&byte) {
+            break;
+        }
+        text.push(byte);
+        bytes.next();
+    }
+    String::from_utf8_lossy(&text).to_string()
+}
+
+/// Double any IACs in `data`, so that it can be sent as ordinary text.
+pub fn escape(data: Vec<u8>) -> Vec<u8> {
+    if !data.contains(&IAC) {
+        return data;
+    }
+    let mut escaped = Vec::with_capacity(data.len() + 1);
+    for byte in data {
+        escaped.push(byte);
+        if byte == IAC {
+            escaped.push(IAC);
+        }
+    }
+    escaped
+}
+
+/// Build a GMCP message to send.
+pub fn gmcp_message(package: &str, data: &str) -> Vec<u8> {
+    let mut message = vec![IAC, SB, GMCP];
+    message.extend_from_slice(package.as_bytes());
+    if !data.is_empty() {
+        message.push(b' ');
+        message.extend_from_slice(data.as_bytes());
+    }
+    // 255 can't turn up in UTF-8, so there's nothing to escape.
+    message.extend_from_slice(&[IAC, SE]);
+    message
+}
+
+#[test]
+fn parses_telnet() {
+    let mut telnet = Telnet::new();
+    let mut data = b"Hi\xff\xff\xff\xfb\x01there\xff\xfb".to_vec();
+    assert_eq!(telnet.parse(&data), vec![Piece::Text(b"Hi\xffthere".to_vec())]);
+    assert_eq!(telnet.take_replies(), vec![IAC, DONT, 1]);
+
+    // The rest of the WILL GMCP, then a message.
+    data = vec![GMCP, IAC, SB, GMCP];
+    data.extend_from_slice(b"Char.Vitals { \"hp\": 10 }");
+    data.extend_from_slice(&[IAC, SE]);
+    data.extend_from_slice(b"> ");
+    assert_eq!(telnet.parse(&data), vec![
+        Piece::Gmcp("Char.Vitals".to_string(), "{ \"hp\": 10 }".to_string()),
+        Piece::Text(b"> ".to_vec()),
+    ]);
+    assert!(telnet.gmcp());
+    assert!(telnet.take_replies().starts_with(&[IAC, DO, GMCP, IAC, SB, GMCP]));
+
+    data = vec![IAC, WILL, MSDP, IAC, SB, MSDP, MSDP_VAR];
+    data.extend_from_slice(b"ROOM");
+    data.extend_from_slice(&[MSDP_VAL, MSDP_TABLE_OPEN, MSDP_VAR]);
+    data.extend_from_slice(b"VNUM\x024\x01EXITS\x02\x03\x01n\x025\x04");
+    data.extend_from_slice(&[MSDP_TABLE_CLOSE, IAC, SE]);
+    assert_eq!(telnet.parse(&data), vec![Piece::Msdp("ROOM".to_string(), r#"{"EXITS":{"n":"5"},"VNUM":"4"}"#.to_string())]);
+    telnet.take_replies();
+
+    data = vec![IAC, DO, CHARSET, IAC, SB, CHARSET, CHARSET_REQUEST];
+    data.extend_from_slice(b" ISO-8859-1 utf-8");
+    data.extend_from_slice(&[IAC, SE]);
+    assert_eq!(telnet.parse(&data), vec![Piece::Charset(Encoding::Utf8)]);
+    let mut accepted = vec![IAC, WILL, CHARSET, IAC, SB, CHARSET, CHARSET_ACCEPTED];
+    accepted.extend_from_slice(b"UTF-8");
+    accepted.extend_from_slice(&[IAC, SE]);
+    assert_eq!(telnet.take_replies(), accepted);
+
+    data = vec![IAC, SB, CHARSET, CHARSET_REQUEST];
+    data.extend_from_slice(b";IBM437");
+    data.extend_from_slice(&[IAC, SE]);
+    assert_eq!(telnet.parse(&data), vec![]);
+    assert_eq!(telnet.take_replies(), vec![IAC, SB, CHARSET, CHARSET_REJECTED, IAC, SE]);
+
+    data = vec![IAC, WILL, TELOPT_EOR];
+    data.extend_from_slice(b"HP: 10> ");
+    data.extend_from_slice(&[IAC, EOR, IAC, GA]);
+    assert_eq!(telnet.parse(&data), vec![Piece::Text(b"HP: 10> ".to_vec()), Piece::Prompt, Piece::Prompt]);
+    assert_eq!(telnet.take_replies(), vec![IAC, DO, TELOPT_EOR]);
+
+    assert_eq!(escape(vec![b'a', IAC, b'b']), vec![b'a', IAC, IAC, b'b']);
+
+    // CHARSET when the server offers it: we ask for UTF-8, and it answers.
+    telnet = Telnet::new();
+    assert_eq!(telnet.parse(&[IAC, WILL, CHARSET]), vec![]);
+    let mut request = vec![IAC, DO, CHARSET, IAC, SB, CHARSET, CHARSET_REQUEST];
+    request.extend_from_slice(b";UTF-8");
+    request.extend_from_slice(&[IAC, SE]);
+    assert_eq!(telnet.take_replies(), request);
+    data = vec![IAC, SB, CHARSET, CHARSET_ACCEPTED];
+    data.extend_from_slice(b"UTF-8");
+    data.extend_from_slice(&[IAC, SE]);
+    assert_eq!(telnet.parse(&data), vec![Piece::Charset(Encoding::Utf8)]);
+    assert_eq!(telnet.parse(&[IAC, SB, CHARSET, CHARSET_REJECTED, IAC, SE]), vec![]);
+    assert!(telnet.take_replies().is_empty());
+
+    // A request with a translation table version in front of the list.
+    telnet = Telnet::new();
+    data = vec![IAC, DO, CHARSET, IAC, SB, CHARSET, CHARSET_REQUEST];
+    data.extend_from_slice(b"[TTABLE]\x01,CP437,UTF-8");
+    data.extend_from_slice(&[IAC, SE]);
+    assert_eq!(telnet.parse(&data), vec![Piece::Charset(Encoding::Utf8)]);
+
+    // MSDP arrays, both kinds, and a table with an array in it.
+    telnet = Telnet::new();
+    data = vec![IAC, WILL, MSDP, IAC, SB, MSDP];
+    data.extend_from_slice(b"\x01AFFECTS\x02\x05\x02haste\x02bless\x06");
+    data.extend_from_slice(b"\x01GROUP\x02Bilbo\x02Frodo");
+    data.extend_from_slice(b"\x01ROOM\x02\x03\x01NAME\x02Bag End\x01EXITS\x02\x05\x02w\x06\x04");
+    data.extend_from_slice(&[IAC, SE]);
+    assert_eq!(telnet.parse(&data), vec![
+        Piece::Msdp("AFFECTS".to_string(), r#"["haste","bless"]"#.to_string()),
+        Piece::Msdp("GROUP".to_string(), r#"["Bilbo","Frodo"]"#.to_string()),
+        Piece::Msdp("ROOM".to_string(), r#"{"EXITS":["w"],"NAME":"Bag End"}"#.to_string()),
+    ]);
+
+    // An escaped 255 inside a subnegotiation is data, not the end of it.
+    telnet = Telnet::new();
+    data = vec![IAC, WILL, MSDP, IAC, SB, MSDP, MSDP_VAR];
+    data.extend_from_slice(b"NAME");
+    data.extend_from_slice(&[MSDP_VAL, b'a', IAC, IAC, b'b', IAC, SE]);
+    assert_eq!(telnet.parse(&data), vec![Piece::Msdp("NAME".to_string(), "\"a\u{fffd}b\"".to_string())]);
+
+    // Commands split across chunks.
+    telnet = Telnet::new();
+    assert_eq!(telnet.parse(&[IAC]), vec![]);
+    assert_eq!(telnet.parse(&[WILL]), vec![]);
+    assert_eq!(telnet.parse(&[GMCP]), vec![]);
+    assert_eq!(telnet.take_replies()[..3], [IAC, DO, GMCP]);
+    assert_eq!(telnet.parse(b"ab\xff\xfa\xc9Core.Ping"), vec![Piece::Text(b"ab".to_vec())]);
+    assert_eq!(telnet.parse(&[IAC]), vec![]);
+    assert_eq!(telnet.parse(&[SE, b'c', IAC]), vec![
+        Piece::Gmcp("Core.Ping".to_string(), String::new()),
+        Piece::Text(b"c".to_vec()),
+    ]);
+    assert_eq!(telnet.parse(&[GA, IAC]), vec![Piece::Prompt]);
+    assert_eq!(telnet.parse(&[IAC]), vec![Piece::Text(vec![IAC])]);
+}
@@ -0,0 +1,85 @@
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore};
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, OnceLock};
+
+/// The client configuration is the same for every connection (the Mozilla root certificates, no
+/// client authentication), so we only build it once.
+fn config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+
+    CONFIG.get_or_init(|| {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .expect("ring supports the default protocol versions")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Arc::new(config)
+    }).clone()
+}
+
+/// The TLS state for one connection.  This doesn't touch the socket except in flush(), so it can
+/// sit on the main thread's side of the TcpConnectionManager and be fed whatever bytes the
+/// listener thread hands over.
+pub struct TlsSession {
+    conn: ClientConnection,
+}
+
+impl TlsSession {
+    /// Start a session with `host`, which is used to check the server's certificate.
+    pub fn new(host: &str) -> Result<TlsSession, String> {
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|e| format!("'{}' can't be used for TLS: {}", host, e))?;
+        let conn = ClientConnection::new(config(), name)
+            .map_err(|e| format!("Couldn't start TLS session: {}", e))?;
+        Ok(TlsSession { conn })
+    }
+
+    /// Feed in bytes received from the server and return whatever plaintext they decrypt to.
+    pub fn receive(&mut self, mut data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut plaintext = vec![];
+
+        while !data.is_empty() {
+            self.conn.read_tls(&mut data).map_err(|e| format!("TLS error: {}", e))?;
+            self.conn.process_new_packets().map_err(|e| format!("TLS error: {}", e))?;
+
+            match self.conn.reader().read_to_end(&mut plaintext) {
+                Ok(_) => { },
+                // All that means is that there's no more plaintext for now.
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => { },
+                Err(e) => return Err(format!("TLS error: {}", e)),
+            }
+        }
+
+        Ok(plaintext)
+    }
+
+    /// Queue some plaintext to be encrypted and sent.  Call flush() afterwards.
+    pub fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        self.conn.writer().write_all(data).map_err(|e| format!("TLS error: {}", e))
+    }
+
+    /// Write out whatever TLS records are waiting to go (handshake messages included.)  If the
+    /// socket isn't ready for them, they stay queued until next time, and wants_write() says so.
+    pub fn flush(&mut self, link: &mut impl Write) -> io::Result<()> {
+        while self.conn.wants_write() {
+            match self.conn.write_tls(link) {
+                Ok(_) => { },
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether there are TLS records still waiting to be written.
+    pub fn wants_write(&self) -> bool {
+        self.conn.wants_write()
+    }
+}
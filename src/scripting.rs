@@ -0,0 +1,418 @@
+//! Scripts, written in Rhai (https://rhai.rs), for automation that's too much for the config
+//! file.  Scripts can send to the server, show text in windows, read and set variables, read
+//! GMCP data, and register triggers, aliases, GMCP hooks and timers that call their own
+//! functions.  Errors in scripts are reported back rather than taking anything down.
+//!
+//! Scripts can't get at the client directly while they're running, so what they ask for that
+//! isn't answered straight away is queued up as Requests, for the client to deal with afterwards.
+
+use regex::Regex;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, Map, AST};
+
+use crate::timer::{TimerID, TimerManager};
+use crate::variables::{Scope, Value, Variables};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+/// How much work a script can do in one go before it's assumed to be stuck in a loop.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Something a script asked for.
+#[derive(Debug, PartialEq)]
+pub enum Request {
+    /// A line to send to the active connection.
+    Send(String),
+    /// A line to show in a window.
+    Show { window: String, line: String },
+}
+
+/// A function a script registered to be called later, and which script it came from.
+#[derive(Clone)]
+struct Callback {
+    script: usize,
+    function: FnPtr,
+}
+
+/// The state the functions we give scripts work on.
+#[derive(Default)]
+struct Api {
+    requests: Vec<Request>,
+    // The script currently running, so callbacks know where they're from.
+    running: usize,
+    triggers: Vec<(Regex, Callback)>,
+    aliases: Vec<(Regex, Callback)>,
+    gmcp_hooks: Vec<(String, Callback)>,
+    timers: HashMap<TimerID, Callback>,
+    // The latest data for each GMCP package from the active connection.
+    gmcp: HashMap<String, String>,
+    world: Option<String>,
+    // A copy of the variables for scripts to use instead of the real ones, while they're being
+    // reloaded (see Scripts::stage_variables().)
+    staged: Option<Variables>,
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// A set of loaded scripts.
+pub struct Scripts {
+    engine: Engine,
+    scripts: Vec<(PathBuf, AST)>,
+    api: Rc<RefCell<Api>>,
+    timers: Option<Rc<RefCell<TimerManager>>>,
+    variables: Rc<RefCell<Variables>>,
+}
+
+impl Scripts {
+    /// An empty set of scripts.  `timers` is where scripts' timers get scheduled; without it,
+    /// scripts can't have any.
+    pub fn new(variables: Rc<RefCell<Variables>>, timers: Option<Rc<RefCell<TimerManager>>>,
+               world: Option<String>) -> Scripts {
+        let api = Rc::new(RefCell::new(Api { world, ..Api::default() }));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        register_api(&mut engine, &api, &variables, &timers);
+
+        Scripts { engine, scripts: vec![], api, timers, variables }
+    }
+
+    /// Have scripts set variables on a copy of them, which only replaces the real ones when
+    /// commit_variables() is called.  If it never is, it's as if they never set anything.
+    pub fn stage_variables(&mut self) {
+        self.api.borrow_mut().staged = Some(self.variables.borrow().clone());
+    }
+
+    /// Replace the real variables with the copy the scripts have been using since
+    /// stage_variables(), and go back to using the real ones.
+    pub fn commit_variables(&mut self) {
+        if let Some(staged) = self.api.borrow_mut().staged.take() {
+            *self.variables.borrow_mut() = staged;
+        }
+    }
+
+    /// Load and run a script file.  If it has an error, nothing it registered before the error
+    /// is kept.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let ast = self.engine.compile_file(path.to_path_buf())
+            .map_err(|e| format!("Script error in {}: {}", path.display(), e))?;
+
+        let index = self.scripts.len();
+        self.api.borrow_mut().running = index;
+        let result = self.engine.run_ast(&ast);
+        if let Err(e) = result {
+            self.forget(index);
+            return Err(format!("Script error in {}: {}", path.display(), e));
+        }
+
+        self.scripts.push((path.to_path_buf(), ast));
+        Ok(())
+    }
+
+    /// Throw away everything script `index` registered.
+    fn forget(&mut self, index: usize) {
+        let mut api = self.api.borrow_mut();
+        api.triggers.retain(|(_, cb)| cb.script != index);
+        api.aliases.retain(|(_, cb)| cb.script != index);
+        api.gmcp_hooks.retain(|(_, cb)| cb.script != index);
+
+        let timers: Vec<TimerID> = api.timers.iter()
+            .filter(|(_, cb)| cb.script == index).map(|(id, _)| *id).collect();
+        for id in timers {
+            api.timers.remove(&id);
+            if let Some(ref manager) = self.timers {
+                manager.borrow_mut().cancel(id);
+            }
+        }
+    }
+
+    /// Take whatever the scripts have asked for since last time.
+    pub fn take_requests(&mut self) -> Vec<Request> {
+        std::mem::take(&mut self.api.borrow_mut().requests)
+    }
+
+    fn call(&self, callback: &Callback, args: impl rhai::FuncArgs) -> Result<Dynamic, String> {
+        let (path, ast) = &self.scripts[callback.script];
+        self.api.borrow_mut().running = callback.script;
+        callback.function.call::<Dynamic>(&self.engine, ast, args)
+            .map_err(|e| format!("Script error in {}: {}", path.display(), e))
+    }
+
+    /// Run a line from the server (without colour codes) past the scripts' triggers.  Every
+    /// trigger that matches is called, with an array of the capture groups.
+    pub fn check_line(&self, line: &str) -> Vec<String> {
+        let matches: Vec<(Array, Callback)> = self.api.borrow().triggers.iter()
+            .filter_map(|(pattern, cb)| Some((captures(pattern, line)?, cb.clone())))
+            .collect();
+
+        matches.into_iter().filter_map(|(caps, cb)| self.call(&cb, (caps,)).err()).collect()
+    }
+
+    /// Run a line of input past the scripts' aliases.  If one matches, it's called with an array
+    /// of the capture groups, and this returns what it sent, along with what it returned if that
+    /// was a string.  Only the first alias that matches is used.
+    pub fn alias(&mut self, line: &str) -> Option<Result<Vec<String>, String>> {
+        let (caps, cb) = self.api.borrow().aliases.iter()
+            .find_map(|(pattern, cb)| Some((captures(pattern, line)?, cb.clone())))?;
+
+        let result = self.call(&cb, (caps,));
+        // What the alias sends goes in with the rest of the input, so it stays in order.
+        let mut sent = vec![];
+        self.api.borrow_mut().requests.retain(|request| match request {
+            Request::Send(line) => {
+                sent.push(line.clone());
+                false
+            },
+            _ => true,
+        });
+
+        Some(result.map(|returned| {
+            if let Some(line) = returned.try_cast::<String>() {
+                sent.push(line);
+            }
+            sent
+        }))
+    }
+
+    /// Note a GMCP message from the active connection, and call any hooks for it with its data.
+    pub fn gmcp(&self, package: &str, data: &str) -> Vec<String> {
+        let hooks: Vec<Callback> = {
+            let mut api = self.api.borrow_mut();
+            api.gmcp.insert(package.to_string(), data.to_string());
+            api.gmcp_hooks.iter()
+                .filter(|(name, _)| name.eq_ignore_ascii_case(package))
+                .map(|(_, cb)| cb.clone())
+                .collect()
+        };
+
+        hooks.into_iter().filter_map(|cb| self.call(&cb, (json_to_dynamic(data),)).err()).collect()
+    }
+
+    /// Start off with the GMCP data `other` has, for when scripts are reloaded.
+    pub fn copy_gmcp(&self, other: &Scripts) {
+        self.api.borrow_mut().gmcp = other.api.borrow().gmcp.clone();
+    }
+
+    /// Run the callback for a timer, if it's one of ours.  Returns None if it isn't.
+    pub fn timer(&mut self, id: TimerID) -> Option<Result<(), String>> {
+        let cb = self.api.borrow().timers.get(&id).cloned()?;
+        let result = self.call(&cb, ()).map(|_| ());
+        // One-shot timers are gone once they've gone off.  (Scripts get their repeating timers
+        // cancelled explicitly, so the manager's records are what tell us which is which.)
+        if self.timers.as_ref().is_some_and(|manager| !manager.borrow().is_scheduled(id)) {
+            self.api.borrow_mut().timers.remove(&id);
+        }
+        Some(result)
+    }
+}
+
+impl Drop for Scripts {
+    fn drop(&mut self) {
+        // Timers are the only thing that would outlive us.
+        if let Some(ref manager) = self.timers {
+            for id in self.api.borrow().timers.keys() {
+                manager.borrow_mut().cancel(*id);
+            }
+        }
+    }
+}
+
+fn captures(pattern: &Regex, text: &str) -> Option<Array> {
+    let caps = pattern.captures(text)?;
+    Some(caps.iter().map(|m| Dynamic::from(m.map_or(String::new(), |m| m.as_str().to_string()))).collect())
+}
+
+/// Turn GMCP's JSON into something scripts can use.  Data that isn't valid JSON is passed on as
+/// a string.
+fn json_to_dynamic(data: &str) -> Dynamic {
+    fn convert(value: serde_json::Value) -> Dynamic {
+        match value {
+            serde_json::Value::Null => Dynamic::UNIT,
+            serde_json::Value::Bool(b) => Dynamic::from(b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Dynamic::from(i),
+                None => Dynamic::from(n.as_f64().unwrap_or(0.0)),
+            },
+            serde_json::Value::String(s) => Dynamic::from(s),
+            serde_json::Value::Array(items) => Dynamic::from(items.into_iter().map(convert).collect::<Array>()),
+            serde_json::Value::Object(fields) => Dynamic::from(fields.into_iter()
+                .map(|(k, v)| (k.into(), convert(v)))
+                .collect::<Map>()),
+        }
+    }
+
+    if data.is_empty() {
+        return Dynamic::UNIT;
+    }
+    match serde_json::from_str(data) {
+        Ok(value) => convert(value),
+        Err(_) => Dynamic::from(data.to_string()),
+    }
+}
+
+fn value_to_dynamic(value: &Value) -> Dynamic {
+    match value {
+        Value::Bool(b) => Dynamic::from(*b),
+        Value::Int(i) => Dynamic::from(*i),
+        Value::Float(f) => Dynamic::from(*f),
+        Value::Str(s) => Dynamic::from(s.clone()),
+    }
+}
+
+fn dynamic_to_value(value: Dynamic) -> Value {
+    if let Some(b) = value.clone().try_cast::<bool>() {
+        Value::Bool(b)
+    } else if let Some(i) = value.clone().try_cast::<i64>() {
+        Value::Int(i)
+    } else if let Some(f) = value.clone().try_cast::<f64>() {
+        Value::Float(f)
+    } else {
+        Value::Str(value.to_string())
+    }
+}
+
+fn duration(seconds: f64) -> ScriptResult<Duration> {
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("bad number of seconds: {}", seconds).into())
+}
+
+/// Give scripts their functions.
+fn register_api(engine: &mut Engine, api: &Rc<RefCell<Api>>, variables: &Rc<RefCell<Variables>>,
+                timers: &Option<Rc<RefCell<TimerManager>>>) {
+    let a = api.clone();
+    engine.register_fn("send", move |line: &str| {
+        a.borrow_mut().requests.push(Request::Send(line.to_string()));
+    });
+    let a = api.clone();
+    engine.register_fn("echo", move |line: &str| {
+        a.borrow_mut().requests.push(Request::Show { window: "default".to_string(), line: line.to_string() });
+    });
+    let a = api.clone();
+    engine.register_fn("echo", move |window: &str, line: &str| {
+        a.borrow_mut().requests.push(Request::Show { window: window.to_string(), line: line.to_string() });
+    });
+    let a = api.clone();
+    engine.on_print(move |line| {
+        a.borrow_mut().requests.push(Request::Show { window: "default".to_string(), line: line.to_string() });
+    });
+
+    let a = api.clone();
+    engine.register_fn("trigger", move |pattern: &str, function: FnPtr| -> ScriptResult<()> {
+        let pattern = Regex::new(pattern).map_err(|e| format!("bad trigger pattern: {}", e))?;
+        let mut api = a.borrow_mut();
+        let script = api.running;
+        api.triggers.push((pattern, Callback { script, function }));
+        Ok(())
+    });
+    let a = api.clone();
+    engine.register_fn("alias", move |pattern: &str, function: FnPtr| -> ScriptResult<()> {
+        let pattern = Regex::new(pattern).map_err(|e| format!("bad alias pattern: {}", e))?;
+        let mut api = a.borrow_mut();
+        let script = api.running;
+        api.aliases.push((pattern, Callback { script, function }));
+        Ok(())
+    });
+    let a = api.clone();
+    engine.register_fn("on_gmcp", move |package: &str, function: FnPtr| {
+        let mut api = a.borrow_mut();
+        let script = api.running;
+        api.gmcp_hooks.push((package.to_string(), Callback { script, function }));
+    });
+    let a = api.clone();
+    engine.register_fn("gmcp", move |package: &str| -> Dynamic {
+        a.borrow().gmcp.get(package).map_or(Dynamic::UNIT, |data| json_to_dynamic(data))
+    });
+
+    for (name, repeating) in [("after", false), ("every", true)] {
+        let (a, t) = (api.clone(), timers.clone());
+        let schedule = move |seconds: f64, function: FnPtr| -> ScriptResult<i64> {
+            let manager = t.as_ref().ok_or("timers aren't available")?;
+            let after = duration(seconds)?;
+            let id = match repeating {
+                false => manager.borrow_mut().schedule(after),
+                true => manager.borrow_mut().schedule_repeating(after),
+            };
+            let mut api = a.borrow_mut();
+            let script = api.running;
+            api.timers.insert(id, Callback { script, function });
+            Ok(id as i64)
+        };
+        let whole = schedule.clone();
+        engine.register_fn(name, schedule);
+        engine.register_fn(name, move |seconds: i64, function: FnPtr| whole(seconds as f64, function));
+    }
+    let (a, t) = (api.clone(), timers.clone());
+    engine.register_fn("cancel", move |id: i64| -> bool {
+        let id = id as TimerID;
+        let ours = a.borrow_mut().timers.remove(&id).is_some();
+        ours && t.as_ref().is_some_and(|manager| manager.borrow_mut().cancel(id))
+    });
+
+    let (a, v) = (api.clone(), variables.clone());
+    engine.register_fn("get_var", move |name: &str| -> Dynamic {
+        let api = a.borrow();
+        let world = api.world.as_deref();
+        match api.staged {
+            Some(ref staged) => staged.get(world, name).map_or(Dynamic::UNIT, value_to_dynamic),
+            None => v.borrow().get(world, name).map_or(Dynamic::UNIT, value_to_dynamic),
+        }
+    });
+    let (a, v) = (api.clone(), variables.clone());
+    engine.register_fn("set_var", move |name: &str, value: Dynamic| -> ScriptResult<()> {
+        if !crate::variables::is_name(name) {
+            return Err(format!("bad variable name '{}'", name).into());
+        }
+        let value = dynamic_to_value(value);
+        match a.borrow_mut().staged {
+            Some(ref mut staged) => staged.set(Scope::Global, name, value, false),
+            None => v.borrow_mut().set(Scope::Global, name, value, false),
+        };
+        Ok(())
+    });
+}
+
+#[test]
+fn runs_scripts() {
+    let path = std::env::temp_dir().join(format!("mint-script-test-{}.rhai", std::process::id()));
+    std::fs::write(&path, r#"
+        trigger("(\\w+) arrives", |caps| send("kill " + caps[1]));
+        alias("^k (.*)$", |caps| { send("stand"); "kill " + caps[1] });
+        on_gmcp("Char.Vitals", |data| set_var("hp", data.hp));
+        print("loaded");
+    "#).unwrap();
+
+    let variables = Rc::new(RefCell::new(Variables::new()));
+    let mut scripts = Scripts::new(variables.clone(), None, None);
+    scripts.load(&path).unwrap();
+    std::fs::write(&path, "this is not rhai").unwrap();
+    assert!(scripts.load(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(scripts.take_requests(), vec![Request::Show { window: "default".to_string(), line: "loaded".to_string() }]);
+    assert!(scripts.check_line("An orc arrives").is_empty());
+    assert!(scripts.check_line("Nothing happens").is_empty());
+    assert_eq!(scripts.take_requests(), vec![Request::Send("kill orc".to_string())]);
+
+    assert_eq!(scripts.alias("k orc"), Some(Ok(vec!["stand".to_string(), "kill orc".to_string()])));
+    assert_eq!(scripts.alias("look"), None);
+
+    assert!(scripts.gmcp("Char.Vitals", r#"{"hp": 25, "maxhp": 30}"#).is_empty());
+    assert_eq!(variables.borrow().get(None, "hp"), Some(&Value::Int(25)));
+
+    // Staged variables only count once they're committed.
+    std::fs::write(&path, r#"set_var("hp", get_var("hp") + 1); set_var("mp", get_var("hp"));"#).unwrap();
+    let mut staged = Scripts::new(variables.clone(), None, None);
+    staged.stage_variables();
+    staged.load(&path).unwrap();
+    assert_eq!(variables.borrow().get(None, "hp"), Some(&Value::Int(25)));
+    drop(staged);
+    assert_eq!(variables.borrow().get(None, "mp"), None);
+    let mut staged = Scripts::new(variables.clone(), None, None);
+    staged.stage_variables();
+    staged.load(&path).unwrap();
+    staged.commit_variables();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(variables.borrow().get(None, "hp"), Some(&Value::Int(26)));
+    assert_eq!(variables.borrow().get(None, "mp"), Some(&Value::Int(26)));
+}
@@ -0,0 +1,179 @@
+// Stand-ins for the event manager, connections and user interface, for driving the client
+// without a terminal or a network.  Everything here is deterministic: Events come out in exactly
+// the order they were scripted, and everything written to a connection or window is kept so it
+// can be checked afterwards.
+
+use crate::client::Client;
+use crate::gauges::Reading;
+use crate::meta::{Event, EventManager, EventSource, SourceID};
+use crate::net::{ConnectionInterface, ConnectionID};
+use crate::ui::{UserInterface, Command};
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+/// EventManager that hands out a scripted sequence of Events instead of listening to anything.
+/// Sources can still be started and stopped, but their listeners are never run.  Once the script
+/// runs out, next_event() returns an error, the same way a real manager does when there's nothing
+/// left that could ever produce an Event.
+pub struct ScriptedManager {
+    script: VecDeque<Event>,
+    sources: Vec<Option<Rc<RefCell<dyn EventSource>>>>,
+}
+
+impl ScriptedManager {
+    pub fn new(script: Vec<Event>) -> ScriptedManager {
+        ScriptedManager {
+            script: script.into(),
+            sources: vec![],
+        }
+    }
+
+    /// Add an Event to the end of the script.
+    pub fn push(&mut self, event: Event) {
+        self.script.push_back(event);
+    }
+
+    /// Return true if the source with this ID has been started and not stopped.
+    pub fn is_running(&self, which: SourceID) -> bool {
+        matches!(self.sources.get(which), Some(Some(_)))
+    }
+}
+
+impl EventManager for ScriptedManager {
+    fn start_source(&mut self, src: Rc<RefCell<dyn EventSource>>) -> SourceID {
+        self.sources.push(Some(src));
+        self.sources.len() - 1
+    }
+
+    fn stop_source(&mut self, which: SourceID) -> Result<(), String> {
+        match self.sources.get_mut(which).and_then(|s| s.take()) {
+            Some(_) => Ok(()),
+            None => Err(format!("No running source with ID {}", which)),
+        }
+    }
+
+    fn next_event(&mut self) -> Result<Event, String> {
+        self.script.pop_front().ok_or_else(|| "The script has run out".to_string())
+    }
+}
+
+/// In-memory ConnectionInterface.  Connections always 'succeed' (no ConnectionStart Event is
+/// generated, though; script one if it matters) and everything written to them is recorded.
+#[derive(Default)]
+pub struct FakeConnections {
+    /// Addresses of every connection started, in order.  ConnectionIDs start at 1, so
+    /// connection N's address is `started[N - 1]`.
+    pub started: Vec<String>,
+    /// Connections that have been started and not stopped.
+    pub open: Vec<ConnectionID>,
+    /// Everything written to any connection, in order.
+    pub written: Vec<(ConnectionID, String)>,
+}
+
+impl FakeConnections {
+    pub fn new() -> FakeConnections {
+        FakeConnections::default()
+    }
+
+    /// Everything written to one connection, in order.
+    pub fn written_to(&self, which: ConnectionID) -> Vec<String> {
+        self.written.iter().filter(|(c, _)| *c == which).map(|(_, s)| s.clone()).collect()
+    }
+}
+
+impl ConnectionInterface for FakeConnections {
+    fn start_connection(&mut self, address: String) -> Result<ConnectionID, String> {
+        self.started.push(address);
+        let cid = self.started.len();
+        self.open.push(cid);
+        Ok(cid)
+    }
+
+    fn stop_connection(&mut self, which: ConnectionID) -> Result<(), ()> {
+        let before = self.open.len();
+        self.open.retain(|c| *c != which);
+        if self.open.len() == before { Err(()) } else { Ok(()) }
+    }
+
+    fn write_to_connection(&mut self, which: ConnectionID, what: String) -> Result<(), ()> {
+        if !self.open.contains(&which) {
+            return Err(());
+        }
+        self.written.push((which, what));
+        Ok(())
+    }
+}
+
+/// A Client wired up to a ScriptedManager running `script`, FakeConnections and a FakeUi, along
+/// with the fakes so tests can see what it did to them.
+pub fn fake_client(script: Vec<Event>) -> (Client, Rc<RefCell<FakeConnections>>, Rc<RefCell<FakeUi>>) {
+    let conns = Rc::new(RefCell::new(FakeConnections::new()));
+    let ui = Rc::new(RefCell::new(FakeUi::new()));
+    let client = Client::new(Box::new(ScriptedManager::new(script)), conns.clone(), ui.clone());
+    (client, conns, ui)
+}
+
+/// In-memory UserInterface that keeps every line pushed to every window.
+#[derive(Default)]
+pub struct FakeUi {
+    pub windows: HashMap<String, Vec<String>>,
+    pub commands: Vec<Command>,
+    /// Every call to alert(): the world, the bell and the flag.
+    pub alerts: Vec<(String, bool, bool)>,
+    /// What was last shown in each pane.
+    pub panes: HashMap<String, Vec<String>>,
+    /// What was last shown on the status line.
+    pub status: Vec<String>,
+    /// The gauges last shown.
+    pub gauges: Vec<Reading>,
+}
+
+impl FakeUi {
+    pub fn new() -> FakeUi {
+        FakeUi::default()
+    }
+
+    /// Everything pushed to one window, in order.
+    pub fn lines(&self, window: &str) -> Vec<String> {
+        self.windows.get(window).cloned().unwrap_or_default()
+    }
+}
+
+impl UserInterface for FakeUi {
+    fn push_to_window(&mut self, window: String, line: String) -> Result<(), ()> {
+        self.windows.entry(window).or_default().push(line);
+        Ok(())
+    }
+
+    fn register_command(&mut self, c: Command) {
+        self.commands.push(c);
+    }
+
+    fn alert(&mut self, world: String, bell: bool, flag: bool) {
+        self.alerts.push((world, bell, flag));
+    }
+
+    fn show_pane(&mut self, name: String, lines: Vec<String>) {
+        self.panes.insert(name, lines);
+    }
+
+    fn set_status(&mut self, fields: Vec<String>) {
+        self.status = fields;
+    }
+
+    fn set_gauges(&mut self, gauges: Vec<Reading>) {
+        self.gauges = gauges;
+    }
+}
+
+#[test]
+fn scripted_manager_follows_script() {
+    let mut manager = ScriptedManager::new(vec![Event::QuitRequest]);
+    manager.push(Event::ConnectionStart { which: 3 });
+
+    assert!(matches!(manager.next_event(), Ok(Event::QuitRequest)));
+    assert!(matches!(manager.next_event(), Ok(Event::ConnectionStart { which: 3 })));
+    assert!(manager.next_event().is_err());
+}
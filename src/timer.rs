@@ -0,0 +1,303 @@
+use crate::meta::{Event, EventSource, Listener, Pollable, ReadinessPager, Stopper};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+pub type TimerID = usize;
+
+/// The shortest interval a repeating timer is allowed to have.  (A repeating timer with an
+/// interval of zero would just make the listener thread spin.)
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+struct Timer {
+    deadline: Instant,
+    // `None` for one-shot timers.
+    every: Option<Duration>,
+}
+
+/// State shared between the TimerManager and its listener thread.
+struct Schedule {
+    timers: HashMap<TimerID, Timer>,
+    // Timers that have gone off but which haven't been turned into Events by process() yet.
+    fired: Vec<TimerID>,
+    // Set when the listener thread has been asked to shut down.
+    stopping: bool,
+}
+
+impl Schedule {
+    /// Move whatever's due (there could be several timers with the same deadline) into `fired`,
+    /// rescheduling or dropping the timers as appropriate.  Returns true if anything went off.
+    fn fire_due(&mut self, now: Instant) -> bool {
+        let mut any_fired = false;
+        let Schedule { timers, fired, .. } = self;
+
+        timers.retain(|id, timer| {
+            if timer.deadline > now {
+                return true;
+            }
+
+            // A repeating timer that goes off again before process() has caught up only needs
+            // the one Event.
+            if !fired.contains(id) {
+                fired.push(*id);
+            }
+            any_fired = true;
+            match timer.every {
+                Some(every) => {
+                    while timer.deadline <= now {
+                        timer.deadline += every;
+                    }
+                    true
+                },
+                None => false,
+            }
+        });
+
+        any_fired
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.timers.values().map(|t| t.deadline).min()
+    }
+}
+
+/// EventSource for timers.  Timers are measured against the monotonic clock (std::time::Instant),
+/// so they won't misbehave if the system clock gets changed underneath us.
+///
+/// When a timer goes off, an `Event::Timer` is produced carrying the TimerID that was returned
+/// when it was scheduled.
+pub struct TimerManager {
+    // The Condvar is used to wake the listener thread whenever the schedule changes, since the
+    // timer it's currently sleeping on might not be the earliest one anymore.
+    shared: Arc<(Mutex<Schedule>, Condvar)>,
+    last_timer_id: TimerID,
+}
+
+impl TimerManager {
+    pub fn new() -> TimerManager {
+        TimerManager {
+            shared: Arc::new((Mutex::new(Schedule {
+                timers: HashMap::new(),
+                fired: vec![],
+                stopping: false,
+            }), Condvar::new())),
+            last_timer_id: 0,
+        }
+    }
+
+    fn add(&mut self, after: Duration, every: Option<Duration>) -> TimerID {
+        self.last_timer_id += 1;
+        let id = self.last_timer_id;
+
+        let (lock, cvar) = &*self.shared;
+        lock.lock().expect("Timer schedule lock poisoned").timers.insert(id, Timer {
+            deadline: Instant::now() + after,
+            every,
+        });
+        cvar.notify_one();
+
+        id
+    }
+
+    /// Schedule a timer that goes off once, `after` from now.
+    pub fn schedule(&mut self, after: Duration) -> TimerID {
+        self.add(after, None)
+    }
+
+    /// Schedule a timer that goes off every `every`, starting `every` from now.  If the main
+    /// thread falls behind, missed ticks are collapsed into one rather than delivered in a burst.
+    pub fn schedule_repeating(&mut self, every: Duration) -> TimerID {
+        let every = every.max(MIN_INTERVAL);
+        self.add(every, Some(every))
+    }
+
+    /// Whether a timer is still going to go off: it hasn't been cancelled, and if it only goes
+    /// off once, it hasn't yet.
+    pub fn is_scheduled(&self, id: TimerID) -> bool {
+        let (lock, _) = &*self.shared;
+        lock.lock().expect("Timer schedule lock poisoned").timers.contains_key(&id)
+    }
+
+    /// Cancel a timer.  This also throws away the timer's Event if it has gone off but the Event
+    /// hasn't been delivered yet.  Returns false if there was no such timer.
+    pub fn cancel(&mut self, id: TimerID) -> bool {
+        let (lock, cvar) = &*self.shared;
+        let mut schedule = lock.lock().expect("Timer schedule lock poisoned");
+
+        let before = schedule.fired.len();
+        schedule.fired.retain(|t| *t != id);
+        let found = schedule.timers.remove(&id).is_some() || schedule.fired.len() != before;
+
+        cvar.notify_one();
+        found
+    }
+}
+
+impl Default for TimerManager {
+    fn default() -> Self {
+        TimerManager::new()
+    }
+}
+
+impl EventSource for TimerManager {
+    fn get_listeners(&mut self) -> Vec<Box<dyn Listener>> {
+        vec![Box::new(TimerListener {
+            shared: self.shared.clone(),
+        })]
+    }
+
+    fn process(&mut self) -> Vec<Event> {
+        let (lock, _) = &*self.shared;
+        let mut schedule = lock.lock().expect("Timer schedule lock poisoned");
+
+        schedule.fired.drain(..).map(|id| Event::Timer { id }).collect()
+    }
+}
+
+/// Listener for TimerManager: sleeps until the earliest timer is due, then pages the main thread.
+struct TimerListener {
+    shared: Arc<(Mutex<Schedule>, Condvar)>,
+}
+
+impl Listener for TimerListener {
+    fn name(&self) -> String {
+        "timer listener".to_string()
+    }
+
+    fn stopper(&mut self) -> Option<Box<dyn Stopper>> {
+        Some(Box::new(TimerStopper {
+            shared: self.shared.clone(),
+        }))
+    }
+
+    fn pollable(&mut self) -> Option<&mut dyn Pollable> {
+        Some(self)
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) {
+        let (lock, cvar) = &*self.shared;
+        let mut schedule = match lock.lock() {
+            Ok(s) => s,
+            Err(_) => { return flag.err("Timer schedule lock poisoned".to_string()); },
+        };
+
+        loop {
+            if schedule.stopping {
+                return;
+            }
+
+            let now = Instant::now();
+            if schedule.fire_due(now) {
+                // Don't hold the lock while paging; process() is going to want it.
+                drop(schedule);
+                flag.ok();
+                schedule = match lock.lock() {
+                    Ok(s) => s,
+                    Err(_) => { return flag.err("Timer schedule lock poisoned".to_string()); },
+                };
+                continue;
+            }
+
+            let result = match schedule.next_deadline() {
+                Some(deadline) => cvar.wait_timeout(schedule, deadline - now)
+                    .map(|(s, _)| s).map_err(|_| ()),
+                None => cvar.wait(schedule).map_err(|_| ()),
+            };
+            schedule = match result {
+                Ok(s) => s,
+                Err(_) => { return flag.err("Timer schedule lock poisoned".to_string()); },
+            };
+        }
+    }
+}
+
+impl Pollable for TimerListener {
+    fn register(&mut self, _poll: &mio::Poll, _token: mio::Token) -> Result<(), String> {
+        // There's nothing to wait on except the clock; see deadline().
+        Ok(())
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.shared.0.lock().ok().and_then(|schedule| schedule.next_deadline())
+    }
+
+    fn poll_once(&mut self, flag: &mut dyn ReadinessPager) -> bool {
+        let fired = match self.shared.0.lock() {
+            Ok(mut schedule) => schedule.fire_due(Instant::now()),
+            Err(_) => {
+                flag.err("Timer schedule lock poisoned".to_string());
+                return false;
+            },
+        };
+
+        if fired {
+            flag.ok();
+        }
+        true
+    }
+}
+
+/// Stopper for TimerListener.
+struct TimerStopper {
+    shared: Arc<(Mutex<Schedule>, Condvar)>,
+}
+
+impl Stopper for TimerStopper {
+    fn stop(&mut self) {
+        let (lock, cvar) = &*self.shared;
+        if let Ok(mut schedule) = lock.lock() {
+            schedule.stopping = true;
+        }
+        cvar.notify_one();
+    }
+}
+
+#[test]
+fn timers_fire_and_cancel() {
+    use crate::events::ThreadedManager;
+    use crate::meta::EventManager;
+    use std::{cell::RefCell, rc::Rc};
+
+    let timers = Rc::new(RefCell::new(TimerManager::new()));
+    let mut manager = ThreadedManager::new();
+    manager.start_source(timers.clone());
+
+    let cancelled = timers.borrow_mut().schedule(Duration::from_millis(20));
+    let once = timers.borrow_mut().schedule(Duration::from_millis(40));
+    let repeating = timers.borrow_mut().schedule_repeating(Duration::from_millis(15));
+    assert!(timers.borrow_mut().cancel(cancelled));
+    assert!(!timers.borrow_mut().cancel(cancelled));
+
+    let mut seen = vec![];
+    while !seen.contains(&once) {
+        match manager.next_event() {
+            Ok(Event::Timer { id }) => seen.push(id),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    assert!(!seen.contains(&cancelled));
+    assert!(seen.iter().filter(|id| **id == repeating).count() >= 2);
+}
+
+#[test]
+fn repeating_timers_fire_once_per_process() {
+    let mut timers = TimerManager::new();
+    let id = timers.schedule_repeating(Duration::from_millis(1));
+
+    let start = Instant::now();
+    {
+        let mut schedule = timers.shared.0.lock().unwrap();
+        for ms in 1..=5 {
+            assert!(schedule.fire_due(start + Duration::from_millis(ms * 10)));
+        }
+    }
+
+    let mut ids = || timers.process().into_iter().map(|event| match event {
+        Event::Timer { id } => id,
+        other => panic!("unexpected {:?}", other),
+    }).collect::<Vec<_>>();
+    assert_eq!(ids(), vec![id]);
+    assert!(ids().is_empty());
+}
@@ -0,0 +1,207 @@
+//! Triggers: regular expressions matched against each line from the server, and what to do when
+//! one matches.
+
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+use std::collections::HashSet;
+
+/// A trigger as written in the config file, in a `[[triggers]]` or `[[worlds.NAME.triggers]]`
+/// table.  Any combination of the actions can be given.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerSpec {
+    pub pattern: String,
+    /// A command to send; `$1` and so on are replaced with the pattern's capture groups, and
+    /// `$0` with the whole match.
+    pub send: Option<String>,
+    /// A message to show, with the same replacements as `send`.
+    pub print: Option<String>,
+    /// Show the line in this window instead of the usual one.
+    pub window: Option<String>,
+    /// Triggers with higher priorities are tried first; ties go in the order they were defined.
+    #[serde(default)]
+    pub priority: i32,
+    /// Groups of triggers can be switched on and off together.
+    pub group: Option<String>,
+    /// Carry on trying lower priority triggers after this one matches.
+    #[serde(default)]
+    pub fall_through: bool,
+}
+
+/// Something a trigger does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Send(String),
+    Print(String),
+    Window(String),
+}
+
+pub struct Trigger {
+    pub pattern: Regex,
+    pub actions: Vec<Action>,
+    pub priority: i32,
+    pub group: Option<String>,
+    pub fall_through: bool,
+}
+
+impl Trigger {
+    pub fn compile(spec: &TriggerSpec) -> Result<Trigger, String> {
+        let pattern = Regex::new(&spec.pattern)
+            .map_err(|e| format!("bad trigger pattern '{}': {}", spec.pattern, e))?;
+
+        let mut actions = vec![];
+        if let Some(ref send) = spec.send {
+            actions.push(Action::Send(send.clone()));
+        }
+        if let Some(ref print) = spec.print {
+            actions.push(Action::Print(print.clone()));
+        }
+        if let Some(ref window) = spec.window {
+            actions.push(Action::Window(window.clone()));
+        }
+
+        Ok(Trigger {
+            pattern,
+            actions,
+            priority: spec.priority,
+            group: spec.group.clone(),
+            fall_through: spec.fall_through,
+        })
+    }
+}
+
+/// Replace `$0`, `$1`... in `template` with capture groups from `caps`.  Groups that didn't match
+/// become empty.  Anything else starting with `$` is left alone.
+pub fn expand_captures(template: &str, caps: &Captures) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits == 0 {
+            out.push('$');
+            continue;
+        }
+        let n: usize = rest[..digits].parse().unwrap_or(usize::MAX);
+        out.push_str(caps.get(n).map_or("", |m| m.as_str()));
+        rest = &rest[digits..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// What happened when a line was run past the triggers.
+#[derive(Debug, Default, PartialEq)]
+pub struct Outcome {
+    /// Commands to send, in order.
+    pub send: Vec<String>,
+    /// Messages to show the user.
+    pub print: Vec<String>,
+    /// Where the line should go, if not the usual window.
+    pub window: Option<String>,
+}
+
+/// An ordered set of triggers.
+#[derive(Default)]
+pub struct TriggerSet {
+    triggers: Vec<Trigger>,
+    disabled: HashSet<String>,
+}
+
+impl TriggerSet {
+    pub fn new() -> TriggerSet {
+        TriggerSet::default()
+    }
+
+    /// Replace the triggers with `triggers`.  Groups that were disabled stay disabled.
+    pub fn set(&mut self, mut triggers: Vec<Trigger>) {
+        // The sort is stable, so definition order breaks ties.
+        triggers.sort_by_key(|t| std::cmp::Reverse(t.priority));
+        self.triggers = triggers;
+    }
+
+    pub fn triggers(&self) -> &[Trigger] {
+        &self.triggers
+    }
+
+    /// Switch a group of triggers on or off.
+    pub fn enable_group(&mut self, group: &str, enabled: bool) {
+        if enabled {
+            self.disabled.remove(group);
+        } else {
+            self.disabled.insert(group.to_string());
+        }
+    }
+
+    pub fn is_enabled(&self, trigger: &Trigger) -> bool {
+        trigger.group.as_ref().is_none_or(|group| !self.disabled.contains(group))
+    }
+
+    /// Run a line (without any colour codes) past the triggers.
+    pub fn check(&self, line: &str) -> Outcome {
+        let mut outcome = Outcome::default();
+
+        for trigger in self.triggers.iter().filter(|t| self.is_enabled(t)) {
+            let caps = match trigger.pattern.captures(line) {
+                Some(caps) => caps,
+                None => continue,
+            };
+
+            for action in &trigger.actions {
+                match action {
+                    Action::Send(template) => outcome.send.push(expand_captures(template, &caps)),
+                    Action::Print(template) => outcome.print.push(expand_captures(template, &caps)),
+                    Action::Window(window) => {
+                        // The first trigger to route the line somewhere gets its way.
+                        outcome.window.get_or_insert_with(|| window.clone());
+                    },
+                }
+            }
+
+            if !trigger.fall_through {
+                break;
+            }
+        }
+
+        outcome
+    }
+}
+
+#[test]
+fn triggers_match_in_order() {
+    let spec = |pattern: &str, send: &str, priority, fall_through| TriggerSpec {
+        pattern: pattern.to_string(),
+        send: Some(send.to_string()),
+        print: None,
+        window: None,
+        priority,
+        group: None,
+        fall_through,
+    };
+
+    let mut set = TriggerSet::new();
+    let mut tells = Trigger::compile(&spec("^(\\w+) tells you '(.*)'", "tell $1 You said $2, $5done $x", 0, false)).unwrap();
+    tells.actions.push(Action::Window("tells".to_string()));
+    tells.group = Some("social".to_string());
+    set.set(vec![
+        Trigger::compile(&spec("rat", "kill rat", 0, false)).unwrap(),
+        tells,
+        Trigger::compile(&spec("tells", "grin", 5, true)).unwrap(),
+    ]);
+    assert!(Trigger::compile(&spec("(", "", 0, false)).is_err());
+
+    let outcome = set.check("Bob tells you 'hi there'");
+    assert_eq!(outcome.send, vec!["grin", "tell Bob You said hi there, done $x"]);
+    assert_eq!(outcome.window, Some("tells".to_string()));
+
+    // The rat trigger stops anything after it; it was defined before the tells one.
+    assert_eq!(set.check("A rat tells you 'squeak'").send, vec!["grin", "kill rat"]);
+
+    set.enable_group("social", false);
+    assert_eq!(set.check("Bob tells you 'hi'").send, vec!["grin"]);
+}
@@ -1,3 +1,5 @@
+use crate::gauges::Reading;
+
 // TODO: Consider how specification of the arguments for commands ought to work, or if it ought
 // to be a thing in the first place.
 pub type Command = String;
@@ -10,6 +12,28 @@ pub trait UserInterface {
     /// is surface to the user, is the UI code's business.
     fn push_to_window(&mut self, window: String, line: String) -> Result<(), ()>;
     fn register_command(&mut self, c: Command);
+
+    /// Let the user know something happened in `world`: ring the bell if `bell` is set, and flag
+    /// the world as wanting attention if `flag` is.  How (or whether) that's shown is up to the UI.
+    fn alert(&mut self, _world: String, _bell: bool, _flag: bool) {
+    }
+
+    /// Replace what's shown in the pane called `name` with `lines`; no lines means there's
+    /// nothing to show.  Unlike windows, panes are for things like maps that are redrawn as a
+    /// whole, and UIs with nowhere to put them can ignore them.
+    fn show_pane(&mut self, _name: String, _lines: Vec<String>) {
+    }
+
+    /// Show `fields` on the status line, or no status line if there aren't any.  They're all
+    /// filled in already, except that the UI replaces `{scroll}` with where its view is scrolled
+    /// to, since only it knows.
+    fn set_status(&mut self, _fields: Vec<String>) {
+    }
+
+    /// Show `gauges`, replacing any that were shown before.
+    fn set_gauges(&mut self, _gauges: Vec<Reading>) {
+    }
 }
 
+pub mod stdio;
 pub mod term;
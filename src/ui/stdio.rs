@@ -0,0 +1,155 @@
+use std::sync::mpsc;
+use std::sync::mpsc::{Sender, Receiver};
+use std::io::{self, BufRead, Write, stdin, stdout};
+
+use mio::unix::EventedFd;
+
+use crate::meta::{Event, EventSource, ReadinessPager, Listener, Pollable, Recovery};
+use crate::ui::{UserInterface, Command};
+
+/// A bare-bones user interface for running without a terminal: lines read from stdin become
+/// UserInput, and lines sent to windows are printed to stdout.  End of input is treated as a
+/// request to quit.
+pub struct StdioUiManager {
+    tx_template: Sender<StdioEvent>,
+    rx: Receiver<StdioEvent>,
+}
+
+/// Event type used internally for communication between threads.
+enum StdioEvent {
+    Line(String),
+    Eof,
+}
+
+impl StdioUiManager {
+    pub fn new() -> StdioUiManager {
+        let (tx, rx) = mpsc::channel();
+        StdioUiManager {
+            tx_template: tx,
+            rx,
+        }
+    }
+}
+
+impl Default for StdioUiManager {
+    fn default() -> Self {
+        StdioUiManager::new()
+    }
+}
+
+impl EventSource for StdioUiManager {
+    fn get_listeners(&mut self) -> Vec<Box<dyn Listener>> {
+        vec![Box::new(LineListener {
+            tx: self.tx_template.clone(),
+            partial: vec![],
+        })]
+    }
+
+    fn listener_failed(&mut self, _which: usize, _why: &str) -> Recovery {
+        // The listener quits once stdin runs out, and we've already asked to quit by then.
+        Recovery::Abandon
+    }
+
+    fn process(&mut self) -> Vec<Event> {
+        self.rx.try_iter().map(|e| match e {
+            StdioEvent::Line(line) => Event::UserInput { line, which: 0 },
+            StdioEvent::Eof => Event::QuitRequest,
+        }).collect()
+    }
+}
+
+impl UserInterface for StdioUiManager {
+    fn push_to_window(&mut self, window: String, line: String) -> Result<(), ()> {
+        let stdout = stdout();
+        let mut stdout = stdout.lock();
+        let written = if window == "default" {
+            writeln!(stdout, "{}", line)
+        } else {
+            writeln!(stdout, "[{}] {}", window, line)
+        };
+        written.and_then(|_| stdout.flush()).map_err(|_| ())
+    }
+
+    fn register_command(&mut self, _c: Command) {
+    }
+
+    fn alert(&mut self, _world: String, bell: bool, _flag: bool) {
+        if bell {
+            let _ = stdout().write_all(b"\x07").and_then(|_| stdout().flush());
+        }
+    }
+}
+
+/// Listener reading lines from stdin.
+struct LineListener {
+    tx: Sender<StdioEvent>,
+    // When polled, the part of a line we've read so far.
+    partial: Vec<u8>,
+}
+
+impl Listener for LineListener {
+    fn name(&self) -> String {
+        "stdin line listener".to_string()
+    }
+
+    fn pollable(&mut self) -> Option<&mut dyn Pollable> {
+        Some(self)
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) {
+        let stdin = stdin();
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => { return flag.err(format!("Couldn't read from stdin: {}", e)); },
+            };
+            if self.tx.send(StdioEvent::Line(line)).is_err() {
+                return;
+            }
+            flag.ok();
+        }
+
+        let _ = self.tx.send(StdioEvent::Eof);
+        flag.ok();
+    }
+}
+
+impl Pollable for LineListener {
+    fn register(&mut self, poll: &mio::Poll, token: mio::Token) -> Result<(), String> {
+        poll.register(&EventedFd(&libc::STDIN_FILENO), token, mio::Ready::readable(), mio::PollOpt::level())
+            .map_err(|e| format!("Couldn't register stdin with Poll: {}", e))
+    }
+
+    fn deregister(&mut self, poll: &mio::Poll) {
+        let _ = poll.deregister(&EventedFd(&libc::STDIN_FILENO));
+    }
+
+    fn poll_once(&mut self, flag: &mut dyn ReadinessPager) -> bool {
+        let mut buffer = [0u8; 1024];
+        // As with the terminal UI, this goes around std::io::stdin()'s buffer.
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+        if n < 0 {
+            flag.err(format!("Couldn't read from stdin: {}", io::Error::last_os_error()));
+            return false;
+        }
+
+        self.partial.extend_from_slice(&buffer[..n as usize]);
+        while let Some(i) = self.partial.iter().position(|c| *c == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=i).collect();
+            let line = String::from_utf8_lossy(&line[..i]).trim_end_matches('\r').to_string();
+            if self.tx.send(StdioEvent::Line(line)).is_err() {
+                return false;
+            }
+        }
+
+        if n == 0 {
+            if !self.partial.is_empty() {
+                let line = String::from_utf8_lossy(&self.partial).to_string();
+                let _ = self.tx.send(StdioEvent::Line(line));
+            }
+            let _ = self.tx.send(StdioEvent::Eof);
+        }
+        flag.ok();
+        n > 0
+    }
+}
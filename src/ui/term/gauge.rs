@@ -0,0 +1,93 @@
+use crate::ansi::{Colour, Style, StyledLine};
+use crate::gauges::Reading;
+
+use super::Window;
+
+/// How wide gauges in the pane are, if they don't say.
+pub const PANE_WIDTH: usize = 20;
+
+/// A gauge drawn as its label and a bar, with the numbers over the bar:
+///
+/// ```text
+/// HP ███████45/100
+/// ```
+///
+/// The filled part of the bar is in the gauge's colour, and the rest is grey.
+pub struct Gauge {
+    pub reading: Reading,
+    width: usize,
+}
+
+impl Gauge {
+    pub fn new(reading: Reading, width: usize) -> Gauge {
+        Gauge { reading, width }
+    }
+
+    fn line(&self) -> StyledLine {
+        let mut line = StyledLine::unstyled(&format!("{} ", self.reading.label));
+        let bar_w = self.width.saturating_sub(self.reading.label.chars().count() + 1);
+
+        let (text, filled) = match (self.reading.value, self.reading.max) {
+            (Some(value), Some(max)) if max > 0.0 => {
+                let filled = (bar_w as f64 * (value / max).clamp(0.0, 1.0)).round() as usize;
+                (format!("{}/{}", value, max), filled)
+            },
+            (Some(value), _) => (format!("{}", value), 0),
+            _ => ("?".to_string(), 0),
+        };
+
+        // Centre the numbers on the bar, and colour its cells in according to how full it is.
+        let text: Vec<char> = text.chars().collect();
+        let start = bar_w.saturating_sub(text.len()) / 2;
+        let full = Style { bg: Some(self.reading.colour), fg: Some(Colour::Indexed(15)), ..Style::default() };
+        let empty = Style { bg: Some(Colour::Indexed(8)), fg: Some(Colour::Indexed(15)), ..Style::default() };
+        for i in 0..bar_w {
+            let c = i.checked_sub(start).and_then(|j| text.get(j)).copied().unwrap_or(' ');
+            line.push(&c.to_string(), if i < filled { full } else { empty });
+        }
+        line
+    }
+}
+
+impl Window for Gauge {
+    fn render(&self) -> Vec<String> {
+        vec![self.line().to_ansi()]
+    }
+
+    fn get_size(&self) -> (usize, usize) {
+        (self.width, 1)
+    }
+
+    fn get_cursor_pos(&self) -> (usize, usize) {
+        (0, 0)
+    }
+
+    fn set_width(&mut self, new_w: usize) {
+        self.width = new_w;
+    }
+
+    fn set_height(&mut self, _new_h: usize) {
+        // Gauges are always one line high.
+    }
+}
+
+#[test]
+fn draws_gauges() {
+    use crate::gauges::Place;
+
+    let mut gauge = Gauge::new(Reading {
+        label: "HP".to_string(),
+        value: Some(5.0),
+        max: Some(10.0),
+        colour: Colour::Indexed(1),
+        place: Place::Bottom,
+        width: None,
+    }, 13);
+    assert_eq!(gauge.line().plain(), "HP    5/10   ");
+    assert_eq!(gauge.line().spans[1].style.bg, Some(Colour::Indexed(1)));
+    assert_eq!(gauge.line().style_at(8), Style { bg: Some(Colour::Indexed(8)), fg: Some(Colour::Indexed(15)), ..Style::default() });
+
+    gauge.reading.value = None;
+    gauge.set_width(7);
+    assert_eq!(gauge.render(), vec!["HP \x1b[0;97;100m ?  \x1b[0m".to_string()]);
+}
@@ -3,6 +3,7 @@ use std::sync::mpsc;
 use std::sync::mpsc::{Sender, Receiver};
 use std::io::{Write, stdout, stdin};
 use std::io;
+use std::collections::{BTreeMap, BTreeSet};
 
 use termion::event::Key;
 use termion::raw::IntoRawMode;
@@ -10,13 +11,17 @@ use termion::screen::AlternateScreen;
 use termion::input::TermRead;
 
 use signal_hook::iterator::Signals;
+use mio::unix::EventedFd;
 
 // TODO: We should just scrape the `Command' type out. It's pointless indirection and introduces
 // confusion as to what Commands even are, plus the possibility to break stuff less-obviously by
 // changing it.
-use crate::meta::{Event, EventSource, ReadinessPager, Listener};
+use crate::ansi::{Style, StyledLine};
+use crate::gauges::{Place, Reading};
+use crate::meta::{Event, EventSource, ReadinessPager, Listener, Pollable, Recovery, Stopper};
 use crate::ui::{UserInterface, Command};
 
+mod gauge;
 mod input;
 mod screen;
 mod text;
@@ -64,22 +69,40 @@ pub struct TermUiManager {
     view: text::WrappedView,
 
     input: input::InputLine,
+
+    // Worlds that something has happened in since the user last typed anything.
+    flagged: BTreeSet<String>,
+
+    // Panes, like the map, drawn one above the other down the right of the view.
+    panes: BTreeMap<String, Vec<StyledLine>>,
+
+    // The fields on the status line between the view and the input line, if there is one.
+    status: Vec<String>,
+
+    gauges: Vec<gauge::Gauge>,
 }
 
+// The view needs to keep at least this much room, or the panes don't get drawn.
+const MIN_VIEW_WIDTH: usize = 40;
+
 impl TermUiManager {
     /// Create a new TermUiManager.  NB: This will expect to be the only TermUiManager, and to be
     /// able to grab a stdout() instance, write to that instance (clearing/setting up the terminal)
     /// and construct the TermUiManager object with ownership of it.
-    pub fn new() -> TermUiManager {
+    pub fn new() -> Result<TermUiManager, String> {
         let (tx, rx) = mpsc::channel();
 
-        let (term_w, term_h) = termion::terminal_size().unwrap();
+        let (term_w, term_h) = termion::terminal_size()
+            .map_err(|e| format!("Couldn't get the terminal size: {}", e))?;
 
-        let mut stdout = AlternateScreen::from(stdout().into_raw_mode().unwrap());
-        write!(stdout, "{}{}", termion::clear::All, termion::cursor::Hide).unwrap();
-        stdout.flush().unwrap();
+        let raw = stdout().into_raw_mode()
+            .map_err(|e| format!("Couldn't put the terminal into raw mode: {}", e))?;
+        let mut stdout = AlternateScreen::from(raw);
+        write!(stdout, "{}{}", termion::clear::All, termion::cursor::Hide)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| format!("Couldn't write to the terminal: {}", e))?;
 
-        TermUiManager {
+        Ok(TermUiManager {
             stdout,
             rx,
             tx_template: tx,
@@ -87,7 +110,11 @@ impl TermUiManager {
             db: screen::DamageBuffer::new(term_w as usize, term_h as usize),
             view: text::WrappedView::new(term_w as usize, term_h as usize),
             input: input::InputLine::new(term_w as usize, term_h as usize),
-        }
+            flagged: BTreeSet::new(),
+            panes: BTreeMap::new(),
+            status: vec![],
+            gauges: vec![],
+        })
     }
 }
 
@@ -100,17 +127,24 @@ impl Drop for TermUiManager {
 }
 
 impl EventSource for TermUiManager {
-    fn get_listeners(&mut self) -> Vec<Box<Listener>> {
+    fn get_listeners(&mut self) -> Vec<Box<dyn Listener>> {
         vec![
             Box::new(TermionListener {
                 tx: self.tx_template.clone(),
             }),
-            Box::new(ResizeListener {
-                tx: self.tx_template.clone(),
-            }),
+            Box::new(ResizeListener::new(self.tx_template.clone())),
         ]
     }
 
+    fn listener_failed(&mut self, which: usize, _why: &str) -> Recovery {
+        match which {
+            // Without keyboard input the client is useless, but if we just stop hearing about
+            // resizes, the worst that happens is that the screen gets a bit ugly.
+            1 => Recovery::Abandon,
+            _ => Recovery::Fatal,
+        }
+    }
+
     fn process(&mut self) -> Vec<Event> {
         // The events from the thread in this case will be either terminal resize or some kind of
         // event from Termion---key, maybe eventually mouse, whatever.  So, when this is called
@@ -146,10 +180,15 @@ impl EventSource for TermUiManager {
                                 which: 0,
                             });
                             self.input.set_string("".to_string());
+                            // If they're typing, they've seen whatever there was to see.
+                            self.flagged.clear();
                             self.redraw();
                         },
                         Key::Char(chr) => { self.input.insert_char(chr) },
 
+                        Key::PageUp    => { self.view.scroll((self.term_size.1 / 2) as isize) },
+                        Key::PageDown  => { self.view.scroll(-((self.term_size.1 / 2) as isize)) },
+
                         Key::Ctrl('h') => { self.input.delete_chars(-1) },
                         Key::Ctrl('d') => { self.input.delete_chars(1) },
 
@@ -181,9 +220,123 @@ impl UserInterface for TermUiManager {
     fn register_command(&mut self, _c: Command) {
         // TODO
     }
+
+    fn alert(&mut self, world: String, bell: bool, flag: bool) {
+        if flag && self.flagged.insert(world) {
+            self.draw_status();
+        }
+        if bell {
+            write!(self.stdout, "\x07").unwrap();
+            self.stdout.flush().unwrap();
+        }
+    }
+
+    fn show_pane(&mut self, name: String, lines: Vec<String>) {
+        if lines.is_empty() {
+            self.panes.remove(&name);
+        } else {
+            let lines = lines.iter().map(|line| StyledLine::parse(line, Style::default()).0).collect();
+            self.panes.insert(name, lines);
+        }
+        self.redraw();
+    }
+
+    fn set_status(&mut self, fields: Vec<String>) {
+        let resized = fields.is_empty() != self.status.is_empty();
+        self.status = fields;
+        // Only the status line itself needs drawing again, unless it's appeared or gone.
+        if resized {
+            self.redraw();
+        } else {
+            self.draw_status();
+        }
+    }
+
+    fn set_gauges(&mut self, gauges: Vec<Reading>) {
+        // Gauges on a line get their widths when it's drawn.
+        self.gauges = gauges.into_iter()
+            .map(|reading| {
+                let width = reading.width.unwrap_or(gauge::PANE_WIDTH);
+                gauge::Gauge::new(reading, width)
+            })
+            .collect();
+        self.redraw();
+    }
 }
 
 impl TermUiManager {
+    /// The status line, `w` characters wide.
+    fn status_line(&self, w: usize) -> StyledLine {
+        let below = self.view.lines_below();
+        let scroll = if below > 0 { format!("{} more", below) } else { String::new() };
+        let mut fields: Vec<String> = self.status.iter().map(|field| field.replace("{scroll}", &scroll)).collect();
+        if !self.flagged.is_empty() {
+            fields.push(format!("activity: {}", self.flagged.iter().cloned().collect::<Vec<_>>().join(", ")));
+        }
+
+        let text: Vec<&str> = fields.iter().map(|field| field.trim()).filter(|field| !field.is_empty()).collect();
+        let mut line = StyledLine::default();
+        line.push(&format!(" {}", text.join(" | ")), Style { reverse: true, ..Style::default() });
+        let len = line.plain().chars().count();
+        if len < w {
+            line.push(&" ".repeat(w - len), Style { reverse: true, ..Style::default() });
+        }
+        text::force_width_styled(line, w)
+    }
+
+    /// The gauges that go at the top or the bottom, laid out on a line `w` characters wide: those
+    /// without widths of their own share out what's left.
+    fn gauge_line(&mut self, place: Place, w: usize) -> Option<StyledLine> {
+        let mut gauges: Vec<&mut gauge::Gauge> = self.gauges.iter_mut().filter(|g| g.reading.place == place).collect();
+        if gauges.is_empty() {
+            return None;
+        }
+
+        let fixed: usize = gauges.iter().filter_map(|g| g.reading.width).sum();
+        let flexible = gauges.iter().filter(|g| g.reading.width.is_none()).count();
+        let gaps = gauges.len() - 1;
+        let share = w.saturating_sub(fixed + gaps).checked_div(flexible).unwrap_or(0);
+
+        let mut line = StyledLine::default();
+        for (i, gauge) in gauges.iter_mut().enumerate() {
+            if i > 0 {
+                line.push(" ", Style::default());
+            }
+            gauge.set_width(gauge.reading.width.unwrap_or(share));
+            for rendered in gauge.render() {
+                line.append(&StyledLine::parse(&rendered, Style::default()).0);
+            }
+        }
+        Some(text::force_width_styled(line, w))
+    }
+
+    /// Draw just the status line, without redrawing everything else.
+    fn draw_status(&mut self) {
+        let (w, h) = self.term_size;
+        let status_y = h.checked_sub(self.input.get_size().1 + 1);
+        if let (false, Some(y)) = (self.status.is_empty(), status_y) {
+            let line = self.status_line(w);
+            self.db.write_styled(0, y, &line);
+            write!(self.stdout, "{}", termion::cursor::Hide).unwrap();
+            self.flush();
+        }
+    }
+
+    /// Write out whatever's changed in the damage buffer, and put the cursor back on the input
+    /// line.
+    fn flush(&mut self) {
+        let input_y = self.term_size.1.saturating_sub(self.input.get_size().1);
+        self.db.redraw(&mut self.stdout).unwrap();
+
+        let (cursor_x, cursor_y) = self.input.get_cursor_pos();
+        let cursor_x = cursor_x as u16;
+        let cursor_y = cursor_y as u16;
+        write!(self.stdout, "{}{}", termion::cursor::Show,
+                                    termion::cursor::Goto(cursor_x + 1, cursor_y + 1 + input_y as u16)).unwrap();
+
+        self.stdout.flush().unwrap();
+    }
+
     fn redraw(&mut self) {
         // Render everything and just write it wholesale to the damage buffer.
         // Underlying assumption: CPU is much cheaper than I/O to the terminal for the
@@ -196,20 +349,61 @@ impl TermUiManager {
 
         self.input.set_width(w);
 
+        let top = self.gauge_line(Place::Top, w);
+        let bottom = self.gauge_line(Place::Bottom, w);
+
+        // From the top: a line of gauges, the view, another line of gauges, the status line and
+        // the input line, leaving out whichever of them there's nothing to put on.
         let edit_h = self.input.get_size().1;
-        let view_h: usize = if edit_h < h {
-            h - edit_h
+        let status_h = if self.status.is_empty() { 0 } else { 1 };
+        let top_h = if top.is_some() { 1 } else { 0 };
+        let bottom_h = if bottom.is_some() { 1 } else { 0 };
+        let fixed_h = edit_h + status_h + top_h + bottom_h;
+        let view_h = h.saturating_sub(fixed_h);
+
+        // The panes go on the right, with a line between them and the view, if there's room.
+        // Gauges placed in the pane go below the others.
+        let mut side: Vec<StyledLine> = self.panes.values().flatten().cloned().collect();
+        for gauge in self.gauges.iter().filter(|g| g.reading.place == Place::Pane) {
+            side.extend(gauge.render().iter().map(|line| StyledLine::parse(line, Style::default()).0));
+        }
+        let pane_w = side.iter().map(|line| line.plain().chars().count()).max().unwrap_or(0);
+        let view_w = if pane_w > 0 && w > MIN_VIEW_WIDTH + pane_w {
+            w - pane_w - 1
         } else {
-            0
+            w
         };
 
+        if let (Some(line), true) = (top, view_h > 0) {
+            self.db.write_styled(0, 0, &line);
+        }
+
         if view_h > 0 {
             // TODO: This should also take a Size type.
-            self.view.resize(self.term_size.0, view_h);
+            self.view.resize(view_w, view_h);
 
-            for (y, line) in self.view.render().into_iter().enumerate() {
-                self.db.write_string(0, y, line);
+            for (y, line) in self.view.render().iter().enumerate() {
+                self.db.write_styled(0, top_h + y, line);
             }
+
+            if view_w < w {
+                let mut pane_lines = side.into_iter()
+                    .map(|line| text::force_width_styled(line, pane_w));
+                for y in top_h..top_h + view_h {
+                    let line = pane_lines.next().unwrap_or_else(|| StyledLine::unstyled(&" ".repeat(pane_w)));
+                    self.db.write_string(view_w, y, "\u{2502}".to_string());
+                    self.db.write_styled(view_w + 1, y, &line);
+                }
+            }
+        }
+
+        if let (Some(line), true) = (bottom, view_h > 0) {
+            self.db.write_styled(0, top_h + view_h, &line);
+        }
+
+        if status_h > 0 && edit_h < h {
+            let line = self.status_line(w);
+            self.db.write_styled(0, h - edit_h - 1, &line);
         }
 
         let input_y = h - edit_h;
@@ -217,17 +411,9 @@ impl TermUiManager {
             self.db.write_string(0, input_y + y, line);
         }
 
-        // Tell the damage buffer to terminal-update.
-        self.db.redraw(&mut self.stdout).unwrap();
-
-        // Restore the cursor to a correct position...
-        let (cursor_x, cursor_y) = self.input.get_cursor_pos();
-        let cursor_x = cursor_x as u16;
-        let cursor_y = cursor_y as u16;
-        write!(self.stdout, "{}{}", termion::cursor::Show,
-                                    termion::cursor::Goto(cursor_x + 1, cursor_y + 1 + input_y as u16)).unwrap();
-
-        self.stdout.flush().unwrap();
+        // Tell the damage buffer to terminal-update, and restore the cursor to a correct
+        // position.
+        self.flush();
     }
 }
 
@@ -240,31 +426,184 @@ enum TermEvent {
 /// Listener for terminal resize events.
 struct ResizeListener {
     tx: Sender<TermEvent>,
+    // Used to wake the listener up from poll() when it's time to stop.
+    stop_alert: mio::Registration,
+    stop_sr: mio::SetReadiness,
+    // Only used when we're being driven as a Pollable.
+    sigs: Option<Signals>,
 }
 
-impl Listener for ResizeListener {
-    fn run(&mut self, mut flag: Box<ReadinessPager>) {
-        let sigs = Signals::new(&[libc::SIGWINCH]).expect("Couldn't create Signals iterator");
-        for _signal in sigs.forever() {
-            self.tx.send(TermEvent::Resize).expect("error sending TermEvent::Resize");
+impl ResizeListener {
+    fn new(tx: Sender<TermEvent>) -> ResizeListener {
+        let (stop_alert, stop_sr) = mio::Registration::new2();
+        ResizeListener { tx, stop_alert, stop_sr, sigs: None }
+    }
+
+    /// Pass on any resizes that have happened.  Returns false if nobody's listening anymore.
+    fn check(&self, sigs: &Signals, flag: &mut dyn ReadinessPager) -> bool {
+        // Several resizes in a row only need one redraw.
+        if sigs.pending().count() > 0 {
+            if self.tx.send(TermEvent::Resize).is_err() {
+                // The TermUiManager is gone, so nobody cares anymore.
+                return false;
+            }
             flag.ok();
         }
+        true
     }
 }
 
-/// Listener for termion (e.g., key, mouse, etc.) events.
+const SIGNAL_TOKEN: mio::Token = mio::Token(0);
+const STOP_TOKEN: mio::Token = mio::Token(1);
+
+impl Listener for ResizeListener {
+    fn name(&self) -> String {
+        "terminal resize listener".to_string()
+    }
+
+    fn stopper(&mut self) -> Option<Box<dyn Stopper>> {
+        Some(Box::new(ResizeStopper {
+            stop_sr: self.stop_sr.clone(),
+        }))
+    }
+
+    fn pollable(&mut self) -> Option<&mut dyn Pollable> {
+        Some(self)
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) {
+        let sigs = match Signals::new([libc::SIGWINCH]) {
+            Ok(sigs) => sigs,
+            Err(e) => { return flag.err(format!("Couldn't listen for SIGWINCH: {}", e)); },
+        };
+
+        let poll = match mio::Poll::new() {
+            Ok(poll) => poll,
+            Err(e) => { return flag.err(format!("Couldn't create Poll: {}", e)); },
+        };
+        let registered = poll.register(&sigs, SIGNAL_TOKEN, mio::Ready::readable(), mio::PollOpt::level())
+            .and_then(|_| poll.register(&self.stop_alert, STOP_TOKEN, mio::Ready::readable(), mio::PollOpt::edge()));
+        if let Err(e) = registered {
+            return flag.err(format!("Couldn't register with Poll: {}", e));
+        }
+
+        let mut events = mio::Events::with_capacity(8);
+        loop {
+            if let Err(e) = poll.poll(&mut events, None) {
+                return flag.err(format!("Couldn't poll for signals: {}", e));
+            }
+
+            for event in &events {
+                if event.token() == STOP_TOKEN {
+                    return;
+                }
+            }
+
+            if !self.check(&sigs, &mut *flag) {
+                return;
+            }
+        }
+    }
+}
+
+impl Pollable for ResizeListener {
+    fn register(&mut self, poll: &mio::Poll, token: mio::Token) -> Result<(), String> {
+        let sigs = Signals::new([libc::SIGWINCH]).map_err(|e| format!("Couldn't listen for SIGWINCH: {}", e))?;
+        poll.register(&sigs, token, mio::Ready::readable(), mio::PollOpt::level())
+            .map_err(|e| format!("Couldn't register with Poll: {}", e))?;
+        self.sigs = Some(sigs);
+        Ok(())
+    }
+
+    fn poll_once(&mut self, flag: &mut dyn ReadinessPager) -> bool {
+        match self.sigs {
+            Some(ref sigs) => self.check(sigs, flag),
+            None => false,
+        }
+    }
+}
+
+/// Stopper for ResizeListener.
+struct ResizeStopper {
+    stop_sr: mio::SetReadiness,
+}
+
+impl Stopper for ResizeStopper {
+    fn stop(&mut self) {
+        let _ = self.stop_sr.set_readiness(mio::Ready::readable());
+    }
+}
+
+/// Listener for termion (e.g., key, mouse, etc.) events.  This one can't be stopped, since it
+/// spends its life blocked in a read() on stdin.
 struct TermionListener {
     tx: Sender<TermEvent>,
 }
 impl Listener for TermionListener {
-    fn run(&mut self, mut flag: Box<ReadinessPager>) {
+    fn name(&self) -> String {
+        "keyboard input listener".to_string()
+    }
+
+    fn pollable(&mut self) -> Option<&mut dyn Pollable> {
+        Some(self)
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) {
         let stdin = stdin();
         for c in stdin.keys() {
-            // TODO: In the future, when we have better error handling for EventManaged
-            // threads, bounce this back to the parent thread and let it crash properly....?
-            let c = c.expect("Couldn't read from stdin?!");
-            self.tx.send(TermEvent::Input { key: c }).expect("error sending TermEvent::Input");
+            let c = match c {
+                Ok(c) => c,
+                Err(e) => { return flag.err(format!("Couldn't read from stdin: {}", e)); },
+            };
+            if self.tx.send(TermEvent::Input { key: c }).is_err() {
+                // The TermUiManager is gone, so nobody cares anymore.
+                return;
+            }
             flag.ok();
         }
     }
 }
+
+/// When polled, we can't let termion read from stdin by itself, since it would block waiting for
+/// more than is available.  Instead, we read whatever's there and let termion parse that.
+impl Pollable for TermionListener {
+    fn register(&mut self, poll: &mio::Poll, token: mio::Token) -> Result<(), String> {
+        poll.register(&EventedFd(&libc::STDIN_FILENO), token, mio::Ready::readable(), mio::PollOpt::level())
+            .map_err(|e| format!("Couldn't register stdin with Poll: {}", e))
+    }
+
+    fn deregister(&mut self, poll: &mio::Poll) {
+        let _ = poll.deregister(&EventedFd(&libc::STDIN_FILENO));
+    }
+
+    fn poll_once(&mut self, flag: &mut dyn ReadinessPager) -> bool {
+        let mut buffer = [0u8; 1024];
+        // Going around std::io::stdin() here, since it has a buffer of its own that poll() can't
+        // see into.
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+        if n < 0 {
+            flag.err(format!("Couldn't read from stdin: {}", io::Error::last_os_error()));
+            return false;
+        }
+
+        for c in buffer[..n as usize].keys() {
+            let c = match c {
+                Ok(c) => c,
+                Err(e) => {
+                    flag.err(format!("Couldn't parse input from stdin: {}", e));
+                    return false;
+                },
+            };
+            if self.tx.send(TermEvent::Input { key: c }).is_err() {
+                return false;
+            }
+        }
+
+        // Reading zero bytes means stdin is closed.
+        if n == 0 {
+            return false;
+        }
+        flag.ok();
+        true
+    }
+}
@@ -1,6 +1,8 @@
 use std::io::Write;
 use std::collections::BTreeSet;
 
+use crate::ansi::{Style, StyledLine};
+
 // also uses termion. TODO: Import at top level of term module? Would that even work?
 
 // Note: Rust docs say std::cmp::PartialOrd is derivable and will produce a lexicographic ordering
@@ -39,6 +41,21 @@ fn point_order() {
 }
 
 
+/// What's in one cell of the screen.
+#[derive(Clone, PartialEq)]
+struct Cell {
+    // This was chosen to be String not Char because some Unicode characters can take up multiple
+    // chars and so why not
+    text: String,
+    style: Style,
+}
+
+impl Cell {
+    fn blank() -> Cell {
+        Cell { text: " ".to_string(), style: Style::default() }
+    }
+}
+
 /// Very work-in-progress 'damage buffer' type of display.
 pub struct DamageBuffer {
     points_to_draw: BTreeSet<Point>,
@@ -47,16 +64,14 @@ pub struct DamageBuffer {
 
     w: usize,
     h: usize,
-    // This was chosen to be String not Char because some Unicode characters can take up multiple
-    // chars and so why not
-    buffer: Vec<String>,
+    buffer: Vec<Cell>,
 }
 
 impl DamageBuffer {
     pub fn new(w: usize, h: usize) -> DamageBuffer {
         let buffer = DamageBuffer {
             w, h,
-            buffer: std::iter::repeat(" ".to_string()).take(w*h).collect(),
+            buffer: std::iter::repeat_n(Cell::blank(), w*h).collect(),
             points_to_draw: BTreeSet::new(),
             redraw_all: false,
             clear_all: false,
@@ -66,9 +81,7 @@ impl DamageBuffer {
     }
 
     pub fn clear(&mut self) {
-        self.buffer = std::iter::repeat(" ".to_string())
-            .take(self.w * self.h)
-            .collect();
+        self.buffer = std::iter::repeat_n(Cell::blank(), self.w * self.h).collect();
         self.points_to_draw.clear();
         self.redraw_all = false;
         self.clear_all = true;
@@ -77,28 +90,43 @@ impl DamageBuffer {
     pub fn resize(&mut self, new_w: usize, new_h: usize) {
         self.w = new_w;
         self.h = new_h;
-        self.buffer.resize(self.w * self.h, " ".to_string());
+        self.buffer.resize(self.w * self.h, Cell::blank());
         self.redraw_all = true;
     }
 
     pub fn write_string(&mut self, x: usize, y: usize, what: String) {
+        self.write_chars(x, y, &what, Style::default());
+    }
+
+    /// Write some styled text, starting at (x, y).
+    pub fn write_styled(&mut self, x: usize, y: usize, what: &StyledLine) {
+        let mut x = x;
+        for span in &what.spans {
+            x = self.write_chars(x, y, &span.text, span.style);
+        }
+    }
+
+    /// Write `what` in `style`, starting at (x, y), and return the x just past the end of it.
+    fn write_chars(&mut self, x: usize, y: usize, what: &str, style: Style) -> usize {
         let mut x = x;
 
         for c in what.chars() {
             if x < self.w && y < self.h {
-                let c = c.to_string();
+                let cell = Cell { text: c.to_string(), style };
                 // We're indexing into a 2D grid laid out row by row in a 1D memory buffer.  So we
                 // compute the 1D index by multiplying y by the row length, then adding x (the
                 // offset inside that row.)
                 let i = y * self.w + x;
 
-                if c != self.buffer[i] {
-                    self.buffer[i] = c;
+                if cell != self.buffer[i] {
+                    self.buffer[i] = cell;
                     self.points_to_draw.insert(Point { x, y });
                 }
             }
             x += 1;
         }
+
+        x
     }
 
     pub fn redraw(&mut self, term: &mut impl Write) -> std::io::Result<()> {
@@ -117,6 +145,11 @@ impl DamageBuffer {
         // I could probably make a closure then call for_each() in two places depending on the
         // branch, but that seems like it'd be slower.  I should probably try doing it anyway.
 
+        // The terminal keeps whatever style we last set until we change it, cursor movements and
+        // all, so we only need to send a new one when the next cell looks different.
+        let mut style = Style::default();
+        term.write_all(b"\x1b[0m")?;
+
         if self.clear_all {
             term.write(format!("{}", termion::clear::All).as_bytes())?;
         }
@@ -128,7 +161,12 @@ impl DamageBuffer {
                         term.write(format!("{}", termion::cursor::Goto((x+1) as u16, (y+1) as u16)).as_bytes())?;
                     }
 
-                    term.write(format!("{}", self.buffer[y * self.w + x]).as_bytes())?;
+                    let cell = &self.buffer[y * self.w + x];
+                    if cell.style != style {
+                        style = cell.style;
+                        term.write_all(style.to_sgr().as_bytes())?;
+                    }
+                    term.write_all(cell.text.as_bytes())?;
                     last_point.x = x; last_point.y = y;
                 }
             }
@@ -142,11 +180,20 @@ impl DamageBuffer {
                     term.write(format!("{}", termion::cursor::Goto((x+1) as u16, (y+1) as u16)).as_bytes())?;
                 }
 
-                term.write(format!("{}", self.buffer[y * self.w + x]).as_bytes())?;
+                let cell = &self.buffer[y * self.w + x];
+                if cell.style != style {
+                    style = cell.style;
+                    term.write_all(style.to_sgr().as_bytes())?;
+                }
+                term.write_all(cell.text.as_bytes())?;
                 last_point.x = *x; last_point.y = *y;
             }
         }
 
+        if style != Style::default() {
+            term.write_all(b"\x1b[0m")?;
+        }
+
         self.points_to_draw.clear();
         self.redraw_all = false;
         self.clear_all = false;
@@ -1,5 +1,7 @@
 use fnv::FnvHashMap;
 
+use crate::ansi::{Style, StyledLine};
+
 
 /// Return a version of `text` that is exactly `width` chars long.  Truncates if it is too long,
 /// and appends space characters if it is not long enough.
@@ -16,6 +18,22 @@ pub fn force_width(mut text: String, width: usize) -> String {
     text
 }
 
+/// The same as force_width(), but for styled text.  Any padding is in the default style.
+pub fn force_width_styled(text: StyledLine, width: usize) -> StyledLine {
+    let plain = text.plain();
+    let mut text = match plain.char_indices().nth(width) {
+        Some((cut, _)) => text.slice(0..cut),
+        None => text,
+    };
+
+    let len = plain.chars().count();
+    if len < width {
+        text.push(&" ".repeat(width - len), Style::default());
+    }
+
+    text
+}
+
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 struct FmtOpts {
@@ -27,12 +45,26 @@ struct FmtOpts {
 
 #[derive(Clone)]
 struct ScreenLine {
-    text: String,
+    text: StyledLine,
     for_opts: FmtOpts,
 }
 
-fn format(text: String, opts: FmtOpts) -> Vec<ScreenLine> {
+/// Build one screen line out of an indent and the part of `styled` between `from` and `to`, with
+/// leading whitespace removed.
+fn screen_line(indent: &str, styled: &StyledLine, text: &str, from: usize, to: usize, width: usize) -> StyledLine {
+    let chunk = &text[from..to];
+    let from = from + (chunk.len() - chunk.trim_start().len());
+
+    let mut line = StyledLine::unstyled(indent);
+    line.append(&styled.slice(from..to));
+    force_width_styled(line, width)
+}
+
+fn format(styled: &StyledLine, opts: FmtOpts) -> Vec<ScreenLine> {
     let mut result = vec![];
+    // We work out where to break the line using the text by itself, then cut the styled version
+    // up in the same places.
+    let text = styled.plain();
 
     // We want to walk through the string and, so long as the amount of space it takes up so
     // far (since the last time we specified 'this should break here') is less than our view
@@ -106,28 +138,30 @@ fn format(text: String, opts: FmtOpts) -> Vec<ScreenLine> {
         // text.  I suspect that might never happen, but I'm not like 100% confident and there's
         // not much to lose. 
         while width_so_far - last_breakpoint > target_width {
-            // We build our line by just cloning the appropriate amount of leading
-            // whitespace to start with, then pushing the line itself onto the end.
-            let mut line: String = match last_breakpoint {
-                0 => indent_first.clone(),
-                _ => indent_rest.clone(),
+            // We build our line by starting with the appropriate amount of leading whitespace,
+            // then adding the line itself onto the end.
+            let indent = match last_breakpoint {
+                0 => &indent_first,
+                _ => &indent_rest,
             };
 
             // If we have a whitespace point break there, but otherwise just break right
             // where we are (in the middle of, presumably, a long word) as there are no
             // other options at that point.
-            if last_whitespace > last_breakpoint {
-                line.push_str(text[last_breakpoint_idx..last_whitespace_idx].trim_start());
+            let line = if last_whitespace > last_breakpoint {
+                let line = screen_line(indent, styled, &text, last_breakpoint_idx, last_whitespace_idx, opts.w);
                 last_breakpoint = last_whitespace;
                 last_breakpoint_idx = last_whitespace_idx;
+                line
             } else {
-                line.push_str(text[last_breakpoint_idx..idx].trim_start());
+                let line = screen_line(indent, styled, &text, last_breakpoint_idx, idx, opts.w);
                 last_breakpoint = width_so_far;
                 last_breakpoint_idx = idx;
-            }
+                line
+            };
 
             result.push(ScreenLine {
-                text: force_width(line, opts.w),
+                text: line,
                 for_opts: opts,
             });
         }
@@ -136,17 +170,16 @@ fn format(text: String, opts: FmtOpts) -> Vec<ScreenLine> {
     // We still need to push the very last line... but fortunately, we still have
     // last_breakpoint_idx and can just take whatever's left over after that point.
     let last_chunk: &str = text.split_at(last_breakpoint_idx).1.trim_start();
-    if last_chunk.len() > 0 {
+    if !last_chunk.is_empty() {
         // We still have to decide which of these we need, because some lines are short
         // enough that they're only pushed once, here.
-        let mut last_line: String = match last_breakpoint {
-            0 => indent_first.clone(),
-            _ => indent_rest.clone(),
+        let indent = match last_breakpoint {
+            0 => &indent_first,
+            _ => &indent_rest,
         };
 
-        last_line.push_str(last_chunk);
         result.push(ScreenLine {
-            text: force_width(last_line, opts.w),
+            text: screen_line(indent, styled, &text, last_breakpoint_idx, text.len(), opts.w),
             for_opts: opts,
         });
     }
@@ -158,9 +191,9 @@ fn format(text: String, opts: FmtOpts) -> Vec<ScreenLine> {
     //
     // Anyway, it's possible to get here and still only have vec![] for the result.  If that
     // happens we're going to return a blank line instead of nothing.
-    if result.len() == 0 {
+    if result.is_empty() {
         result.push(ScreenLine {
-            text: "".to_string(),
+            text: StyledLine::default(),
             for_opts: opts,
         });
     }
@@ -178,7 +211,7 @@ pub struct WrappedView {
     // the highest index.  We're usually going to be going in reverse chronological order because
     // we draw up from the bottom of the view and new lines appear on the bottom of the view; it's
     // a chat program, after all.
-    history: Vec<String>,
+    history: Vec<StyledLine>,
 
     // We store a _cache_ of the results of word-wrapping each of the history lines to our view
     // settings (stored in self.fmt) so that we're not calling the relatively expensive
@@ -222,11 +255,11 @@ impl WrappedView {
 
     /// Add a line to the View.
     ///
-    /// This function expects that its argument will, logically, be a single line.  If you pass it
-    /// a line with `\n`, `\r` or potentially other similar control characters included, it will
-    /// remove them.
-    pub fn push(&mut self, mut line: String) {
-        line.retain(|c| c != '\n' && c != '\r');
+    /// This function expects that its argument will, logically, be a single line, starting in the
+    /// default style.  ANSI colour codes are interpreted; `\n`, `\r` and other control characters
+    /// are removed.
+    pub fn push(&mut self, line: String) {
+        let (line, _) = StyledLine::parse(&line, Style::default());
 
         let current_histlen = self.history.len();
         self.history.push(line);
@@ -240,6 +273,26 @@ impl WrappedView {
         }
     }
 
+    /// Scroll back through the history by `lines` history lines, or forward if it's negative.
+    /// (Whole history lines, since that's how the scroll position is kept.)
+    pub fn scroll(&mut self, lines: isize) {
+        if self.history.is_empty() {
+            return;
+        }
+        let last = self.history.len() - 1;
+        self.position.0 = if lines > 0 {
+            self.position.0.saturating_sub(lines as usize)
+        } else {
+            (self.position.0 + lines.unsigned_abs()).min(last)
+        };
+        self.position.1 = 0;
+    }
+
+    /// How many history lines there are below the bottom of the view.
+    pub fn lines_below(&self) -> usize {
+        self.history.len().saturating_sub(self.position.0 + 1)
+    }
+
     /// Internal function: Fetch the list of word-wrapped lines representing a single logical line,
     /// recomputing only if necessary.  Called on a history index and not a String.
     fn wrap(&mut self, line: usize) -> Option<Vec<ScreenLine>> {
@@ -255,15 +308,15 @@ impl WrappedView {
 
         // If we got here, either it hasn't been calculated yet or we changed the format options,
         // which means we'd better recompute.
-        let new_lines = format(self.history[line].clone(), self.fmt);
+        let new_lines = format(&self.history[line], self.fmt);
         self.cache.insert(line, new_lines.clone());
         Some(new_lines)
     }
 
-    /// Return a Vec of Strings representing what should currently be drawn on screen for
-    /// this view.  The Vec is guaranteed to be self.h items long (index 0 = top of view) and each
-    /// String attempts to be self.fmt.w `char`s wide.
-    pub fn render(&mut self) -> Vec<String> {
+    /// Return a Vec of lines representing what should currently be drawn on screen for this
+    /// view.  The Vec is guaranteed to be self.h items long (index 0 = top of view) and each line
+    /// attempts to be self.fmt.w `char`s wide.
+    pub fn render(&mut self) -> Vec<StyledLine> {
         let lines_wanted = self.h;
         let fmt = self.fmt;
 
@@ -273,10 +326,10 @@ impl WrappedView {
             // This does exactly what I want, but it's probably kind of hard to read.  In fact,
             // I've even kind of confused myself.  Sorry?
 
-            let v: Vec<String> = (0..self.position.0+1).rev().map(|i| {
+            let v: Vec<StyledLine> = (0..self.position.0+1).rev().flat_map(|i| {
                 // For every line in history, going backwards from the most recent...
                 self.wrap(i).expect("wrap(i) in render()").into_iter().rev()
-            }).flatten().map(|l| l.text).chain(std::iter::repeat(" ".repeat(fmt.w)))
+            }).map(|l| l.text).chain(std::iter::repeat(StyledLine::unstyled(&" ".repeat(fmt.w))))
               .take(lines_wanted).collect();
 
             // We needed to reverse the final iterator but take() isn't a DoubleEndedIterator.  So I
@@ -284,7 +337,7 @@ impl WrappedView {
             // doesn't hurt performance too much.
             v.into_iter().rev().collect()
         } else {
-            std::iter::repeat(" ".repeat(fmt.w)).take(self.h).collect()
+            std::iter::repeat_n(StyledLine::unstyled(&" ".repeat(fmt.w)), self.h).collect()
         }
     }
 }
@@ -0,0 +1,269 @@
+//! Client-side variables, which triggers, aliases and the user can all read and write.  Each one
+//! lives either in the global scope or in a world's scope, and can be marked to be saved so it's
+//! still there next time.
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The value of a variable.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl Value {
+    /// Work out what type of value some text the user typed is meant to be.
+    pub fn parse(text: &str) -> Value {
+        if let Ok(b) = text.parse() {
+            Value::Bool(b)
+        } else if let Ok(i) = text.parse() {
+            Value::Int(i)
+        } else if let Ok(f) = text.parse::<f64>() {
+            // Things like "inf" and "NaN" are more likely meant as words.
+            if f.is_finite() { Value::Float(f) } else { Value::Str(text.to_string()) }
+        } else {
+            Value::Str(text.to_string())
+        }
+    }
+
+    /// Convert a value from the config file, if it's a type that we have.
+    pub fn from_toml(value: &toml::Value) -> Option<Value> {
+        match value {
+            toml::Value::Boolean(b) => Some(Value::Bool(*b)),
+            toml::Value::Integer(i) => Some(Value::Int(*i)),
+            toml::Value::Float(f) => Some(Value::Float(*f)),
+            toml::Value::String(s) => Some(Value::Str(s.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Str(_) => "string",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// Which set of variables something's in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope<'a> {
+    Global,
+    World(&'a str),
+}
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub value: Value,
+    /// Whether this is saved when it changes.
+    pub persist: bool,
+}
+
+/// What gets saved: just the values of the variables marked to be.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SavedFile {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    global: BTreeMap<String, Value>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    worlds: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+/// All the variables there are.
+#[derive(Debug, Default, Clone)]
+pub struct Variables {
+    global: BTreeMap<String, Variable>,
+    worlds: BTreeMap<String, BTreeMap<String, Variable>>,
+}
+
+impl Variables {
+    pub fn new() -> Variables {
+        Variables::default()
+    }
+
+    fn scope(&self, scope: Scope) -> Option<&BTreeMap<String, Variable>> {
+        match scope {
+            Scope::Global => Some(&self.global),
+            Scope::World(world) => self.worlds.get(world),
+        }
+    }
+
+    fn scope_mut(&mut self, scope: Scope) -> &mut BTreeMap<String, Variable> {
+        match scope {
+            Scope::Global => &mut self.global,
+            Scope::World(world) => self.worlds.entry(world.to_string()).or_default(),
+        }
+    }
+
+    /// Look a variable up, in `world`'s scope first if there is one, then the global scope.
+    pub fn get(&self, world: Option<&str>, name: &str) -> Option<&Value> {
+        world.and_then(|world| self.scope(Scope::World(world))?.get(name))
+            .or_else(|| self.global.get(name))
+            .map(|var| &var.value)
+    }
+
+    /// Set a variable.  Returns true if it's one that should be saved, now or before.
+    pub fn set(&mut self, scope: Scope, name: &str, value: Value, persist: bool) -> bool {
+        let var = self.scope_mut(scope).entry(name.to_string())
+            .or_insert(Variable { value: Value::Bool(false), persist: false });
+        var.value = value;
+        var.persist |= persist;
+        var.persist
+    }
+
+    /// Set a variable unless it's already set.
+    pub fn set_default(&mut self, scope: Scope, name: &str, value: Value) {
+        self.scope_mut(scope).entry(name.to_string())
+            .or_insert(Variable { value, persist: false });
+    }
+
+    /// Remove a variable, returning it if it was there.
+    pub fn unset(&mut self, scope: Scope, name: &str) -> Option<Variable> {
+        self.scope_mut(scope).remove(name)
+    }
+
+    /// Everything in one scope, in order of name.
+    pub fn list(&self, scope: Scope) -> Vec<(&str, &Variable)> {
+        self.scope(scope).into_iter().flatten().map(|(name, var)| (name.as_str(), var)).collect()
+    }
+
+    /// Replace `$name` and `${name}` in `text` with the values of variables, looked up as for
+    /// get().  `$$` is a `$`, and anything else starting with `$`, including variables that
+    /// aren't set, is left as it is.
+    pub fn expand(&self, world: Option<&str>, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(i) = rest.find('$') {
+            out.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                out.push('$');
+                rest = after;
+                continue;
+            }
+
+            let (name, after) = match rest.strip_prefix('{') {
+                Some(braced) => match braced.find('}') {
+                    Some(end) => (&braced[..end], &braced[end + 1..]),
+                    None => ("", rest),
+                },
+                None => {
+                    let end = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+                    (&rest[..end], &rest[end..])
+                },
+            };
+
+            match self.get(world, name).filter(|_| is_name(name)) {
+                Some(value) => {
+                    out.push_str(&value.to_string());
+                    rest = after;
+                },
+                None => out.push('$'),
+            }
+        }
+
+        out.push_str(rest);
+        out
+    }
+
+    /// Load saved variables from `path`, marked to be saved again.  It's fine for there not to
+    /// be a file.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Couldn't read {}: {}", path.display(), e)),
+        };
+        let saved: SavedFile = toml::from_str(&text)
+            .map_err(|e| format!("{}: {}", path.display(), e.message()))?;
+
+        for (name, value) in saved.global {
+            self.set(Scope::Global, &name, value, true);
+        }
+        for (world, vars) in saved.worlds {
+            for (name, value) in vars {
+                self.set(Scope::World(&world), &name, value, true);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the variables marked to be saved to `path`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let saved_values = |vars: &BTreeMap<String, Variable>| -> BTreeMap<String, Value> {
+            vars.iter().filter(|(_, var)| var.persist)
+                .map(|(name, var)| (name.clone(), var.value.clone()))
+                .collect()
+        };
+        let saved = SavedFile {
+            global: saved_values(&self.global),
+            worlds: self.worlds.iter()
+                .map(|(world, vars)| (world.clone(), saved_values(vars)))
+                .filter(|(_, vars)| !vars.is_empty())
+                .collect(),
+        };
+
+        let text = toml::to_string(&saved).map_err(|e| format!("Couldn't save variables: {}", e))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Couldn't create {}: {}", dir.display(), e))?;
+        }
+        fs::write(path, text).map_err(|e| format!("Couldn't write {}: {}", path.display(), e))
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Whether `name` will do as a variable name: letters, digits and underscores, not starting with
+/// a digit (since `$1` and so on are for capture groups.)
+pub fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && name.chars().all(is_name_char)
+}
+
+#[test]
+fn stores_and_expands_variables() {
+    let mut vars = Variables::new();
+    vars.set(Scope::Global, "target", Value::parse("rat"), false);
+    vars.set(Scope::Global, "hp_low", Value::parse("50"), true);
+    vars.set(Scope::World("mume"), "target", Value::parse("orc"), false);
+    assert_eq!(vars.get(None, "hp_low"), Some(&Value::Int(50)));
+    assert_eq!(Value::parse("0.5"), Value::Float(0.5));
+    assert_eq!(Value::parse("true"), Value::Bool(true));
+
+    assert_eq!(vars.expand(None, "kill $target"), "kill rat");
+    assert_eq!(vars.expand(Some("mume"), "kill ${target}s for $$5, $1 $nothing"), "kill orcs for $5, $1 $nothing");
+
+    let path = std::env::temp_dir().join(format!("mint-vars-test-{}.toml", std::process::id()));
+    vars.save(&path).unwrap();
+    let mut loaded = Variables::new();
+    loaded.load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.get(Some("mume"), "hp_low"), Some(&Value::Int(50)));
+    assert_eq!(loaded.get(Some("mume"), "target"), None);
+
+    assert!(vars.unset(Scope::World("mume"), "target").is_some());
+    assert_eq!(vars.expand(Some("mume"), "kill $target"), "kill rat");
+}
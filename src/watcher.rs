@@ -0,0 +1,240 @@
+//! Watching files for changes, so that the config and scripts can be reloaded when they're
+//! edited.  Files are checked every so often by looking at their modification times and sizes,
+//! which works everywhere and is plenty quick enough for files people edit by hand.
+
+use crate::meta::{Event, EventSource, Listener, Pollable, ReadinessPager, Stopper};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often files are checked.
+const INTERVAL: Duration = Duration::from_millis(500);
+
+/// What a file looked like the last time we checked: None if it wasn't there.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// State shared between the FileWatcher and its listener thread.
+struct Watched {
+    files: HashMap<PathBuf, Stamp>,
+    // Files that have changed, but which process() hasn't reported yet.
+    changed: Vec<PathBuf>,
+    next_check: Instant,
+    stopping: bool,
+}
+
+impl Watched {
+    /// Check the files if it's time to.  Returns true if any have changed.
+    fn check_due(&mut self, now: Instant) -> bool {
+        if now < self.next_check {
+            return false;
+        }
+        self.next_check = now + INTERVAL;
+
+        let Watched { files, changed, .. } = self;
+        let mut any = false;
+        for (path, last) in files.iter_mut() {
+            let current = stamp(path);
+            if current != *last {
+                *last = current;
+                if !changed.contains(path) {
+                    changed.push(path.clone());
+                }
+                any = true;
+            }
+        }
+        any
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        if self.files.is_empty() { None } else { Some(self.next_check) }
+    }
+}
+
+/// EventSource that produces an `Event::FilesChanged` when any of the files it's watching are
+/// changed, created or deleted.
+pub struct FileWatcher {
+    shared: Arc<(Mutex<Watched>, Condvar)>,
+}
+
+impl FileWatcher {
+    pub fn new() -> FileWatcher {
+        FileWatcher {
+            shared: Arc::new((Mutex::new(Watched {
+                files: HashMap::new(),
+                changed: vec![],
+                next_check: Instant::now(),
+                stopping: false,
+            }), Condvar::new())),
+        }
+    }
+
+    /// Watch `paths` instead of whatever was being watched before.  Files that were already
+    /// being watched don't lose track of changes that haven't been noticed yet.
+    pub fn watch(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        let (lock, cvar) = &*self.shared;
+        let mut watched = lock.lock().expect("File watcher lock poisoned");
+
+        let mut old = std::mem::take(&mut watched.files);
+        for path in paths {
+            let last = old.remove(&path).unwrap_or_else(|| stamp(&path));
+            watched.files.insert(path, last);
+        }
+        let Watched { files, changed, .. } = &mut *watched;
+        changed.retain(|path| files.contains_key(path));
+
+        cvar.notify_one();
+    }
+}
+
+impl Default for FileWatcher {
+    fn default() -> Self {
+        FileWatcher::new()
+    }
+}
+
+impl EventSource for FileWatcher {
+    fn get_listeners(&mut self) -> Vec<Box<dyn Listener>> {
+        vec![Box::new(WatchListener {
+            shared: self.shared.clone(),
+        })]
+    }
+
+    fn process(&mut self) -> Vec<Event> {
+        let (lock, _) = &*self.shared;
+        let mut watched = lock.lock().expect("File watcher lock poisoned");
+
+        if watched.changed.is_empty() {
+            return vec![];
+        }
+        vec![Event::FilesChanged { paths: watched.changed.drain(..).collect() }]
+    }
+}
+
+/// Listener for FileWatcher: checks the files every so often, and pages the main thread when
+/// they change.
+struct WatchListener {
+    shared: Arc<(Mutex<Watched>, Condvar)>,
+}
+
+impl Listener for WatchListener {
+    fn name(&self) -> String {
+        "file watcher".to_string()
+    }
+
+    fn stopper(&mut self) -> Option<Box<dyn Stopper>> {
+        Some(Box::new(WatchStopper {
+            shared: self.shared.clone(),
+        }))
+    }
+
+    fn pollable(&mut self) -> Option<&mut dyn Pollable> {
+        Some(self)
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) {
+        let (lock, cvar) = &*self.shared;
+        let mut watched = match lock.lock() {
+            Ok(w) => w,
+            Err(_) => { return flag.err("File watcher lock poisoned".to_string()); },
+        };
+
+        loop {
+            if watched.stopping {
+                return;
+            }
+
+            let now = Instant::now();
+            if watched.check_due(now) {
+                // Don't hold the lock while paging; process() is going to want it.
+                drop(watched);
+                flag.ok();
+                watched = match lock.lock() {
+                    Ok(w) => w,
+                    Err(_) => { return flag.err("File watcher lock poisoned".to_string()); },
+                };
+                continue;
+            }
+
+            let result = match watched.next_deadline() {
+                Some(deadline) => cvar.wait_timeout(watched, deadline.saturating_duration_since(now))
+                    .map(|(w, _)| w).map_err(|_| ()),
+                None => cvar.wait(watched).map_err(|_| ()),
+            };
+            watched = match result {
+                Ok(w) => w,
+                Err(_) => { return flag.err("File watcher lock poisoned".to_string()); },
+            };
+        }
+    }
+}
+
+impl Pollable for WatchListener {
+    fn register(&mut self, _poll: &mio::Poll, _token: mio::Token) -> Result<(), String> {
+        // Like the timer listener, all we wait on is the clock.
+        Ok(())
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.shared.0.lock().ok().and_then(|watched| watched.next_deadline())
+    }
+
+    fn poll_once(&mut self, flag: &mut dyn ReadinessPager) -> bool {
+        let any = match self.shared.0.lock() {
+            Ok(mut watched) => watched.check_due(Instant::now()),
+            Err(_) => {
+                flag.err("File watcher lock poisoned".to_string());
+                return false;
+            },
+        };
+
+        if any {
+            flag.ok();
+        }
+        true
+    }
+}
+
+/// Stopper for WatchListener.
+struct WatchStopper {
+    shared: Arc<(Mutex<Watched>, Condvar)>,
+}
+
+impl Stopper for WatchStopper {
+    fn stop(&mut self) {
+        let (lock, cvar) = &*self.shared;
+        if let Ok(mut watched) = lock.lock() {
+            watched.stopping = true;
+        }
+        cvar.notify_one();
+    }
+}
+
+#[test]
+fn notices_changes() {
+    use crate::events::ThreadedManager;
+    use crate::meta::EventManager;
+    use std::{cell::RefCell, rc::Rc};
+
+    let path = std::env::temp_dir().join(format!("mint-watch-test-{}", std::process::id()));
+    fs::write(&path, "one").unwrap();
+
+    let watcher = Rc::new(RefCell::new(FileWatcher::new()));
+    let mut manager = ThreadedManager::new();
+    manager.start_source(watcher.clone());
+    watcher.borrow_mut().watch(vec![path.clone()]);
+
+    fs::write(&path, "three").unwrap();
+    match manager.next_event() {
+        Ok(Event::FilesChanged { paths }) => assert_eq!(paths, vec![path.clone()]),
+        other => panic!("unexpected {:?}", other),
+    }
+    fs::remove_file(&path).unwrap();
+}